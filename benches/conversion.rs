@@ -0,0 +1,73 @@
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use json2toon::converter::Converter;
+use serde_json::{Map, Value, json};
+
+/// One object with many scalar fields
+fn wide_flat_object(fields: usize) -> Value {
+    let map: Map<String, Value> = (0..fields)
+        .map(|i| (format!("field_{}", i), json!(i)))
+        .collect();
+    Value::Object(map)
+}
+
+/// A single chain of objects nested `depth` levels deep
+fn deeply_nested_object(depth: usize) -> Value {
+    (0..depth).fold(
+        json!({ "leaf": "value" }),
+        |inner, i| json!({ format!("level{}", i): inner, "index": i }),
+    )
+}
+
+/// A top-level array of small records
+fn large_array(len: usize) -> Value {
+    Value::Array(
+        (0..len)
+            .map(|i| json!({ "id": i, "name": format!("item {}", i), "active": i % 3 == 0 }))
+            .collect(),
+    )
+}
+
+/// A realistic service configuration mixing all value types
+fn mixed_config(services: usize) -> Value {
+    let services: Map<String, Value> = (0..services)
+        .map(|i| {
+            let service = json!({
+                "image": format!("registry.example.com/service-{}:1.{}.0", i, i),
+                "replicas": i % 5 + 1,
+                "cpu_limit": 0.5 + i as f64 / 10.0,
+                "debug": false,
+                "owner": null,
+                "ports": [8080 + i, 9090 + i],
+                "env": { "LOG_LEVEL": "info", "REGION": "us-east-1", "FEATURES": "" },
+                "healthcheck": { "path": "/health", "interval_s": 30, "retries": 3 },
+                "labels": {},
+                "volumes": [],
+            });
+            (format!("service-{}", i), service)
+        })
+        .collect();
+    json!({ "version": "3.8", "services": services })
+}
+
+fn bench_conversion(c: &mut Criterion) {
+    let inputs = [
+        ("wide_flat_object", wide_flat_object(10_000)),
+        ("deeply_nested_object", deeply_nested_object(120)),
+        ("large_array", large_array(10_000)),
+        ("mixed_config", mixed_config(500)),
+    ];
+    let converter = Converter::new();
+
+    let mut group = c.benchmark_group("convert");
+    for (name, value) in &inputs {
+        let json = value.to_string();
+        group.throughput(Throughput::Bytes(json.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &json, |b, json| {
+            b.iter(|| converter.convert(black_box(json)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_conversion);
+criterion_main!(benches);
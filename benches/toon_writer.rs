@@ -0,0 +1,96 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use json2toon::converter::Converter;
+use json2toon::toon::ToonWriter;
+use serde_json::{Value, json};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Global allocator wrapper that counts allocations
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Build a nested document with `breadth` children per level, `depth` levels deep
+fn nested_document(breadth: usize, depth: usize) -> Value {
+    if depth == 0 {
+        return json!({
+            "name": "leaf \"node\"",
+            "count": 42,
+            "ratio": 0.75,
+            "enabled": true,
+            "missing": null,
+        });
+    }
+    let children: serde_json::Map<String, Value> = (0..breadth)
+        .map(|i| (format!("child{}", i), nested_document(breadth, depth - 1)))
+        .collect();
+    json!({ "items": [1, 2, 3], "children": children })
+}
+
+fn bench_write_nested(c: &mut Criterion) {
+    let json = nested_document(6, 5).to_string();
+    let converter = Converter::new();
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let toon = converter.convert(&json).unwrap();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "nested document: {} lines, {} allocations per conversion",
+        toon.lines().count(),
+        allocations
+    );
+
+    c.bench_function("write_nested_document", |b| {
+        b.iter(|| converter.convert(black_box(&json)).unwrap())
+    });
+}
+
+/// Write every value type directly, isolating `ToonWriter` from JSON parsing
+fn write_entries(count: usize) -> String {
+    let mut writer = ToonWriter::new();
+    for i in 0..count {
+        writer.write_string("config.service.name", "api \"gateway\"");
+        writer.write_number("config.service.port", i as f64);
+        writer.write_number("config.service.ratio", 0.25);
+        writer.write_bool("config.service.enabled", i % 2 == 0);
+        writer.write_null("config.service.owner");
+    }
+    writer.finish()
+}
+
+fn bench_write_entries(c: &mut Criterion) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let toon = write_entries(10_000);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "direct writes: {} lines, {} allocations",
+        toon.lines().count(),
+        allocations
+    );
+
+    c.bench_function("write_entries", |b| {
+        b.iter(|| write_entries(black_box(10_000)))
+    });
+}
+
+criterion_group!(benches, bench_write_entries, bench_write_nested);
+criterion_main!(benches);
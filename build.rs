@@ -3,7 +3,7 @@ use std::process::Command;
 fn main() {
     // Get git commit SHA
     let commit = Command::new("git")
-        .args(&["rev-parse", "--short=8", "HEAD"])
+        .args(["rev-parse", "--short=8", "HEAD"])
         .output()
         .ok()
         .and_then(|output| {
@@ -0,0 +1,845 @@
+//! Conversion of many files: every matching file under a directory, a glob's
+//! matches, or the paths listed in a file, each to its own output or all
+//! into one with `--combine`
+
+use crate::cli;
+use anyhow::{Context, Result};
+use json2toon::converter::{ConversionStats, Converter};
+use json2toon::diag;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// One input file and where its output goes
+struct Job {
+    input: PathBuf,
+    output: PathBuf,
+}
+
+/// A batch that cannot start with the given arguments, e.g. `--output` with a glob pattern
+///
+/// The process exits with 2 for it, like for arguments clap rejects.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct UsageError(pub String);
+
+/// What happened to one input of a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FileStatus {
+    Converted,
+    /// Already up to date with `--recursive`
+    Skipped,
+    Failed,
+}
+
+/// The `--report` of a batch run: totals and the outcome of every input, in order
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    converted: usize,
+    skipped: usize,
+    failed: usize,
+    files: Vec<FileReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileReport {
+    input: String,
+    output: String,
+    status: FileStatus,
+    /// The failure with its causes, e.g. `Failed to convert a.json: Failed to parse JSON: ...`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// One element of the `--summary-json` array of a batch run, for a file that was converted
+#[derive(Debug, Serialize)]
+struct FileSummary {
+    input: String,
+    #[serde(flatten)]
+    stats: ConversionStats,
+}
+
+impl Report {
+    fn add(&mut self, job: &Job, status: FileStatus, error: Option<String>) {
+        match status {
+            FileStatus::Converted => self.converted += 1,
+            FileStatus::Skipped => self.skipped += 1,
+            FileStatus::Failed => self.failed += 1,
+        }
+        self.files.push(FileReport {
+            input: job.input.display().to_string(),
+            output: job.output.display().to_string(),
+            status,
+            error,
+        });
+    }
+}
+
+/// Convert every input-format file under the input directory
+///
+/// Each output goes next to its source, or with `--out-dir` to the same
+/// relative path under that directory. Files whose output is already newer
+/// than the source are skipped unless `--force` is given, so repeated runs
+/// only redo what changed. Files are converted in `--sort-inputs` order,
+/// by path by default. Symlinks are skipped unless `--follow-symlinks`
+/// is given.
+pub fn run(args: &cli::Args, converter: &Converter) -> Result<()> {
+    let root = args.get_input_path();
+    if !root.is_dir() {
+        return Err(UsageError(format!("--recursive needs a directory, got: {}", root.display())).into());
+    }
+
+    let extensions = args.input_extensions();
+    // An output tree inside the input tree is not walked for inputs
+    let skip_dir = args.out_dir.as_deref().and_then(|dir| dir.canonicalize().ok());
+    let inputs = InputWalk::new(extensions, skip_dir, args.follow_symlinks).collect(root)?;
+    let inputs = sort_inputs(inputs, args.sort_inputs.unwrap_or(cli::InputOrder::Name))?;
+    if let Some(ref combined) = args.combine {
+        return combine(args, converter, &inputs, &root.display().to_string(), combined);
+    }
+
+    let jobs = inputs.into_iter().map(|input| Job {
+        output: output_path_for(args, root, &input),
+        input,
+    });
+    convert_all(args, converter, jobs, !args.force)
+}
+
+/// Convert every file matched by a glob pattern, each next to its source
+///
+/// Unlike `--recursive`, every match is converted whether or not its output
+/// is up to date, as if each file had been given on its own.
+pub fn run_glob(args: &cli::Args, converter: &Converter, pattern: &str) -> Result<()> {
+    let single_file_options = [
+        ("--output", !args.output.is_empty()),
+        ("--count-only", args.count_only),
+        ("--manifest", args.manifest.is_some()),
+        ("--profile", args.profile.is_some()),
+        ("--preview", args.preview.is_some()),
+        ("--chunk-lines", args.chunk_lines.is_some()),
+    ];
+    if let Some((option, _)) = single_file_options.iter().find(|(_, used)| *used) {
+        return Err(UsageError(format!("{} cannot be used with the glob pattern {}", option, pattern)).into());
+    }
+
+    let matches = glob::glob(pattern).map_err(|e| UsageError(format!("Invalid glob pattern: {}: {}", pattern, e)))?;
+    let mut inputs = Vec::new();
+    for entry in matches {
+        let path = entry.context("Failed to read a glob match")?;
+        if path.is_file() {
+            inputs.push(path);
+        }
+    }
+    if inputs.is_empty() {
+        anyhow::bail!("No files match: {}", pattern);
+    }
+    let inputs = sort_inputs(inputs, args.sort_inputs.unwrap_or(cli::InputOrder::Name))?;
+    if let Some(ref combined) = args.combine {
+        return combine(args, converter, &inputs, pattern, combined);
+    }
+
+    let jobs = inputs.into_iter().map(|input| Job {
+        output: input.with_extension(args.output_extension()),
+        input,
+    });
+    convert_all(args, converter, jobs, false)
+}
+
+/// Convert every path listed in `list`, one per line (`-` reads the list from stdin)
+///
+/// Blank lines are ignored, and files are converted in the listed order
+/// unless `--sort-inputs` is given. Outputs go next to each source, or with
+/// `--out-dir` under that directory: a relative path keeps its directories
+/// there, an absolute one only its file name. Like a glob, every listed
+/// file is converted whether or not its output is up to date.
+pub fn run_list(args: &cli::Args, converter: &Converter, list: &Path) -> Result<()> {
+    let text = if list == Path::new("-") {
+        let mut text = String::new();
+        io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read input list from stdin")?;
+        text
+    } else {
+        fs::read_to_string(list)
+            .with_context(|| format!("Failed to read input list: {}", list.display()))?
+    };
+
+    let mut inputs = listed_paths(&text);
+    if inputs.is_empty() {
+        anyhow::bail!("No input files listed in {}", list.display());
+    }
+    if let Some(order) = args.sort_inputs {
+        inputs = sort_inputs(inputs, order)?;
+    }
+    if let Some(ref combined) = args.combine {
+        return combine(args, converter, &inputs, &list.display().to_string(), combined);
+    }
+    let jobs = inputs.into_iter().map(|input| Job {
+        output: listed_output_path(args, &input),
+        input,
+    });
+    convert_all(args, converter, jobs, false)
+}
+
+/// The non-blank lines of an input list, as paths
+fn listed_paths(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.trim().is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Inputs in the given order, ties broken by path so the result never depends on how they were found
+fn sort_inputs(mut inputs: Vec<PathBuf>, order: cli::InputOrder) -> Result<Vec<PathBuf>> {
+    inputs.sort();
+    match order {
+        cli::InputOrder::Name => Ok(inputs),
+        cli::InputOrder::Mtime => sort_by_metadata(inputs, |metadata| metadata.modified()),
+        cli::InputOrder::Size => sort_by_metadata(inputs, |metadata| Ok(metadata.len())),
+    }
+}
+
+/// Stable sort by a key read from each file's metadata
+fn sort_by_metadata<K: Ord>(
+    inputs: Vec<PathBuf>,
+    key: impl Fn(&fs::Metadata) -> io::Result<K>,
+) -> Result<Vec<PathBuf>> {
+    let mut keyed = inputs
+        .into_iter()
+        .map(|path| {
+            let key = fs::metadata(&path)
+                .and_then(|metadata| key(&metadata))
+                .with_context(|| format!("Failed to read metadata of {}", path.display()))?;
+            Ok((key, path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(keyed.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Output path for a listed input; see [`run_list`]
+fn listed_output_path(args: &cli::Args, input: &Path) -> PathBuf {
+    let output = input.with_extension(args.output_extension());
+    match &args.out_dir {
+        Some(out_dir) if output.is_absolute() => match output.file_name() {
+            Some(name) => out_dir.join(name),
+            None => output,
+        },
+        Some(out_dir) => out_dir.join(output),
+        None => output,
+    }
+}
+
+/// Convert each job in turn, then print a summary and write the `--report`
+///
+/// A job that fails is reported and the rest are still converted; the run
+/// then fails as a whole. With `incremental`, jobs whose output is already
+/// newer than the source are skipped.
+fn convert_all(
+    args: &cli::Args,
+    converter: &Converter,
+    jobs: impl Iterator<Item = Job>,
+    incremental: bool,
+) -> Result<()> {
+    let mut report = Report::default();
+    let mut summaries = Vec::new();
+    for job in jobs {
+        match convert_job(args, converter, &job, incremental, &mut summaries) {
+            Ok(status) => report.add(&job, status, None),
+            Err(e) => {
+                diag::error(format!("{:#}", e));
+                report.add(&job, FileStatus::Failed, Some(format!("{:#}", e)));
+            }
+        }
+    }
+
+    let verb = if args.dry_run {
+        "Would convert"
+    } else {
+        "Converted"
+    };
+    let mut summary = format!("{} {} file(s)", verb, report.converted);
+    if incremental {
+        summary.push_str(&format!(", skipped {} up to date", report.skipped));
+    }
+    if report.failed > 0 {
+        summary.push_str(&format!(", {} failed", report.failed));
+    }
+    crate::report(summary);
+
+    if let Some(ref path) = args.report {
+        write_report(&report, path)?;
+    }
+    if let Some(ref path) = args.summary_json {
+        crate::write_summary(&summaries, path)?;
+    }
+    if report.failed > 0 {
+        anyhow::bail!("Failed to convert {} of {} file(s)", report.failed, report.files.len());
+    }
+    Ok(())
+}
+
+/// Convert one job, or only say what would be done with `--dry-run`, adding
+/// its `--summary-json` entry to `summaries` once converted
+fn convert_job(
+    args: &cli::Args,
+    converter: &Converter,
+    job: &Job,
+    incremental: bool,
+    summaries: &mut Vec<FileSummary>,
+) -> Result<FileStatus> {
+    if incremental && is_up_to_date(job)? {
+        log::debug!("Up to date: {}", job.output.display());
+        return Ok(FileStatus::Skipped);
+    }
+
+    if args.dry_run {
+        println!(
+            "Would convert {} to {}",
+            job.input.display(),
+            job.output.display()
+        );
+    } else if let Some(stats) = convert(args, converter, job)? {
+        summaries.push(FileSummary {
+            input: job.input.display().to_string(),
+            stats,
+        });
+    }
+    Ok(FileStatus::Converted)
+}
+
+/// Write the batch report as pretty-printed JSON
+fn write_report(report: &Report, path: &Path) -> Result<()> {
+    let mut json = serde_json::to_string_pretty(report).expect("report serializes");
+    json.push('\n');
+    crate::write_atomic(path, json.as_bytes())?;
+
+    log::info!("Report written to: {}", path.display());
+    Ok(())
+}
+
+/// Convert every input into the one `--combine` output, each file's keys under its file stem
+///
+/// e.g. `app.json` and `db.json` give `app.port=...` then `db.host=...`, in
+/// input order. All inputs are converted whether or not anything changed,
+/// and two inputs with the same stem are an error since their keys would
+/// mix. `source` names the batch in the `--header` comment.
+fn combine(args: &cli::Args, converter: &Converter, inputs: &[PathBuf], source: &str, output: &Path) -> Result<()> {
+    let mut stems: HashMap<String, &Path> = HashMap::new();
+    let mut keyed = Vec::new();
+    for input in inputs {
+        let stem = input.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        if let Some(first) = stems.insert(stem.clone(), input) {
+            anyhow::bail!(
+                "--combine cannot key both {} and {} by the stem {}",
+                first.display(),
+                input.display(),
+                stem
+            );
+        }
+        keyed.push((input, stem));
+    }
+
+    if args.dry_run {
+        println!("Would combine {} file(s) into {}", inputs.len(), output.display());
+        return Ok(());
+    }
+
+    let mut toon = String::new();
+    let mut summaries = Vec::new();
+    for (input, stem) in keyed {
+        let start = Instant::now();
+        let content = crate::read_file(args, input)?;
+        let converted = converter
+            .convert_prefixed(&content, &stem)
+            .with_context(|| format!("Failed to convert {}", input.display()))?;
+        if args.summary_json.is_some() {
+            let stats = file_stats(args, converter, input, &content, converted.len(), start)?;
+            summaries.push(FileSummary {
+                input: input.display().to_string(),
+                stats,
+            });
+        }
+        toon.push_str(&converted);
+        converter.check_output_len(toon.len())?;
+    }
+    crate::check_not_empty(args, &toon)?;
+    let toon = crate::with_header(args, source, toon);
+
+    if let Some(parent) = output.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output directory: {}", parent.display()))?;
+    }
+    crate::write_output(args, None, output, &toon)?;
+    if let Some(ref path) = args.summary_json {
+        crate::write_summary(&summaries, path)?;
+    }
+    crate::report(format!("Combined {} file(s) into {}", inputs.len(), output.display()));
+    Ok(())
+}
+
+/// Convert one file, returning its stats for `--summary-json`
+fn convert(args: &cli::Args, converter: &Converter, job: &Job) -> Result<Option<ConversionStats>> {
+    let start = Instant::now();
+    let content = crate::read_file(args, &job.input)?;
+    let toon = if args.jsonl {
+        converter.convert_jsonl(&content)
+    } else if args.multi_doc {
+        converter.convert_multi_doc(&content).map(|(toon, count)| {
+            log::info!("Found {} document(s) in {}", count, job.input.display());
+            toon
+        })
+    } else {
+        converter.convert(&content)
+    }
+    .with_context(|| format!("Failed to convert {}", job.input.display()))?;
+    crate::check_not_empty(args, &toon).with_context(|| format!("Failed to convert {}", job.input.display()))?;
+    let toon = crate::with_header(args, &job.input.display().to_string(), toon);
+
+    if let Some(parent) = job.output.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create output directory: {}", parent.display()))?;
+    }
+    let stats = match args.summary_json {
+        Some(_) => Some(file_stats(args, converter, &job.input, &content, toon.len(), start)?),
+        None => None,
+    };
+    crate::write_output(args, Some(&job.input), &job.output, &toon)?;
+    crate::report(format!("Converted {} to {}", job.input.display(), job.output.display()));
+    Ok(stats)
+}
+
+/// The `--summary-json` stats of one converted file, timed from `start`
+fn file_stats(
+    args: &cli::Args,
+    converter: &Converter,
+    input: &Path,
+    content: &str,
+    output_bytes: usize,
+    start: Instant,
+) -> Result<ConversionStats> {
+    let mut stats = crate::summary_stats(args, converter, content)
+        .with_context(|| format!("Failed to summarize {}", input.display()))?;
+    stats.output_bytes = output_bytes;
+    stats.duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+    Ok(stats)
+}
+
+/// Output path for an input file found under `root`
+///
+/// The input with the output format's extension, rebased from `root` onto
+/// `--out-dir` when one is given.
+fn output_path_for(args: &cli::Args, root: &Path, input: &Path) -> PathBuf {
+    let output = input.with_extension(args.output_extension());
+    match (&args.out_dir, output.strip_prefix(root)) {
+        (Some(out_dir), Ok(relative)) => out_dir.join(relative),
+        _ => output,
+    }
+}
+
+/// Whether the output exists and was modified after its source
+///
+/// Equal timestamps count as stale, since coarse filesystem clocks cannot
+/// tell which write came first.
+fn is_up_to_date(job: &Job) -> Result<bool> {
+    let Ok(output) = fs::metadata(&job.output) else {
+        return Ok(false);
+    };
+    let source = fs::metadata(&job.input)
+        .and_then(|meta| meta.modified())
+        .with_context(|| {
+            format!(
+                "Failed to read modification time of: {}",
+                job.input.display()
+            )
+        })?;
+    Ok(output.modified().is_ok_and(|output| output > source))
+}
+
+/// Recursive search for input files
+struct InputWalk<'a> {
+    extensions: &'a [&'a str],
+    /// Canonical path of a directory not to descend into
+    skip_dir: Option<PathBuf>,
+    follow_symlinks: bool,
+    /// Canonical paths of the directories and files seen so far, when following symlinks
+    visited: HashSet<PathBuf>,
+    inputs: Vec<PathBuf>,
+}
+
+impl<'a> InputWalk<'a> {
+    fn new(extensions: &'a [&'a str], skip_dir: Option<PathBuf>, follow_symlinks: bool) -> Self {
+        Self {
+            extensions,
+            skip_dir,
+            follow_symlinks,
+            visited: HashSet::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Walk `root`, then return the input files found in directory order
+    fn collect(mut self, root: &Path) -> Result<Vec<PathBuf>> {
+        if self.follow_symlinks {
+            self.visited.extend(root.canonicalize().ok());
+        }
+        self.walk(root)?;
+        Ok(self.inputs)
+    }
+
+    /// Append the files under `dir` whose extension is one of `extensions`
+    ///
+    /// Symlinks are skipped unless following them, and then each directory
+    /// and file is visited once by canonical path, so link cycles end and
+    /// a file reachable through several links is converted once.
+    fn walk(&mut self, dir: &Path) -> Result<()> {
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+        for entry in entries {
+            let path = entry
+                .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+                .path();
+            let is_symlink = fs::symlink_metadata(&path)
+                .with_context(|| format!("Failed to read metadata of: {}", path.display()))?
+                .file_type()
+                .is_symlink();
+            if is_symlink && !self.follow_symlinks {
+                log::info!("Skipping symlink (use --follow-symlinks to follow): {}", path.display());
+                continue;
+            }
+
+            if path.is_dir() {
+                let canonical = path.canonicalize().ok();
+                if canonical.is_some() && canonical == self.skip_dir {
+                    continue;
+                }
+                if self.follow_symlinks && !self.first_visit(canonical, &path) {
+                    continue;
+                }
+                self.walk(&path)?;
+            } else if path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| self.extensions.contains(&ext))
+            {
+                if self.follow_symlinks && !self.first_visit(path.canonicalize().ok(), &path) {
+                    continue;
+                }
+                self.inputs.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a canonical path, returning false if it was already visited
+    ///
+    /// A path that cannot be canonicalized (e.g. a dangling link) is never
+    /// visited.
+    fn first_visit(&mut self, canonical: Option<PathBuf>, path: &Path) -> bool {
+        let Some(canonical) = canonical else {
+            log::info!("Skipping unresolvable symlink: {}", path.display());
+            return false;
+        };
+        if !self.visited.insert(canonical) {
+            log::info!("Skipping already visited path: {}", path.display());
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("json2toon-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_collect_inputs() {
+        let dir = temp_dir("collect");
+        for file in ["a.json", "nested/b.json", "c.toon", "d.yaml"] {
+            fs::write(dir.join(file), "{}").unwrap();
+        }
+
+        let mut inputs = InputWalk::new(&["json"], None, false).collect(&dir).unwrap();
+        inputs.sort();
+        assert_eq!(inputs, [dir.join("a.json"), dir.join("nested/b.json")]);
+
+        let skip = dir.join("nested").canonicalize().unwrap();
+        let inputs = InputWalk::new(&["json"], Some(skip), false).collect(&dir).unwrap();
+        assert_eq!(inputs, [dir.join("a.json")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_inputs_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = temp_dir("symlinks");
+        fs::write(dir.join("nested/b.json"), "{}").unwrap();
+        // A link back to the root, a second route to nested/ and a link to a file
+        symlink(&dir, dir.join("nested/loop")).unwrap();
+        symlink(dir.join("nested"), dir.join("alias")).unwrap();
+        symlink(dir.join("nested/b.json"), dir.join("link.json")).unwrap();
+        symlink(dir.join("missing"), dir.join("dangling")).unwrap();
+
+        let inputs = InputWalk::new(&["json"], None, false).collect(&dir).unwrap();
+        assert_eq!(inputs, [dir.join("nested/b.json")]);
+
+        let inputs = InputWalk::new(&["json"], None, true).collect(&dir).unwrap();
+        assert_eq!(inputs.len(), 1, "each file once: {:?}", inputs);
+        assert_eq!(inputs[0].canonicalize().unwrap(), dir.join("nested/b.json").canonicalize().unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_glob() {
+        use clap::Parser;
+
+        let dir = temp_dir("glob");
+        for file in ["a.json", "nested/b.json", "nested/c.yaml"] {
+            fs::write(dir.join(file), "{\"x\": 1}").unwrap();
+        }
+        let pattern = format!("{}/**/*.json", dir.display());
+        let args = cli::Args::parse_from(["json2toon", pattern.as_str()]);
+        run_glob(&args, &Converter::new(), &pattern).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("a.toon")).unwrap(), "x=1\n");
+        assert_eq!(fs::read_to_string(dir.join("nested/b.toon")).unwrap(), "x=1\n");
+        assert!(!dir.join("nested/c.toon").exists());
+
+        let none = format!("{}/*.toml", dir.display());
+        let err = run_glob(&args, &Converter::new(), &none).unwrap_err();
+        assert!(err.to_string().starts_with("No files match"));
+
+        let args = cli::Args::parse_from(["json2toon", "-o", "out.toon", pattern.as_str()]);
+        assert!(run_glob(&args, &Converter::new(), &pattern).unwrap_err().is::<UsageError>());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_output_path_for() {
+        use clap::Parser;
+
+        let args = cli::Args::parse_from(["json2toon", "-r", "src"]);
+        let input = Path::new("src/app/config.json");
+        assert_eq!(output_path_for(&args, Path::new("src"), input), PathBuf::from("src/app/config.toon"));
+
+        let args = cli::Args::parse_from(["json2toon", "-r", "--out-dir", "build", "src"]);
+        assert_eq!(output_path_for(&args, Path::new("src"), input), PathBuf::from("build/app/config.toon"));
+    }
+
+    #[test]
+    fn test_listed_paths() {
+        let text = "a.json\r\n\n  \nconfigs/my app.json\n/abs/b.json";
+        assert_eq!(
+            listed_paths(text),
+            [PathBuf::from("a.json"), PathBuf::from("configs/my app.json"), PathBuf::from("/abs/b.json")]
+        );
+    }
+
+    #[test]
+    fn test_run_list() {
+        use clap::Parser;
+
+        let dir = temp_dir("list");
+        for file in ["a.json", "nested/b.json"] {
+            fs::write(dir.join(file), "{\"x\": 1}").unwrap();
+        }
+        let list = dir.join("inputs.txt");
+        fs::write(&list, format!("{}\n\n{}\n", dir.join("a.json").display(), dir.join("nested/b.json").display()))
+            .unwrap();
+
+        let out_dir = dir.join("out");
+        let args = cli::Args::parse_from([
+            "json2toon",
+            "--input-list",
+            list.to_str().unwrap(),
+            "--out-dir",
+            out_dir.to_str().unwrap(),
+        ]);
+        run_list(&args, &Converter::new(), &list).unwrap();
+        assert_eq!(fs::read_to_string(out_dir.join("a.toon")).unwrap(), "x=1\n");
+        assert_eq!(fs::read_to_string(out_dir.join("b.toon")).unwrap(), "x=1\n");
+
+        fs::write(&list, "\n").unwrap();
+        let err = run_list(&args, &Converter::new(), &list).unwrap_err();
+        assert!(err.to_string().starts_with("No input files listed"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_failures_do_not_stop_the_batch() {
+        use clap::Parser;
+
+        let dir = temp_dir("report");
+        fs::write(dir.join("a.json"), "{\"x\": 1}").unwrap();
+        fs::write(dir.join("b.json"), "{\"x\":").unwrap();
+        fs::write(dir.join("nested/c.json"), "[1]").unwrap();
+        let report = dir.join("report.out");
+        let args = cli::Args::parse_from(["json2toon", "-r", "--report", report.to_str().unwrap(), dir.to_str().unwrap()]);
+
+        let err = run(&args, &Converter::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to convert 1 of 3 file(s)");
+        assert!(dir.join("a.toon").exists() && dir.join("nested/c.toon").exists());
+
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+        assert_eq!((report["converted"].as_u64(), report["failed"].as_u64()), (Some(2), Some(1)));
+        let files = report["files"].as_array().unwrap();
+        let statuses: Vec<&str> = files.iter().map(|file| file["status"].as_str().unwrap()).collect();
+        assert_eq!(statuses, ["converted", "failed", "converted"]);
+        assert!(report["files"][1]["error"].as_str().unwrap().contains("Failed to parse JSON"));
+        assert!(report["files"][0].get("error").is_none());
+
+        // Up-to-date files are reported as skipped on the next run
+        fs::write(dir.join("b.json"), "{}").unwrap();
+        run(&args, &Converter::new()).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(dir.join("report.out")).unwrap()).unwrap();
+        assert_eq!((report["converted"].as_u64(), report["skipped"].as_u64()), (Some(1), Some(2)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_summary_json() {
+        use clap::Parser;
+
+        let dir = temp_dir("summary");
+        fs::write(dir.join("a.json"), r#"{"x": 1, "y": "z"}"#).unwrap();
+        fs::write(dir.join("nested/b.json"), "[true]").unwrap();
+        let summary = dir.join("summary.out");
+        let args = cli::Args::parse_from(["json2toon", "-r", "--summary-json", summary.to_str().unwrap(), dir.to_str().unwrap()]);
+        run(&args, &Converter::new()).unwrap();
+
+        let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+        let files = summary.as_array().unwrap();
+        let inputs: Vec<&str> = files.iter().map(|file| file["input"].as_str().unwrap()).collect();
+        assert_eq!(inputs, [dir.join("a.json").to_str().unwrap(), dir.join("nested/b.json").to_str().unwrap()]);
+        assert_eq!((files[0]["key_count"].as_u64(), files[0]["types"]["string"].as_u64()), (Some(2), Some(1)));
+        assert_eq!(files[1]["output_bytes"].as_u64(), Some("0=true\n".len() as u64));
+
+        let combined = dir.join("all.toon");
+        let summary = dir.join("combined.out");
+        let args = cli::Args::parse_from([
+            "json2toon",
+            "-r",
+            "--combine",
+            combined.to_str().unwrap(),
+            "--summary-json",
+            summary.to_str().unwrap(),
+            dir.to_str().unwrap(),
+        ]);
+        run(&args, &Converter::new()).unwrap();
+        let summary: serde_json::Value = serde_json::from_str(&fs::read_to_string(&summary).unwrap()).unwrap();
+        assert_eq!(summary.as_array().unwrap().len(), 2);
+        assert_eq!(summary[1]["output_bytes"].as_u64(), Some("b.0=true\n".len() as u64));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_sort_inputs() {
+        use cli::InputOrder;
+
+        let dir = temp_dir("sort");
+        let (a, b, c) = (dir.join("a.json"), dir.join("b.json"), dir.join("nested/c.json"));
+        let epoch = SystemTime::UNIX_EPOCH;
+        for (path, content, age) in [(&a, "[1, 2]", 30), (&b, "[]", 10), (&c, "[]", 20)] {
+            fs::write(path, content).unwrap();
+            let file = fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(epoch + Duration::from_secs(1000 - age)).unwrap();
+        }
+
+        let found = vec![c.clone(), a.clone(), b.clone()];
+        assert_eq!(sort_inputs(found.clone(), InputOrder::Name).unwrap(), [a.clone(), b.clone(), c.clone()]);
+        assert_eq!(sort_inputs(found.clone(), InputOrder::Mtime).unwrap(), [a.clone(), c.clone(), b.clone()]);
+        // b and c are the same size, so they stay in path order
+        assert_eq!(sort_inputs(found, InputOrder::Size).unwrap(), [b, c, a]);
+
+        assert!(sort_inputs(vec![dir.join("missing.json")], InputOrder::Size).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_combine() {
+        use clap::Parser;
+
+        let dir = temp_dir("combine");
+        fs::write(dir.join("app.json"), r#"{"config": {"port": 80}}"#).unwrap();
+        fs::write(dir.join("nested/db.json"), r#"{"host": "x"}"#).unwrap();
+        let combined = dir.join("out/all.toon");
+        let args = cli::Args::parse_from([
+            "json2toon",
+            "-r",
+            "--combine",
+            combined.to_str().unwrap(),
+            dir.to_str().unwrap(),
+        ]);
+        run(&args, &Converter::new()).unwrap();
+        assert_eq!(fs::read_to_string(&combined).unwrap(), "app.config.port=80\ndb.host=\"x\"\n");
+        assert!(!dir.join("app.toon").exists());
+
+        fs::write(dir.join("nested/app.json"), "{}").unwrap();
+        let err = run(&args, &Converter::new()).unwrap_err();
+        assert!(err.to_string().contains("by the stem app"), "{}", err);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_listed_output_path() {
+        use clap::Parser;
+
+        let args = cli::Args::parse_from(["json2toon", "--input-list", "-"]);
+        assert_eq!(listed_output_path(&args, Path::new("app/a.json")), PathBuf::from("app/a.toon"));
+
+        let args = cli::Args::parse_from(["json2toon", "--input-list", "-", "--out-dir", "build"]);
+        assert_eq!(listed_output_path(&args, Path::new("app/a.json")), PathBuf::from("build/app/a.toon"));
+        assert_eq!(listed_output_path(&args, Path::new("/srv/a.json")), PathBuf::from("build/a.toon"));
+    }
+
+    #[test]
+    fn test_is_up_to_date() {
+        let dir = temp_dir("mtime");
+        let job = Job {
+            input: dir.join("a.json"),
+            output: dir.join("a.toon"),
+        };
+        fs::write(&job.input, "{}").unwrap();
+        assert!(!is_up_to_date(&job).unwrap(), "missing output is stale");
+
+        let set_mtime = |path: &Path, time: SystemTime| {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        let now = SystemTime::now();
+        fs::write(&job.output, "").unwrap();
+
+        set_mtime(&job.input, now - Duration::from_secs(10));
+        set_mtime(&job.output, now);
+        assert!(is_up_to_date(&job).unwrap());
+
+        set_mtime(&job.output, now - Duration::from_secs(10));
+        assert!(!is_up_to_date(&job).unwrap(), "equal times are stale");
+
+        set_mtime(&job.input, now);
+        assert!(!is_up_to_date(&job).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
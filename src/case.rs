@@ -0,0 +1,117 @@
+//! Key case transformations for `--key-case`
+
+use crate::prelude::*;
+use alloc::borrow::Cow;
+
+/// Case applied to object keys during flattening
+///
+/// Only object keys are transformed; array index segments are never touched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCase {
+    /// `userName` -> `username`
+    Lower,
+    /// `userName` -> `USERNAME`
+    Upper,
+    /// `userName`, `User-Name`, `HTTPServer` -> `user_name`, `user_name`, `http_server`
+    Snake,
+    /// `user_name`, `User-Name` -> `userName`
+    Camel,
+}
+
+impl KeyCase {
+    /// Transform a single object key
+    pub fn apply(self, key: &str) -> Cow<'_, str> {
+        match self {
+            KeyCase::Lower => lowercase(key),
+            KeyCase::Upper if key.chars().any(char::is_lowercase) => Cow::Owned(key.to_uppercase()),
+            KeyCase::Upper => Cow::Borrowed(key),
+            KeyCase::Snake => Cow::Owned(words(key).join("_")),
+            KeyCase::Camel => {
+                let mut camel = String::with_capacity(key.len());
+                for (i, word) in words(key).iter().enumerate() {
+                    if i == 0 {
+                        camel.push_str(word);
+                    } else {
+                        let mut chars = word.chars();
+                        if let Some(first) = chars.next() {
+                            camel.extend(first.to_uppercase());
+                            camel.push_str(chars.as_str());
+                        }
+                    }
+                }
+                Cow::Owned(camel)
+            }
+        }
+    }
+}
+
+fn lowercase(key: &str) -> Cow<'_, str> {
+    if key.chars().any(char::is_uppercase) {
+        Cow::Owned(key.to_lowercase())
+    } else {
+        Cow::Borrowed(key)
+    }
+}
+
+/// Split a key into lowercase words at `_`, `-`, spaces and case changes
+///
+/// A run of capitals is one word, except that its last capital starts the
+/// next word when followed by a lowercase letter (`HTTPServer` -> `http`, `server`).
+fn words(key: &str) -> Vec<String> {
+    let chars: Vec<char> = key.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for (i, &ch) in chars.iter().enumerate() {
+        if matches!(ch, '_' | '-') || ch.is_whitespace() {
+            if !word.is_empty() {
+                words.push(core::mem::take(&mut word));
+            }
+            continue;
+        }
+
+        if ch.is_uppercase() && !word.is_empty() {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+            if !prev.is_uppercase() || next_is_lower {
+                words.push(core::mem::take(&mut word));
+            }
+        }
+        word.extend(ch.to_lowercase());
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lower_and_upper() {
+        assert_eq!(KeyCase::Lower.apply("UserName"), "username");
+        assert_eq!(KeyCase::Upper.apply("user_name"), "USER_NAME");
+        assert!(matches!(KeyCase::Lower.apply("plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_snake() {
+        assert_eq!(KeyCase::Snake.apply("userName"), "user_name");
+        assert_eq!(KeyCase::Snake.apply("User-Name"), "user_name");
+        assert_eq!(KeyCase::Snake.apply("HTTPServer"), "http_server");
+        assert_eq!(KeyCase::Snake.apply("already_snake"), "already_snake");
+        assert_eq!(KeyCase::Snake.apply("first name"), "first_name");
+        assert_eq!(KeyCase::Snake.apply("id2Value"), "id2_value");
+    }
+
+    #[test]
+    fn test_camel() {
+        assert_eq!(KeyCase::Camel.apply("user_name"), "userName");
+        assert_eq!(KeyCase::Camel.apply("User-Name"), "userName");
+        assert_eq!(KeyCase::Camel.apply("HTTP_SERVER"), "httpServer");
+        assert_eq!(KeyCase::Camel.apply("userName"), "userName");
+    }
+}
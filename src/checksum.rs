@@ -0,0 +1,144 @@
+use crate::cli::ChecksumAlgorithm;
+use anyhow::{Context, Result};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Compute the lowercase hex digest of the given bytes
+pub fn digest(algorithm: ChecksumAlgorithm, data: &[u8]) -> String {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Incremental digest, for output that is written in pieces
+enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Md5 => Hasher::Md5(Md5::new()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The lowercase hex digest of everything hashed
+    fn finish(self) -> String {
+        let bytes = match self {
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+        };
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Writer passing bytes through to another while digesting exactly the bytes written,
+/// for `--checksum` of output sent to stdout
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Option<Hasher>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    /// Digest with `algorithm`, or only pass bytes through if it is `None`
+    pub fn new(inner: W, algorithm: Option<ChecksumAlgorithm>) -> Self {
+        Self {
+            inner,
+            hasher: algorithm.map(Hasher::new),
+        }
+    }
+
+    /// The hex digest of the bytes written, if digesting
+    pub fn finish(self) -> Option<String> {
+        self.hasher.map(Hasher::finish)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Path of the sidecar file for an output, e.g. `data.toon.sha256`
+pub fn sidecar_path(output: &Path, algorithm: ChecksumAlgorithm) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".");
+    name.push(algorithm.extension());
+    PathBuf::from(name)
+}
+
+/// Write a sidecar file in the `<digest>  <filename>` format used by `sha256sum`/`md5sum`
+pub fn write_sidecar(output: &Path, algorithm: ChecksumAlgorithm, hex: &str) -> Result<PathBuf> {
+    let path = sidecar_path(output, algorithm);
+    let file_name = output
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    fs::write(&path, format!("{}  {}\n", hex, file_name))
+        .with_context(|| format!("Failed to write checksum file: {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_digest() {
+        assert_eq!(
+            digest(ChecksumAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_md5_digest() {
+        assert_eq!(
+            digest(ChecksumAlgorithm::Md5, b"abc"),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+    }
+
+    #[test]
+    fn test_hashing_writer() {
+        let mut writer = HashingWriter::new(Vec::new(), Some(ChecksumAlgorithm::Sha256));
+        writer.write_all(b"a").unwrap();
+        writer.write_all(b"bc").unwrap();
+        assert_eq!(writer.inner, b"abc");
+        assert_eq!(writer.finish(), Some(digest(ChecksumAlgorithm::Sha256, b"abc")));
+
+        let mut writer = HashingWriter::new(Vec::new(), None);
+        writer.write_all(b"abc").unwrap();
+        assert_eq!(writer.finish(), None);
+    }
+
+    #[test]
+    fn test_sidecar_path() {
+        assert_eq!(
+            sidecar_path(Path::new("out/data.toon"), ChecksumAlgorithm::Sha256),
+            PathBuf::from("out/data.toon.sha256")
+        );
+    }
+}
@@ -0,0 +1,147 @@
+//! Removal of in-progress temporary files on Ctrl-C
+
+use json2toon::diag;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Temporary files that have been created but not yet renamed into place
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Whether the temporary file of a failed write is left in place (`--keep-temp`)
+static KEEP_ON_FAILURE: AtomicBool = AtomicBool::new(false);
+
+/// Exit status for termination by SIGINT (128 + 2)
+const INTERRUPTED: i32 = 130;
+
+/// Install a Ctrl-C handler that removes pending temporary files, then exits
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| {
+        remove_pending();
+        std::process::exit(INTERRUPTED);
+    })
+}
+
+/// Leave the temporary files of failed writes in place instead of removing them
+///
+/// Interrupted writes are still cleaned up.
+pub fn keep_on_failure(keep: bool) {
+    KEEP_ON_FAILURE.store(keep, Ordering::Relaxed);
+}
+
+/// Remove every pending temporary file
+fn remove_pending() {
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    for path in pending.drain(..) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// A temporary file that is removed unless it is kept with [`TempFile::persist`]
+///
+/// Registered while alive so an interrupt can remove it too. With
+/// [`keep_on_failure`] a file dropped without being persisted is left in
+/// place and its path printed.
+pub struct TempFile {
+    path: PathBuf,
+    persisted: bool,
+    keep_on_failure: bool,
+}
+
+impl TempFile {
+    /// Register `path` before it is created
+    pub fn register(path: PathBuf) -> Self {
+        Self::with_keep(path, KEEP_ON_FAILURE.load(Ordering::Relaxed))
+    }
+
+    fn with_keep(path: PathBuf, keep_on_failure: bool) -> Self {
+        PENDING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(path.clone());
+        Self {
+            path,
+            persisted: false,
+            keep_on_failure,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stop tracking the file once it has been renamed into place
+    pub fn persist(mut self) {
+        self.persisted = true;
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+        pending.retain(|path| path != &self.path);
+        if self.persisted {
+            return;
+        }
+        if !self.keep_on_failure {
+            let _ = fs::remove_file(&self.path);
+        } else if self.path.exists() {
+            diag::info(format!("Kept temporary file: {}", self.path.display()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("json2toon-cleanup-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_interrupt_removes_pending_file() {
+        let path = scratch("interrupt");
+        let temp = TempFile::register(path.clone());
+        fs::write(temp.path(), "partial").unwrap();
+
+        remove_pending();
+        assert!(!path.exists());
+        drop(temp);
+    }
+
+    #[test]
+    fn test_drop_removes_unpersisted_file() {
+        let path = scratch("drop");
+        let temp = TempFile::register(path.clone());
+        fs::write(temp.path(), "partial").unwrap();
+
+        drop(temp);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_keep_on_failure_leaves_file() {
+        let path = scratch("keep");
+        let temp = TempFile::with_keep(path.clone(), true);
+        fs::write(temp.path(), "partial").unwrap();
+
+        drop(temp);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "partial");
+        assert!(!PENDING.lock().unwrap().contains(&path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_persist_keeps_file_and_unregisters() {
+        let path = scratch("persist");
+        let temp = TempFile::register(path.clone());
+        fs::write(temp.path(), "done").unwrap();
+
+        temp.persist();
+        assert!(path.exists());
+        assert!(!PENDING.lock().unwrap().contains(&path));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,10 +1,228 @@
-use clap::Parser;
-use std::path::PathBuf;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use crate::hook::Hook;
+use clap_complete::Shell;
+use json2toon::case::KeyCase;
+use json2toon::coerce::CoercionRule;
+use json2toon::converter::{ConvertOptions, DEFAULT_MAX_RECURSION, InputFormat, MAX_RECURSION_LIMIT};
+use json2toon::diag::ColorChoice;
+use json2toon::interpolate::{Interpolation, MissingVar};
+use json2toon::toon::{self, ASSIGN, ByteCount, FloatStyle, Indent, OutputFormat, Projection, QUOTE, Template, ToonOptions};
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+/// Digest algorithm for `--checksum`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    /// File extension used for checksum sidecar files
+    pub fn extension(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Md5 => "md5",
+            ChecksumAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+/// Text encoding of the written output, selected with `--output-encoding`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// UTF-8 without a byte order mark
+    Utf8,
+    /// UTF-16 little-endian with a byte order mark
+    Utf16le,
+    /// UTF-16 big-endian with a byte order mark
+    Utf16be,
+}
+
+impl OutputEncoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputEncoding::Utf8 => "UTF-8",
+            OutputEncoding::Utf16le => "UTF-16LE",
+            OutputEncoding::Utf16be => "UTF-16BE",
+        }
+    }
+}
+
+/// Order batch inputs are converted in, selected with `--sort-inputs`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputOrder {
+    /// Lexically by path
+    Name,
+    /// By modification time, oldest first
+    Mtime,
+    /// By file size, smallest first
+    Size,
+}
+
+/// Kind of empty value that `--skip-empty` can omit
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyKind {
+    String,
+    Array,
+    Object,
+}
+
+/// How errors are reported on stderr
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// `[ERROR] message`
+    Text,
+    /// `{"error":"parse","message":"...","line":N,"column":M}`
+    Json,
+}
+
+/// Input syntax selected with `--input-format`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputSyntax {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl From<InputSyntax> for InputFormat {
+    fn from(syntax: InputSyntax) -> Self {
+        match syntax {
+            InputSyntax::Json => InputFormat::Json,
+            InputSyntax::Toml => InputFormat::Toml,
+            InputSyntax::Yaml => InputFormat::Yaml,
+        }
+    }
+}
+
+/// Length reported by `--annotate-bytes`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteCountArg {
+    /// The value before quoting, escaping and truncation
+    Raw,
+    /// The value as written, quotes and escapes included
+    Escaped,
+}
+
+impl From<ByteCountArg> for ByteCount {
+    fn from(count: ByteCountArg) -> Self {
+        match count {
+            ByteCountArg::Raw => ByteCount::Raw,
+            ByteCountArg::Escaped => ByteCount::Escaped,
+        }
+    }
+}
+
+/// How `--float-style` writes floats
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatStyleArg {
+    /// Drop trailing zeros but keep a decimal (5.0, 98.50 as 98.5)
+    Trim,
+    /// Keep the number as written in the JSON source (5.0, 98.50)
+    Preserve,
+    /// Write whole floats as integers (5.0 as 5, 98.50 as 98.5)
+    IntegerCollapse,
+}
+
+impl From<FloatStyleArg> for FloatStyle {
+    fn from(style: FloatStyleArg) -> Self {
+        match style {
+            FloatStyleArg::Trim => FloatStyle::Trim,
+            FloatStyleArg::Preserve => FloatStyle::Preserve,
+            FloatStyleArg::IntegerCollapse => FloatStyle::IntegerCollapse,
+        }
+    }
+}
+
+/// Case selected with `--key-case`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyCaseArg {
+    Lower,
+    Upper,
+    Snake,
+    Camel,
+}
+
+impl From<KeyCaseArg> for KeyCase {
+    fn from(case: KeyCaseArg) -> Self {
+        match case {
+            KeyCaseArg::Lower => KeyCase::Lower,
+            KeyCaseArg::Upper => KeyCase::Upper,
+            KeyCaseArg::Snake => KeyCase::Snake,
+            KeyCaseArg::Camel => KeyCase::Camel,
+        }
+    }
+}
+
+/// Handling of unset variables selected with `--env-missing`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnvMissing {
+    /// Fail the conversion
+    Error,
+    /// Substitute an empty string
+    Empty,
+    /// Leave the `${VAR}` placeholder as written
+    Keep,
+}
+
+impl From<EnvMissing> for MissingVar {
+    fn from(missing: EnvMissing) -> Self {
+        match missing {
+            EnvMissing::Error => MissingVar::Error,
+            EnvMissing::Empty => MissingVar::Empty,
+            EnvMissing::Keep => MissingVar::Keep,
+        }
+    }
+}
+
+/// Output format selected with `--format`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// `key=value` lines
+    Toon,
+    /// Two-column `key,value` CSV
+    Csv,
+    /// Two-column tab-separated values
+    Tsv,
+    /// RFC 6901 JSON Pointer and JSON-encoded value per leaf, e.g. `/config/db/host "x"`
+    Jsonpath,
+}
+
+impl Format {
+    /// File extension used for the default output path
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Toon => "toon",
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+            Format::Jsonpath => "txt",
+        }
+    }
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Toon => OutputFormat::Toon,
+            Format::Csv => OutputFormat::Csv,
+            Format::Tsv => OutputFormat::Tsv,
+            Format::Jsonpath => OutputFormat::JsonPointer,
+        }
+    }
+}
+
+/// Character used for pretty-mode indentation
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndentChar {
+    Space,
+    Tab,
+}
 
 #[derive(Parser, Debug)]
 #[command(name = "json2toon")]
 #[command(author = "Michael A Wright")]
 #[command(version = env!("CARGO_PKG_VERSION"))]
+#[command(disable_version_flag = true)]
 #[command(about = "Convert JSON to TOON (Token-Oriented-Object-Notation) format")]
 #[command(
     long_about = "json2toon - Convert JSON to TOON format\n\n\
@@ -15,11 +233,25 @@ use std::path::PathBuf;
     json2toon input.json              # Convert input.json to input.toon\n  \
     json2toon input.json -o out.toon  # Convert with custom output\n  \
     json2toon -n input.json           # Dry run (preview)\n  \
-    json2toon -v input.json           # Verbose output\n\n\
+    json2toon -n --preview 20 in.json # Dry run showing the first 20 output lines\n  \
+    json2toon -v input.json           # Verbose output\n  \
+    json2toon -r configs/             # Convert a tree, skipping up-to-date outputs\n  \
+    json2toon --count-only input.json # Print number of key-value pairs\n  \
+    json2toon --input-format toml Cargo.toml  # Convert TOML (or yaml) input\n  \
+    json2toon --format csv in.json    # Key/value pairs as CSV (in.csv)\n  \
+    json2toon --template '{key}\\t{value}' in.json  # Custom per-line format\n  \
+    cat events.jsonl | json2toon --jsonl -  # Stream JSON Lines from stdin to stdout\n  \
+    json2toon --multi-doc dump.json   # Convert concatenated documents (0.key, 1.key, ...)\n  \
+    json2toon diff old.json new.json  # Compare two files key by key\n  \
+    json2toon repl                    # Convert snippets interactively\n\n\
+    Environment:\n  \
+    NO_COLOR - Disable colored output when --color is auto\n\n\
     Exit Codes:\n  \
     0 - Success\n  \
-    1 - General error (I/O, conversion failure)\n  \
-    2 - Usage error (invalid arguments)"
+    1 - General error (I/O, conversion failure), some files of a batch failed, or differences found by diff\n  \
+    2 - Usage error (invalid arguments, or options a batch run cannot use)\n  \
+    3 - Output would exceed --max-output-size (nothing is written)\n  \
+    130 - Interrupted by Ctrl-C (temporary output files are removed)"
 )]
 #[command(help_template = "\
 {before-help}{name} {version}
@@ -28,63 +260,1486 @@ use std::path::PathBuf;
 
 {all-args}{after-help}
 ")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(group = clap::ArgGroup::new("batch").args(["recursive", "input_list"]))]
 pub struct Args {
-    /// Input JSON file to convert
-    #[arg(value_name = "FILE")]
-    pub input: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Input JSON file to convert ("-" reads from stdin)
+    #[arg(
+        value_name = "FILE",
+        required_unless_present_any = ["version", "generate_completions", "generate_man", "input_list"]
+    )]
+    pub input: Option<PathBuf>,
 
-    /// Output TOON file (defaults to input with .toon extension)
+    /// Output file (defaults to input with the format's extension, e.g. .toon, or stdout for stdin; "-" for stdout);
+    /// repeat to write the same output to several files, each on its own
     #[arg(short, long, value_name = "FILE")]
-    pub output: Option<PathBuf>,
+    pub output: Vec<PathBuf>,
+
+    /// Begin the output with a `# generated from FILE by json2toon VERSION (commit SHA) at TIME` comment
+    #[arg(long)]
+    pub header: bool,
+
+    /// With --recursive, --input-list or a glob pattern, write every input into FILE instead,
+    /// each file's keys under its file stem (e.g. `app.config.port=...`)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "output", "out_dir", "force", "jsonl", "multi_doc", "dedup_values", "chunk_lines",
+            "preserve_mode",
+        ]
+    )]
+    pub combine: Option<PathBuf>,
+
+    /// Split the output file into parts of at most N lines each: OUT.001, OUT.002, ...
+    #[arg(long, value_name = "N", conflicts_with_all = ["recursive", "input_list"])]
+    pub chunk_lines: Option<NonZeroUsize>,
+
+    /// Extension for the default output path when --output is not given (e.g. tn)
+    #[arg(long, value_name = "EXT", value_parser = parse_extension)]
+    pub out_ext: Option<String>,
 
     /// Show version information
     #[arg(short = 'V', long)]
     pub version: bool,
 
+    /// Print a completion script for the given shell to stdout
+    #[arg(long, value_enum, value_name = "SHELL", hide = true, exclusive = true)]
+    pub generate_completions: Option<Shell>,
+
+    /// Print a roff man page to stdout
+    #[arg(long, hide = true, exclusive = true)]
+    pub generate_man: bool,
+
+    /// Convert every input-format file under the input directory, each next to its source
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["output", "count_only", "manifest", "profile", "preview"]
+    )]
+    pub recursive: bool,
+
+    /// Convert every path listed in FILE, one per line, each next to its source ("-" reads the list from stdin)
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["input", "recursive", "output", "count_only", "manifest", "profile", "preview"]
+    )]
+    pub input_list: Option<PathBuf>,
+
+    /// Take the input path literally even if it contains glob characters (`*`, `?`, `[`)
+    /// and names no existing file
+    #[arg(long)]
+    pub no_glob: bool,
+
+    /// With --recursive, descend into symlinked directories and convert symlinked files
+    /// (each file once) instead of skipping them
+    #[arg(long, requires = "recursive")]
+    pub follow_symlinks: bool,
+
+    /// Order batch inputs are converted in (ties by path); --recursive and glob matches are
+    /// sorted by name unless given, --input-list keeps the listed order
+    #[arg(long, value_enum, value_name = "ORDER")]
+    pub sort_inputs: Option<InputOrder>,
+
+    /// With --recursive, also convert files whose output is already newer than the source
+    #[arg(long, requires = "recursive")]
+    pub force: bool,
+
+    /// With --recursive or --input-list, write outputs under DIR mirroring the input paths
+    /// instead of next to each source
+    #[arg(long, value_name = "DIR", requires = "batch")]
+    pub out_dir: Option<PathBuf>,
+
     /// Dry run - show what would be done without modifying files
     #[arg(short = 'n', long)]
     pub dry_run: bool,
 
-    /// Verbose output - show detailed progress
-    #[arg(short, long)]
-    pub verbose: bool,
+    /// Syntax of the input file; TOML and YAML are converted like the equivalent JSON
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = InputSyntax::Json)]
+    pub input_format: InputSyntax,
+
+    /// Replace invalid UTF-8 in the input with U+FFFD instead of failing
+    #[arg(long, global = true)]
+    pub lossy_utf8: bool,
+
+    /// Produce empty output for empty or whitespace-only input instead of failing
+    #[arg(long)]
+    pub allow_empty: bool,
+
+    /// If the input is not valid JSON, retry it as relaxed JSON: `//` and `/* */` comments,
+    /// trailing commas and 'single-quoted' strings (verbose output notes when this was needed)
+    #[arg(long, conflicts_with_all = ["multi_doc", "source_lines"])]
+    pub try_relaxed: bool,
+
+    /// With --dry-run, convert in memory and print the first N lines of output
+    #[arg(long, value_name = "N", requires = "dry_run")]
+    pub preview: Option<usize>,
+
+    /// Treat input as JSON Lines: convert each line as a record keyed by its index
+    #[arg(long, conflicts_with = "dry_run", conflicts_with = "input_format")]
+    pub jsonl: bool,
+
+    /// Treat input as JSON documents written back to back (`{...}{...}`, with or without
+    /// whitespace between): convert each as a document keyed by its index
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "jsonl", "input_format", "dry_run", "count_only", "manifest", "summary_json",
+            "profile", "source_lines", "dedup_values",
+        ]
+    )]
+    pub multi_doc: bool,
+
+    /// Print the number of key-value pairs that would be produced, without writing output
+    #[arg(long, conflicts_with = "dry_run")]
+    pub count_only: bool,
+
+    /// Output format: TOON lines, key/value pairs as CSV or TSV, or JSON Pointer leaf paths
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        default_value_t = Format::Toon,
+        conflicts_with_all = [
+            "pretty",
+            "template",
+            "keys_only",
+            "values_only",
+            "show_depth",
+            "tabular",
+        ]
+    )]
+    pub format: Format,
+
+    /// Character joining key path segments (also --object-sep)
+    #[arg(
+        long,
+        visible_alias = "object-sep",
+        value_name = "CHAR",
+        default_value = ".",
+        value_parser = parse_separator
+    )]
+    pub separator: char,
+
+    /// Character joining array indices to their array's key instead of the separator,
+    /// e.g. `#` for `user.items#0`, so arrays and objects with digit keys read differently
+    #[arg(long, value_name = "CHAR", value_parser = parse_separator, conflicts_with = "pretty")]
+    pub array_sep: Option<char>,
+
+    /// Write the separator as `\<CHAR>` inside string values, so e.g. "a/b" cannot look
+    /// like a key path with --separator /
+    #[arg(long)]
+    pub escape_separator: bool,
+
+    /// Quote object keys made only of digits, e.g. `ids."0"="a"`, so they cannot be
+    /// mistaken for array indices like `list.0`
+    #[arg(long)]
+    pub numeric_keys_quoted: bool,
+
+    /// Text between each key and its value, e.g. ":" or " = " (must not contain the separator)
+    #[arg(long, value_name = "STR", default_value = ASSIGN)]
+    pub assign: String,
+
+    /// Bare value written for an empty array; strings equal to it stay quoted
+    #[arg(long, value_name = "TOKEN", default_value = toon::EMPTY_ARRAY)]
+    pub empty_array_token: String,
+
+    /// Bare value written for an empty object; strings equal to it stay quoted
+    #[arg(long, value_name = "TOKEN", default_value = toon::EMPTY_OBJECT)]
+    pub empty_object_token: String,
+
+    /// Annotate each key with its source JSON type (e.g. age:integer=30)
+    #[arg(long)]
+    pub annotate_types: bool,
+
+    /// Follow each entry with the UTF-8 byte length of its value, e.g. `summary="..." (1240B)`,
+    /// counted before escaping (raw, the default) or as written (escaped)
+    #[arg(
+        long,
+        value_enum,
+        value_name = "COUNT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "raw"
+    )]
+    pub annotate_bytes: Option<ByteCountArg>,
+
+    /// Omit quotes around strings that cannot be mistaken for other values
+    #[arg(long)]
+    pub unquoted_safe_strings: bool,
+
+    /// Collapse runs of spaces, tabs and newlines inside string values to one space and trim
+    /// their ends (entries only; table cells and folded arrays are left as they are)
+    #[arg(long)]
+    pub normalize_whitespace: bool,
+
+    /// Quote numbers like strings (e.g. age="30")
+    #[arg(long)]
+    pub quote_numbers: bool,
+
+    /// How floats are written (preserve keeps the JSON source text, e.g. 98.50, where known)
+    #[arg(long, value_enum, value_name = "STYLE", default_value = "integer-collapse")]
+    pub float_style: FloatStyleArg,
+
+    /// Write whole floats below N in magnitude as integers (e.g. 42.0 as 42); larger ones keep float form
+    #[arg(long, value_name = "N", default_value_t = toon::WHOLE_NUMBER_LIMIT)]
+    pub stable_float_threshold: u64,
+
+    /// Quote booleans like strings (e.g. active="true")
+    #[arg(long)]
+    pub quote_bools: bool,
+
+    /// Render nested sections as indented blocks instead of full dotted keys
+    #[arg(long)]
+    pub pretty: bool,
+
+    /// Indentation character for --pretty
+    #[arg(long, value_enum, value_name = "CHAR", default_value_t = IndentChar::Space, requires = "pretty")]
+    pub indent_char: IndentChar,
+
+    /// Indentation width for --pretty (ignored for tabs)
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "pretty")]
+    pub indent_width: usize,
+
+    /// Render each entry with a format string using {key}, {value} and {type}
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        value_parser = Template::parse,
+        conflicts_with_all = ["pretty", "annotate_types"]
+    )]
+    pub template: Option<Template>,
+
+    /// Write arrays of objects with identical keys as a table: a header plus one line per row
+    #[arg(
+        long,
+        conflicts_with_all = ["pretty", "template", "keys_only", "values_only", "show_depth"]
+    )]
+    pub tabular: bool,
+
+    /// Write arrays of only scalars on one line, e.g. `ids=[1,2,3]`
+    #[arg(long)]
+    pub fold_scalar_arrays: bool,
+
+    /// Shorten strings longer than N characters to their first N plus `…(M more)`
+    #[arg(long, value_name = "N")]
+    pub truncate_long_strings: Option<usize>,
+
+    /// Prefix each line with its nesting level, e.g. `2 | config.db.host="x"`
+    #[arg(long, conflicts_with_all = ["pretty", "template"])]
+    pub show_depth: bool,
+
+    /// Write only the key path of each entry, one per line
+    #[arg(long, conflicts_with_all = ["values_only", "template", "pretty"])]
+    pub keys_only: bool,
+
+    /// Write only the value of each entry, one per line
+    #[arg(long, conflicts_with_all = ["template", "pretty"])]
+    pub values_only: bool,
+
+    /// Transform object keys to this case; array index segments are never changed
+    #[arg(long, value_enum, value_name = "CASE")]
+    pub key_case: Option<KeyCaseArg>,
+
+    /// Keep containers above level N (top-level keys are level 0) as JSON strings of their
+    /// scalar members, flattening only their nested containers; 0 flattens everything
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub flatten_from: usize,
+
+    /// Reinterpret values at matching key paths, e.g. `config.port:number` (repeatable;
+    /// types: string, number, integer, float, bool; `*` matches within a segment, `**` across)
+    #[arg(long, value_name = "GLOB:TYPE", value_parser = CoercionRule::parse)]
+    pub coerce: Vec<CoercionRule>,
+
+    /// Coerce and check values against a JSON file mapping globs to types, e.g.
+    /// `{"config.port": "integer"}`; a matching value that cannot take its type is an error
+    /// (--coerce rules take precedence)
+    #[arg(long, value_name = "FILE")]
+    pub types: Option<PathBuf>,
+
+    /// Also write `KEY.__present=true` for every object field, even null or skipped ones,
+    /// so absent keys can be told apart from present ones
+    #[arg(long)]
+    pub presence_keys: bool,
+
+    /// Write arrays of more than N items as one entry holding the array as compact JSON,
+    /// e.g. `ids="[1,2,3]"`, instead of one entry per item
+    #[arg(long, value_name = "N")]
+    pub array_threshold: Option<usize>,
+
+    /// Convert only the first N items of every array, marking each shortened one with
+    /// `<key>.__truncated=true`, for a quick look at the shape of a large document
+    #[arg(long, value_name = "N")]
+    pub sample: Option<usize>,
+
+    /// Fail cleanly on input with containers nested more than N levels deep (at most 10000)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_RECURSION,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..=MAX_RECURSION_LIMIT as u64)
+    )]
+    pub max_recursion: usize,
+
+    /// Key for a top-level scalar or empty container, e.g. `value` for `value="hello"`
+    /// (empty by default, giving `="hello"`)
+    #[arg(long, value_name = "NAME", default_value = "")]
+    pub root_key: String,
+
+    /// Apply a JSON Merge Patch (RFC 7386) file to the input before converting (repeatable,
+    /// applied in order; a null member deletes the key, objects merge, anything else replaces)
+    #[arg(long, value_name = "FILE")]
+    pub patch: Vec<PathBuf>,
+
+    /// Substitute `${VAR}` in string values with environment variables after parsing
+    /// (`$${` writes a literal `${`)
+    #[arg(long)]
+    pub expand_env: bool,
+
+    /// Also substitute `${VAR}` in object keys
+    #[arg(long, requires = "expand_env")]
+    pub expand_env_keys: bool,
+
+    /// What to do with `${VAR}` when VAR is not set
+    #[arg(long, value_enum, value_name = "MODE", default_value_t = EnvMissing::Error, requires = "expand_env")]
+    pub env_missing: EnvMissing,
+
+    /// Exit with an error instead of writing output that has no entries
+    /// (e.g. when --skip-nulls or --skip-empty drops every value)
+    #[arg(long)]
+    pub fail_on_empty: bool,
+
+    /// Flatten string values that hold a JSON object or array under their own key,
+    /// repeatedly for double-encoded payloads (nesting counts towards --max-recursion)
+    #[arg(long)]
+    pub parse_embedded_json: bool,
+
+    /// Note the line of the source JSON each value starts on after its entry, e.g. `port=8080  # L42`
+    #[arg(long, conflicts_with = "jsonl")]
+    pub source_lines: bool,
+
+    /// Abort without writing anything if the output would exceed BYTES (exit code 3)
+    #[arg(long, value_name = "BYTES")]
+    pub max_output_size: Option<usize>,
+
+    /// Write repeated string values once in a table of `&v1="..."` references at the top,
+    /// and each entry holding one as `key=&v1` (TOON output only)
+    #[arg(long, conflicts_with = "jsonl")]
+    pub dedup_values: bool,
+
+    /// Begin the output with a `#root=TYPE` line (e.g. `#root=array`) when the top-level value
+    /// is not an object, so `0=1` from `[1]` is not mistaken for `{"0": 1}` (TOON output only)
+    #[arg(long, conflicts_with_all = ["jsonl", "multi_doc", "combine"])]
+    pub emit_root_type: bool,
+
+    /// Order the fields of objects whose keys are all integers (e.g. stringified IDs)
+    /// numerically, `2` before `10`; other objects keep their source order
+    #[arg(long)]
+    pub preserve_key_order_numeric: bool,
+
+    /// Nest a top-level array under NAME, e.g. `items.0=` instead of `0=`
+    #[arg(long, value_name = "NAME", default_value = "")]
+    pub array_root_key: String,
+
+    /// Drop leading key segments from every key that starts with them, e.g. `config` writes
+    /// `config.port` as `port` (segments joined by the key separator; other keys are unchanged)
+    #[arg(long, value_name = "PREFIX", default_value = "")]
+    pub strip_prefix: String,
+
+    /// Omit null values (including nulls inside arrays) instead of writing key=null
+    #[arg(long)]
+    pub skip_nulls: bool,
+
+    /// Omit empty values; optionally limit to a comma-separated list of kinds
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KINDS",
+        value_delimiter = ',',
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_values = ["string", "array", "object"]
+    )]
+    pub skip_empty: Vec<EmptyKind>,
+
+    /// Insert a blank line between top-level sections
+    #[arg(long)]
+    pub group_by_toplevel: bool,
+
+    /// Write a sorted list of all flattened key paths to this file
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    pub manifest: Option<PathBuf>,
+
+    /// Write a JSON summary (sizes, key count, max depth, duration, type counts) to this file;
+    /// in batch mode an array with one summary per converted file
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    pub summary_json: Option<PathBuf>,
+
+    /// With --recursive, --input-list or a glob pattern, write each file's outcome
+    /// (converted, skipped or failed, with the error) and the totals to FILE as JSON
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "combine"])]
+    pub report: Option<PathBuf>,
+
+    /// Encoding of the output; UTF-16 output starts with a byte order mark
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = OutputEncoding::Utf8)]
+    pub output_encoding: OutputEncoding,
+
+    /// Write JSON timings of the read, parse, traverse and write phases, and the
+    /// traversal time of each top-level key, to this file
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["dry_run", "jsonl"])]
+    pub profile: Option<PathBuf>,
+
+    /// Print a digest of the TOON output to stderr after conversion (named `-` for stdout)
+    #[arg(long, value_enum, value_name = "ALGORITHM")]
+    pub checksum: Option<ChecksumAlgorithm>,
+
+    /// Also write the digest to a sidecar file next to the output (e.g. out.toon.sha256);
+    /// needs an output file
+    #[arg(long, requires = "checksum")]
+    pub checksum_file: bool,
+
+    /// Run a command after each output file is written, e.g. "git add {output}";
+    /// {input} is "-" for stdin. Run without a shell; a failing command is an error
+    #[arg(long, value_name = "COMMAND", value_parser = Hook::parse)]
+    pub on_success: Option<Hook>,
+
+    /// Give the output file the input file's permission bits (e.g. keep a secret config at 0600)
+    #[arg(long)]
+    pub preserve_mode: bool,
+
+    /// If writing an output fails (e.g. the final rename), leave its temporary file in place
+    /// and print its path instead of removing it
+    #[arg(long)]
+    pub keep_temp: bool,
+
+    /// Log progress to stderr; repeat for more detail (-v info, -vv debug, -vvv trace).
+    /// RUST_LOG overrides this, e.g. RUST_LOG=json2toon::converter=debug
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Format for errors written to stderr
+    #[arg(long, global = true, value_enum, value_name = "FORMAT", default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// Colorize diagnostic output
+    #[arg(long, global = true, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compare two JSON files by their flattened keys
+    ///
+    /// Prints `+ key=value` for added keys, `- key=value` for removed keys and
+    /// `~ key: old -> new` for changed values. Exits with 1 when the files differ.
+    Diff {
+        /// Original JSON file
+        #[arg(value_name = "OLD")]
+        old: PathBuf,
+
+        /// Updated JSON file
+        #[arg(value_name = "NEW")]
+        new: PathBuf,
+    },
+
+    /// Check that a file is valid JSON, without converting it
+    ///
+    /// Prints `<file>: OK` and exits with 0 when the file parses, or prints
+    /// the parse error with its line and column and exits with 1.
+    Validate {
+        /// JSON file to check
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+
+        /// Check each non-empty line as a separate JSON document
+        #[arg(long)]
+        jsonl: bool,
+    },
+
+    /// Convert JSON snippets interactively
+    ///
+    /// Reads a JSON snippet terminated by a blank line from stdin, prints its
+    /// TOON, and repeats. `:set separator /` (and other `:set` options) apply
+    /// to the following snippets; `:help` lists them and `:quit` exits.
+    Repl,
 }
 
 impl Args {
+    pub fn convert_options(&self) -> ConvertOptions {
+        ConvertOptions {
+            input_format: self.input_format.into(),
+            allow_empty: self.allow_empty,
+            try_relaxed: self.try_relaxed,
+            group_by_toplevel: self.group_by_toplevel,
+            skip_nulls: self.skip_nulls,
+            skip_empty_strings: self.skip_empty.contains(&EmptyKind::String),
+            skip_empty_arrays: self.skip_empty.contains(&EmptyKind::Array),
+            skip_empty_objects: self.skip_empty.contains(&EmptyKind::Object),
+            key_case: self.key_case.map(KeyCase::from),
+            flatten_from: self.flatten_from,
+            coerce: self.coerce.clone(),
+            // Parsed from the --types file by the caller, like the patches
+            types: Vec::new(),
+            array_threshold: self.array_threshold,
+            sample: self.sample,
+            presence_keys: self.presence_keys,
+            max_recursion: self.max_recursion,
+            root_key: self.root_key.clone(),
+            numeric_key_order: self.preserve_key_order_numeric,
+            parse_embedded_json: self.parse_embedded_json,
+            max_output_size: self.max_output_size,
+            source_lines: self.source_lines,
+            dedup_values: self.dedup_values,
+            emit_root_type: self.emit_root_type,
+            array_root_key: self.array_root_key.clone(),
+            strip_prefix: self.strip_prefix.clone(),
+            // Parsed from the --patch files by the caller, which can report I/O errors
+            patches: Vec::new(),
+            expand_env: self.expand_env.then(|| Interpolation {
+                keys: self.expand_env_keys,
+                missing: self.env_missing.into(),
+            }),
+        }
+    }
+
+    /// Check constraints between arguments that clap cannot express
+    pub fn validate(&self) -> Result<(), clap::Error> {
+        let invalid = |flag: &str, message: String| {
+            Args::command().error(
+                clap::error::ErrorKind::ValueValidation,
+                format!("invalid value for --{}: {}", flag, message),
+            )
+        };
+        toon::check_assign(&self.assign, self.separator).map_err(|message| invalid("assign", message))?;
+        if let Some(separator) = self.array_sep {
+            toon::check_assign(&self.assign, separator).map_err(|message| invalid("assign", message))?;
+        }
+        toon::check_empty_token(&self.empty_array_token)
+            .map_err(|message| invalid("empty-array-token", message))?;
+        toon::check_empty_token(&self.empty_object_token)
+            .map_err(|message| invalid("empty-object-token", message))?;
+        if self.dedup_values && OutputFormat::from(self.format) != OutputFormat::Toon {
+            return Err(invalid(
+                "format",
+                "--dedup-values only applies to the toon format".to_string(),
+            ));
+        }
+        if self.annotate_bytes.is_some() && OutputFormat::from(self.format) != OutputFormat::Toon {
+            return Err(invalid(
+                "format",
+                "--annotate-bytes only applies to the toon format".to_string(),
+            ));
+        }
+        if self.emit_root_type && OutputFormat::from(self.format) != OutputFormat::Toon {
+            return Err(invalid(
+                "format",
+                "--emit-root-type only applies to the toon format".to_string(),
+            ));
+        }
+        if self.source_lines && OutputFormat::from(self.format) != OutputFormat::Toon {
+            return Err(invalid(
+                "format",
+                "--source-lines only applies to the toon format".to_string(),
+            ));
+        }
+        if self.float_style == FloatStyleArg::Preserve && OutputFormat::from(self.format) != OutputFormat::Toon {
+            return Err(invalid(
+                "format",
+                "--float-style preserve only applies to the toon format".to_string(),
+            ));
+        }
+        if self.float_style == FloatStyleArg::Preserve && self.input_format != InputSyntax::Json {
+            return Err(invalid(
+                "input-format",
+                "--float-style preserve needs JSON input".to_string(),
+            ));
+        }
+        if self.try_relaxed && self.input_format != InputSyntax::Json {
+            return Err(invalid(
+                "input-format",
+                "--try-relaxed needs JSON input".to_string(),
+            ));
+        }
+        if self.source_lines && self.input_format != InputSyntax::Json {
+            return Err(invalid(
+                "input-format",
+                "--source-lines needs JSON input".to_string(),
+            ));
+        }
+        if self.output.len() > 1 && self.output.iter().any(|output| output == Path::new("-")) {
+            return Err(invalid(
+                "output",
+                "stdout (-) cannot be one of several outputs".to_string(),
+            ));
+        }
+        if self.chunk_lines.is_some() && self.output.len() > 1 {
+            return Err(invalid(
+                "chunk-lines",
+                "needs a single --output".to_string(),
+            ));
+        }
+        if self.chunk_lines.is_some() && self.input.is_some() && self.writes_stdout() {
+            return Err(invalid(
+                "chunk-lines",
+                "output must go to a file, not stdout".to_string(),
+            ));
+        }
+        let batch = self.recursive || self.input_list.is_some() || self.glob_pattern().is_some();
+        if self.combine.is_some() && !batch {
+            return Err(invalid(
+                "combine",
+                "needs --recursive, --input-list or a glob pattern as input".to_string(),
+            ));
+        }
+        if self.checksum_file && !batch && self.input.is_some() && self.writes_stdout() {
+            return Err(invalid(
+                "checksum-file",
+                "needs an output file to write the sidecar next to, not stdout".to_string(),
+            ));
+        }
+        if self.report.is_some() && !batch {
+            return Err(invalid(
+                "report",
+                "needs --recursive, --input-list or a glob pattern as input".to_string(),
+            ));
+        }
+        if self.empty_array_token == self.empty_object_token {
+            return Err(invalid(
+                "empty-object-token",
+                "must differ from --empty-array-token".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn toon_options(&self) -> ToonOptions {
+        ToonOptions {
+            separator: self.separator,
+            array_separator: self.array_sep,
+            assign: self.assign.clone(),
+            annotate_types: self.annotate_types,
+            unquoted_safe_strings: self.unquoted_safe_strings,
+            pretty: self.pretty.then_some(match self.indent_char {
+                IndentChar::Space => Indent::Spaces(self.indent_width),
+                IndentChar::Tab => Indent::Tab,
+            }),
+            template: self.template.clone(),
+            output_format: self.format.into(),
+            show_depth: self.show_depth,
+            tabular: self.tabular,
+            fold_scalar_arrays: self.fold_scalar_arrays,
+            truncate_strings: self.truncate_long_strings,
+            quote_numbers: self.quote_numbers,
+            quote_bools: self.quote_bools,
+            escape_separator: self.escape_separator,
+            quote_numeric_keys: self.numeric_keys_quoted,
+            empty_array_token: self.empty_array_token.clone(),
+            empty_object_token: self.empty_object_token.clone(),
+            float_style: self.float_style.into(),
+            whole_number_limit: self.stable_float_threshold,
+            normalize_whitespace: self.normalize_whitespace,
+            annotate_bytes: self.annotate_bytes.map(Into::into),
+            projection: if self.keys_only {
+                Projection::KeysOnly
+            } else if self.values_only {
+                Projection::ValuesOnly
+            } else {
+                Projection::Entries
+            },
+        }
+    }
+
+    pub fn get_input_path(&self) -> &PathBuf {
+        self.input
+            .as_ref()
+            .expect("clap requires an input unless --version is given")
+    }
+
+    /// The input as a glob pattern to expand, e.g. `data/**/*.json`
+    ///
+    /// Only an input that does not name an existing path but contains `*`, `?`
+    /// or `[` is a pattern, and never with `--no-glob`.
+    pub fn glob_pattern(&self) -> Option<&str> {
+        let input = self.input.as_deref()?;
+        if self.no_glob || input.exists() {
+            return None;
+        }
+        input.to_str().filter(|input| input.contains(['*', '?', '[']))
+    }
+
+    /// Whether the input is read from stdin (`-`)
+    pub fn reads_stdin(&self) -> bool {
+        self.get_input_path() == Path::new("-")
+    }
+
+    /// Whether the input is a named pipe or device rather than a file, e.g. `<(generate.sh)`
+    pub fn reads_stream(&self) -> bool {
+        std::fs::metadata(self.get_input_path()).is_ok_and(|metadata| !metadata.is_file() && !metadata.is_dir())
+    }
+
+    /// Whether the output goes to stdout (`-o -`, or stdin or stream input without `-o`)
+    pub fn writes_stdout(&self) -> bool {
+        match self.output.as_slice() {
+            [] => self.reads_stdin() || self.reads_stream(),
+            [output] => output == Path::new("-"),
+            _ => false,
+        }
+    }
+
+    /// The first output path; see [`Args::get_output_paths`]
     pub fn get_output_path(&self) -> PathBuf {
-        if let Some(ref output) = self.output {
-            output.clone()
+        match self.output.first() {
+            Some(output) => output.clone(),
+            None => self.get_input_path().with_extension(self.output_extension()),
+        }
+    }
+
+    /// Every --output path, or the default output path when none is given
+    pub fn get_output_paths(&self) -> Vec<PathBuf> {
+        if self.output.is_empty() {
+            vec![self.get_output_path()]
         } else {
-            self.input.with_extension("toon")
+            self.output.clone()
+        }
+    }
+
+    /// Extension of default output paths: --out-ext, or the output format's
+    pub fn output_extension(&self) -> &str {
+        self.out_ext.as_deref().unwrap_or(self.format.extension())
+    }
+
+    /// File extensions --recursive picks up for the input format
+    pub fn input_extensions(&self) -> &'static [&'static str] {
+        if self.jsonl {
+            return &["jsonl", "ndjson"];
+        }
+        match self.input_format {
+            InputSyntax::Json => &["json"],
+            InputSyntax::Toml => &["toml"],
+            InputSyntax::Yaml => &["yaml", "yml"],
         }
     }
 }
 
+/// Write the completion script for `shell`, generated from the argument definitions
+pub fn write_completions(shell: Shell, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut Args::command(), "json2toon", out);
+}
+
+/// Write the roff man page, generated from the argument definitions
+pub fn write_man_page(out: &mut dyn Write) -> std::io::Result<()> {
+    clap_mangen::Man::new(Args::command()).render(out)
+}
+
+/// Validate a key separator: one character that cannot be confused with the
+/// rest of the line grammar
+pub fn parse_separator(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    let (Some(separator), None) = (chars.next(), chars.next()) else {
+        return Err("separator must be a single character".to_string());
+    };
+    let reserved = matches!(separator, '=' | '\\' | QUOTE);
+    if reserved || separator.is_whitespace() || separator.is_control() {
+        return Err(format!("{:?} cannot be used as a separator", separator));
+    }
+    Ok(separator)
+}
+
+/// Validate an `--out-ext` value, accepting it with or without a leading dot
+fn parse_extension(ext: &str) -> Result<String, String> {
+    let ext = ext.strip_prefix('.').unwrap_or(ext);
+    if ext.is_empty() {
+        return Err("extension must not be empty".to_string());
+    }
+    if ext.contains(['/', '\\']) {
+        return Err("extension must not contain path separators".to_string());
+    }
+    Ok(ext.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_cli_definition() {
+        Args::command().debug_assert();
+    }
+
     #[test]
     fn test_default_output_path() {
-        let args = Args {
-            input: PathBuf::from("test.json"),
-            output: None,
-            version: false,
-            dry_run: false,
-            verbose: false,
-        };
+        let args = Args::parse_from(["json2toon", "test.json"]);
         assert_eq!(args.get_output_path(), PathBuf::from("test.toon"));
     }
 
+    #[test]
+    fn test_out_ext() {
+        let args = Args::parse_from(["json2toon", "--out-ext", "tn", "data/test.json"]);
+        assert_eq!(args.get_output_path(), PathBuf::from("data/test.tn"));
+
+        let args = Args::parse_from(["json2toon", "--out-ext", ".tn", "test.json", "-o", "x.toon"]);
+        assert_eq!(args.get_output_path(), PathBuf::from("x.toon"));
+
+        assert!(Args::try_parse_from(["json2toon", "--out-ext", "a/b", "test.json"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--out-ext", "..\\x", "test.json"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--out-ext", ".", "test.json"]).is_err());
+    }
+
     #[test]
     fn test_custom_output_path() {
-        let args = Args {
-            input: PathBuf::from("test.json"),
-            output: Some(PathBuf::from("custom.toon")),
-            version: false,
-            dry_run: false,
-            verbose: false,
-        };
+        let args = Args::parse_from(["json2toon", "test.json", "-o", "custom.toon"]);
         assert_eq!(args.get_output_path(), PathBuf::from("custom.toon"));
     }
+
+    #[test]
+    fn test_repeated_output() {
+        let args = Args::parse_from(["json2toon", "test.json", "-o", "build/a.toon", "-o", "cache/a.toon"]);
+        assert_eq!(args.get_output_paths(), [PathBuf::from("build/a.toon"), PathBuf::from("cache/a.toon")]);
+        assert!(!args.writes_stdout());
+        assert!(args.validate().is_ok());
+        assert_eq!(Args::parse_from(["json2toon", "test.json"]).get_output_paths(), [PathBuf::from("test.toon")]);
+
+        assert!(Args::parse_from(["json2toon", "test.json", "-o", "a.toon", "-o", "-"]).validate().is_err());
+        let args = Args::parse_from(["json2toon", "--chunk-lines", "5", "test.json", "-o", "a", "-o", "b"]);
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_stdin_defaults_to_stdout() {
+        let args = Args::parse_from(["json2toon", "-"]);
+        assert!(args.reads_stdin());
+        assert!(args.writes_stdout());
+
+        let args = Args::parse_from(["json2toon", "-", "-o", "out.toon"]);
+        assert!(!args.writes_stdout());
+
+        let args = Args::parse_from(["json2toon", "test.json", "-o", "-"]);
+        assert!(!args.reads_stdin());
+        assert!(args.writes_stdout());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stream_input_defaults_to_stdout() {
+        let args = Args::parse_from(["json2toon", "/dev/null"]);
+        assert!(args.reads_stream());
+        assert!(args.writes_stdout());
+
+        let args = Args::parse_from(["json2toon", "/dev/null", "-o", "out.toon"]);
+        assert!(!args.writes_stdout());
+        assert!(!Args::parse_from(["json2toon", "test.json"]).reads_stream());
+        assert!(!Args::parse_from(["json2toon", "."]).reads_stream());
+    }
+
+    #[test]
+    fn test_diff_subcommand() {
+        let args = Args::parse_from(["json2toon", "diff", "a.json", "b.json"]);
+        match args.command {
+            Some(Command::Diff { old, new }) => {
+                assert_eq!(old, PathBuf::from("a.json"));
+                assert_eq!(new, PathBuf::from("b.json"));
+            }
+            _ => panic!("expected diff subcommand"),
+        }
+        assert!(args.input.is_none());
+    }
+
+    #[test]
+    fn test_validate_subcommand() {
+        let args = Args::parse_from(["json2toon", "validate", "--jsonl", "events.jsonl"]);
+        match args.command {
+            Some(Command::Validate { file, jsonl }) => {
+                assert_eq!(file, PathBuf::from("events.jsonl"));
+                assert!(jsonl);
+            }
+            _ => panic!("expected validate subcommand"),
+        }
+        assert!(args.input.is_none());
+    }
+
+    #[test]
+    fn test_repl_subcommand() {
+        let args = Args::parse_from(["json2toon", "repl"]);
+        assert!(matches!(args.command, Some(Command::Repl)));
+    }
+
+    #[test]
+    fn test_version_without_input() {
+        let args = Args::parse_from(["json2toon", "-V"]);
+        assert!(args.version);
+        assert!(args.input.is_none());
+    }
+
+    #[test]
+    fn test_color_choice() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.color, ColorChoice::Auto);
+
+        let args = Args::parse_from(["json2toon", "--color", "never", "test.json"]);
+        assert_eq!(args.color, ColorChoice::Never);
+    }
+
+    #[test]
+    fn test_toon_options() {
+        let args = Args::parse_from(["json2toon", "--annotate-types", "test.json"]);
+        assert!(args.toon_options().annotate_types);
+
+        let args = Args::parse_from(["json2toon", "--quote-numbers", "--quote-bools", "test.json"]);
+        let options = args.toon_options();
+        assert!(options.quote_numbers && options.quote_bools);
+        assert_eq!(options.whole_number_limit, 1 << 53);
+        assert!(!options.normalize_whitespace);
+
+        let args = Args::parse_from(["json2toon", "--normalize-whitespace", "test.json"]);
+        assert!(args.toon_options().normalize_whitespace);
+
+        let args = Args::parse_from(["json2toon", "--stable-float-threshold", "1000", "test.json"]);
+        assert_eq!(args.toon_options().whole_number_limit, 1000);
+    }
+
+    #[test]
+    fn test_float_style_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.toon_options().float_style, FloatStyle::IntegerCollapse);
+
+        let args = Args::parse_from(["json2toon", "--float-style", "preserve", "test.json"]);
+        assert!(args.validate().is_ok());
+        assert_eq!(args.toon_options().float_style, FloatStyle::Preserve);
+        let args = Args::parse_from(["json2toon", "--float-style", "trim", "--format", "tsv", "test.json"]);
+        assert!(args.validate().is_ok());
+        assert!(Args::parse_from(["json2toon", "--float-style", "preserve", "--format", "tsv", "x"]).validate().is_err());
+        assert!(Args::parse_from(["json2toon", "--float-style", "preserve", "--input-format", "toml", "x"]).validate().is_err());
+        assert!(Args::try_parse_from(["json2toon", "--float-style", "round", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_pretty_indent_options() {
+        let args = Args::parse_from(["json2toon", "--pretty", "test.json"]);
+        assert_eq!(args.toon_options().pretty, Some(Indent::Spaces(2)));
+
+        let args = Args::parse_from([
+            "json2toon",
+            "--pretty",
+            "--indent-char",
+            "tab",
+            "--indent-width",
+            "8",
+            "test.json",
+        ]);
+        assert_eq!(args.toon_options().pretty, Some(Indent::Tab));
+
+        assert!(Args::try_parse_from(["json2toon", "--indent-width", "4", "test.json"]).is_err());
+    }
+
+    #[test]
+    fn test_template_option() {
+        let args = Args::parse_from(["json2toon", "--template", "{key}: {value}", "test.json"]);
+        assert!(args.toon_options().template.is_some());
+
+        assert!(Args::try_parse_from(["json2toon", "--template", "{key}", "test.json"]).is_err());
+        let result =
+            Args::try_parse_from(["json2toon", "--template", "{key}={value}", "--pretty", "x"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_format_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.toon_options().output_format, OutputFormat::Toon);
+
+        let args = Args::parse_from(["json2toon", "--format", "tsv", "test.json"]);
+        assert_eq!(args.toon_options().output_format, OutputFormat::Tsv);
+        assert_eq!(args.get_output_path(), PathBuf::from("test.tsv"));
+
+        let args = Args::parse_from(["json2toon", "--format", "jsonpath", "test.json"]);
+        assert_eq!(args.toon_options().output_format, OutputFormat::JsonPointer);
+
+        let result = Args::try_parse_from(["json2toon", "--format", "csv", "--pretty", "x"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_projection_options() {
+        let args = Args::parse_from(["json2toon", "--keys-only", "test.json"]);
+        assert_eq!(args.toon_options().projection, Projection::KeysOnly);
+
+        let args = Args::parse_from(["json2toon", "--values-only", "test.json"]);
+        assert_eq!(args.toon_options().projection, Projection::ValuesOnly);
+
+        let result = Args::try_parse_from(["json2toon", "--keys-only", "--values-only", "x"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_input_format() {
+        let args = Args::parse_from(["json2toon", "config.toml", "--input-format", "toml"]);
+        assert_eq!(args.convert_options().input_format, InputFormat::Toml);
+        assert_eq!(args.get_output_path(), PathBuf::from("config.toon"));
+
+        let result = Args::try_parse_from(["json2toon", "--jsonl", "--input-format", "yaml", "x"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_show_depth_option() {
+        let args = Args::parse_from(["json2toon", "--show-depth", "test.json"]);
+        assert!(args.toon_options().show_depth);
+
+        assert!(Args::try_parse_from(["json2toon", "--show-depth", "--pretty", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_tabular_option() {
+        let args = Args::parse_from(["json2toon", "--tabular", "test.json"]);
+        assert!(args.toon_options().tabular);
+
+        assert!(Args::try_parse_from(["json2toon", "--tabular", "--format", "csv", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_truncate_long_strings_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.toon_options().truncate_strings, None);
+
+        let args = Args::parse_from(["json2toon", "--truncate-long-strings", "20", "test.json"]);
+        assert_eq!(args.toon_options().truncate_strings, Some(20));
+    }
+
+    #[test]
+    fn test_fold_scalar_arrays_option() {
+        let args = Args::parse_from(["json2toon", "--fold-scalar-arrays", "test.json"]);
+        assert!(args.toon_options().fold_scalar_arrays);
+        assert!(!Args::parse_from(["json2toon", "test.json"]).toon_options().fold_scalar_arrays);
+    }
+
+    #[test]
+    fn test_separator() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.toon_options().separator, '.');
+
+        let args = Args::parse_from(["json2toon", "--separator", "/", "test.json"]);
+        assert_eq!(args.toon_options().separator, '/');
+
+        for bad in ["", "ab", "=", " ", "\""] {
+            assert!(parse_separator(bad).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn test_array_sep() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.toon_options().array_separator, None);
+
+        let args = Args::parse_from(["json2toon", "--object-sep", "/", "--array-sep", "#", "test.json"]);
+        let options = args.toon_options();
+        assert_eq!((options.separator, options.array_separator), ('/', Some('#')));
+        assert!(args.validate().is_ok());
+
+        assert!(Args::try_parse_from(["json2toon", "--array-sep", "=", "test.json"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--array-sep", "#", "--pretty", "test.json"]).is_err());
+        let args = Args::parse_from(["json2toon", "--array-sep", ":", "--assign", ":", "test.json"]);
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_key_case_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.convert_options().key_case, None);
+
+        let args = Args::parse_from(["json2toon", "--key-case", "camel", "test.json"]);
+        assert_eq!(args.convert_options().key_case, Some(KeyCase::Camel));
+    }
+
+    #[test]
+    fn test_coerce_option() {
+        let args = Args::parse_from([
+            "json2toon",
+            "--coerce",
+            "config.port:number",
+            "--coerce",
+            "**.enabled:bool",
+            "test.json",
+        ]);
+        let rules = args.convert_options().coerce;
+        assert_eq!(rules.len(), 2);
+        assert!(rules[0].matches("config.port", '.'));
+
+        assert!(Args::try_parse_from(["json2toon", "--coerce", "port:decimal", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_completions() {
+        let args = Args::parse_from(["json2toon", "--generate-completions", "bash"]);
+        assert_eq!(args.generate_completions, Some(Shell::Bash));
+
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut script = Vec::new();
+            write_completions(shell, &mut script);
+            let script = String::from_utf8(script).unwrap();
+            assert!(script.contains("json2toon"));
+            assert!(script.contains("recursive"), "{:?} completes flags", shell);
+        }
+
+        assert!(Args::try_parse_from(["json2toon", "--generate-completions", "bash", "x.json"]).is_err());
+    }
+
+    #[test]
+    fn test_man_page() {
+        let args = Args::parse_from(["json2toon", "--generate-man"]);
+        assert!(args.generate_man);
+
+        let mut page = Vec::new();
+        write_man_page(&mut page).unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.starts_with(".ie"), "roff output");
+        assert!(page.contains(".TH json2toon"));
+        // Built from the same definitions as --help
+        assert!(page.contains("Exit Codes"));
+        assert!(page.contains("recursive"));
+    }
+
+    #[test]
+    fn test_glob_pattern() {
+        let pattern = "no-such-dir/**/*.json";
+        assert_eq!(Args::parse_from(["json2toon", pattern]).glob_pattern(), Some(pattern));
+        assert_eq!(Args::parse_from(["json2toon", "--no-glob", pattern]).glob_pattern(), None);
+        assert_eq!(Args::parse_from(["json2toon", "missing.json"]).glob_pattern(), None);
+        // An existing path is taken literally
+        assert_eq!(Args::parse_from(["json2toon", "src"]).glob_pattern(), None);
+    }
+
+    #[test]
+    fn test_recursive_options() {
+        let args = Args::parse_from(["json2toon", "-r", "--force", "configs"]);
+        assert!(args.recursive && args.force);
+        assert_eq!(args.input_extensions(), ["json"]);
+
+        assert!(!args.follow_symlinks);
+        assert!(Args::parse_from(["json2toon", "-r", "--follow-symlinks", "configs"]).follow_symlinks);
+        assert!(Args::try_parse_from(["json2toon", "--follow-symlinks", "configs"]).is_err());
+
+        let args = Args::parse_from(["json2toon", "-r", "--out-dir", "build", "configs"]);
+        assert_eq!(args.out_dir, Some(PathBuf::from("build")));
+        assert!(Args::try_parse_from(["json2toon", "--out-dir", "build", "configs"]).is_err());
+
+        let args = Args::parse_from(["json2toon", "-r", "--input-format", "yaml", "configs"]);
+        assert_eq!(args.input_extensions(), ["yaml", "yml"]);
+
+        assert!(Args::try_parse_from(["json2toon", "--force", "configs"]).is_err());
+
+        let args = Args::parse_from(["json2toon", "--input-list", "files.txt", "--out-dir", "build"]);
+        assert_eq!(args.input_list, Some(PathBuf::from("files.txt")));
+        assert!(Args::try_parse_from(["json2toon", "--input-list", "-", "x.json"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--input-list", "-", "-r"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "-r", "-o", "x.toon", "configs"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "-r", "--summary-json", "s.json", "configs"]).is_ok());
+        assert!(Args::try_parse_from(["json2toon", "--input-list", "-", "--summary-json", "s.json"]).is_ok());
+    }
+
+    #[test]
+    fn test_numeric_keys_quoted_option() {
+        assert!(!Args::parse_from(["json2toon", "x"]).toon_options().quote_numeric_keys);
+        assert!(Args::parse_from(["json2toon", "--numeric-keys-quoted", "x"]).toon_options().quote_numeric_keys);
+    }
+
+    #[test]
+    fn test_escape_separator_option() {
+        assert!(!Args::parse_from(["json2toon", "x"]).toon_options().escape_separator);
+        let options = Args::parse_from(["json2toon", "--separator", "/", "--escape-separator", "x"]).toon_options();
+        assert!(options.escape_separator);
+        assert_eq!(options.separator, '/');
+    }
+
+    #[test]
+    fn test_empty_token_options() {
+        let options = Args::parse_from(["json2toon", "x"]).toon_options();
+        assert_eq!((options.empty_array_token.as_str(), options.empty_object_token.as_str()), ("[]", "{}"));
+
+        let args = Args::parse_from(["json2toon", "--empty-array-token", "-", "x"]);
+        assert!(args.validate().is_ok());
+        assert_eq!(args.toon_options().empty_array_token, "-");
+
+        assert!(Args::parse_from(["json2toon", "--empty-array-token", "null", "x"]).validate().is_err());
+        assert!(Args::parse_from(["json2toon", "--empty-object-token", "[]", "x"]).validate().is_err());
+    }
+
+    #[test]
+    fn test_assign_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.toon_options().assign, "=");
+        assert!(args.validate().is_ok());
+
+        let args = Args::parse_from(["json2toon", "--assign", ": ", "test.json"]);
+        assert_eq!(args.toon_options().assign, ": ");
+        assert!(args.validate().is_ok());
+
+        // Collisions with the key separator are only known once all arguments are parsed
+        let args = Args::parse_from(["json2toon", "--assign", ":", "--separator", ":", "test.json"]);
+        assert!(args.validate().is_err());
+        assert!(Args::parse_from(["json2toon", "--assign", "\"", "x"]).validate().is_err());
+    }
+
+    #[test]
+    fn test_root_key_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.convert_options().root_key, "");
+
+        let args = Args::parse_from(["json2toon", "--root-key", "value", "test.json"]);
+        assert_eq!(args.convert_options().root_key, "value");
+    }
+
+    #[test]
+    fn test_verbose_count() {
+        assert_eq!(Args::parse_from(["json2toon", "x"]).verbose, 0);
+        assert_eq!(Args::parse_from(["json2toon", "-v", "x"]).verbose, 1);
+        assert_eq!(Args::parse_from(["json2toon", "-vv", "x"]).verbose, 2);
+    }
+
+    #[test]
+    fn test_profile_option() {
+        let args = Args::parse_from(["json2toon", "--profile", "profile.json", "in.json"]);
+        assert_eq!(args.profile, Some(PathBuf::from("profile.json")));
+        assert!(Args::try_parse_from(["json2toon", "--profile", "p.json", "--jsonl", "in.jsonl"]).is_err());
+    }
+
+    #[test]
+    fn test_patch_option() {
+        let args = Args::parse_from(["json2toon", "--patch", "prod.json", "--patch", "local.json", "base.json"]);
+        assert_eq!(args.patch, [PathBuf::from("prod.json"), PathBuf::from("local.json")]);
+    }
+
+    #[test]
+    fn test_expand_env_options() {
+        let args = Args::parse_from(["json2toon", "x"]);
+        assert_eq!(args.convert_options().expand_env, None);
+
+        let args = Args::parse_from(["json2toon", "--expand-env", "--expand-env-keys", "--env-missing", "keep", "x"]);
+        assert_eq!(
+            args.convert_options().expand_env,
+            Some(Interpolation {
+                keys: true,
+                missing: MissingVar::Keep,
+            })
+        );
+
+        assert!(Args::try_parse_from(["json2toon", "--env-missing", "empty", "x"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--expand-env-keys", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_embedded_json_option() {
+        assert!(!Args::parse_from(["json2toon", "x"]).convert_options().parse_embedded_json);
+        let args = Args::parse_from(["json2toon", "--parse-embedded-json", "x"]);
+        assert!(args.convert_options().parse_embedded_json);
+    }
+
+    #[test]
+    fn test_chunk_lines_option() {
+        let args = Args::parse_from(["json2toon", "--chunk-lines", "1000", "x.json"]);
+        assert!(args.validate().is_ok());
+        assert_eq!(args.chunk_lines, NonZeroUsize::new(1000));
+        assert!(Args::try_parse_from(["json2toon", "--chunk-lines", "0", "x.json"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--chunk-lines", "10", "-r", "dir"]).is_err());
+        assert!(Args::parse_from(["json2toon", "--chunk-lines", "10", "-"]).validate().is_err());
+    }
+
+    #[test]
+    fn test_source_lines_option() {
+        let args = Args::parse_from(["json2toon", "--source-lines", "x"]);
+        assert!(args.validate().is_ok());
+        assert!(args.convert_options().source_lines);
+        assert!(Args::parse_from(["json2toon", "--source-lines", "--format", "tsv", "x"]).validate().is_err());
+        assert!(Args::parse_from(["json2toon", "--source-lines", "--input-format", "yaml", "x"]).validate().is_err());
+        assert!(Args::try_parse_from(["json2toon", "--source-lines", "--jsonl", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_multi_doc_option() {
+        assert!(Args::parse_from(["json2toon", "--multi-doc", "dump.json"]).multi_doc);
+        assert!(Args::try_parse_from(["json2toon", "--multi-doc", "--jsonl", "x"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--multi-doc", "--input-format", "yaml", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_max_output_size_option() {
+        assert_eq!(Args::parse_from(["json2toon", "x"]).convert_options().max_output_size, None);
+        let args = Args::parse_from(["json2toon", "--max-output-size", "1048576", "x"]);
+        assert_eq!(args.convert_options().max_output_size, Some(1048576));
+        assert!(Args::try_parse_from(["json2toon", "--max-output-size", "1MB", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_dedup_values_option() {
+        let args = Args::parse_from(["json2toon", "--dedup-values", "x"]);
+        assert!(args.validate().is_ok());
+        assert!(args.convert_options().dedup_values);
+        assert!(Args::parse_from(["json2toon", "--dedup-values", "--format", "csv", "x"]).validate().is_err());
+        assert!(Args::try_parse_from(["json2toon", "--dedup-values", "--jsonl", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_try_relaxed_option() {
+        assert!(!Args::parse_from(["json2toon", "x"]).convert_options().try_relaxed);
+        let args = Args::parse_from(["json2toon", "--try-relaxed", "x"]);
+        assert!(args.validate().is_ok());
+        assert!(args.convert_options().try_relaxed);
+        let args = Args::parse_from(["json2toon", "--try-relaxed", "--input-format", "yaml", "x"]);
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn test_annotate_bytes_option() {
+        assert_eq!(Args::parse_from(["json2toon", "x"]).toon_options().annotate_bytes, None);
+        let args = Args::parse_from(["json2toon", "--annotate-bytes", "x"]);
+        assert_eq!(args.toon_options().annotate_bytes, Some(ByteCount::Raw));
+        let args = Args::parse_from(["json2toon", "--annotate-bytes=escaped", "x"]);
+        assert_eq!(args.toon_options().annotate_bytes, Some(ByteCount::Escaped));
+        assert!(Args::parse_from(["json2toon", "--annotate-bytes", "--format", "csv", "x"]).validate().is_err());
+    }
+
+    #[test]
+    fn test_emit_root_type_option() {
+        let args = Args::parse_from(["json2toon", "--emit-root-type", "x"]);
+        assert!(args.validate().is_ok());
+        assert!(args.convert_options().emit_root_type);
+        assert!(Args::parse_from(["json2toon", "--emit-root-type", "--format", "csv", "x"]).validate().is_err());
+        assert!(Args::try_parse_from(["json2toon", "--emit-root-type", "--jsonl", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_numeric_key_order_option() {
+        assert!(!Args::parse_from(["json2toon", "x"]).convert_options().numeric_key_order);
+        let args = Args::parse_from(["json2toon", "--preserve-key-order-numeric", "x"]);
+        assert!(args.convert_options().numeric_key_order);
+    }
+
+    #[test]
+    fn test_array_root_key_option() {
+        let args = Args::parse_from(["json2toon", "--array-root-key", "items", "x"]);
+        assert_eq!(args.convert_options().array_root_key, "items");
+    }
+
+    #[test]
+    fn test_combine_option() {
+        let args = Args::parse_from(["json2toon", "-r", "--combine", "all.toon", "configs"]);
+        assert_eq!(args.combine, Some(PathBuf::from("all.toon")));
+        assert!(args.validate().is_ok());
+        assert!(Args::parse_from(["json2toon", "--combine", "all.toon", "in.json"]).validate().is_err());
+        assert!(Args::try_parse_from(["json2toon", "-r", "--combine", "a", "--out-dir", "b", "c"]).is_err());
+    }
+
+    #[test]
+    fn test_strip_prefix_option() {
+        assert_eq!(Args::parse_from(["json2toon", "x"]).convert_options().strip_prefix, "");
+        let args = Args::parse_from(["json2toon", "--strip-prefix", "config.db", "x"]);
+        assert_eq!(args.convert_options().strip_prefix, "config.db");
+    }
+
+    #[test]
+    fn test_presence_keys_option() {
+        assert!(!Args::parse_from(["json2toon", "x"]).convert_options().presence_keys);
+        assert!(Args::parse_from(["json2toon", "--presence-keys", "x"]).convert_options().presence_keys);
+    }
+
+    #[test]
+    fn test_array_threshold_option() {
+        assert_eq!(Args::parse_from(["json2toon", "x"]).convert_options().array_threshold, None);
+        let args = Args::parse_from(["json2toon", "--array-threshold", "100", "x"]);
+        assert_eq!(args.convert_options().array_threshold, Some(100));
+    }
+
+    #[test]
+    fn test_sample_option() {
+        assert_eq!(Args::parse_from(["json2toon", "x"]).convert_options().sample, None);
+        let args = Args::parse_from(["json2toon", "--sample", "5", "x"]);
+        assert_eq!(args.convert_options().sample, Some(5));
+    }
+
+    #[test]
+    fn test_max_recursion_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.convert_options().max_recursion, DEFAULT_MAX_RECURSION);
+
+        let args = Args::parse_from(["json2toon", "--max-recursion", "64", "test.json"]);
+        assert_eq!(args.convert_options().max_recursion, 64);
+
+        let args = Args::parse_from(["json2toon", "--max-recursion", "10000", "test.json"]);
+        assert_eq!(args.convert_options().max_recursion, MAX_RECURSION_LIMIT);
+        assert!(Args::try_parse_from(["json2toon", "--max-recursion", "10001", "test.json"]).is_err());
+        assert!(Args::try_parse_from(["json2toon", "--max-recursion", "0", "test.json"]).is_err());
+    }
+
+    #[test]
+    fn test_flatten_from_option() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.convert_options().flatten_from, 0);
+
+        let args = Args::parse_from(["json2toon", "--flatten-from", "2", "test.json"]);
+        assert_eq!(args.convert_options().flatten_from, 2);
+    }
+
+    #[test]
+    fn test_skip_empty_kinds() {
+        let options = Args::parse_from(["json2toon", "test.json"]).convert_options();
+        assert!(!options.skip_empty_strings && !options.skip_empty_arrays);
+
+        let options =
+            Args::parse_from(["json2toon", "--skip-empty", "test.json"]).convert_options();
+        assert!(options.skip_empty_strings);
+        assert!(options.skip_empty_arrays);
+        assert!(options.skip_empty_objects);
+
+        let args = Args::parse_from(["json2toon", "--skip-empty=string,array", "test.json"]);
+        let options = args.convert_options();
+        assert!(options.skip_empty_strings);
+        assert!(options.skip_empty_arrays);
+        assert!(!options.skip_empty_objects);
+    }
+
+    #[test]
+    fn test_error_format() {
+        let args = Args::parse_from(["json2toon", "test.json"]);
+        assert_eq!(args.error_format, ErrorFormat::Text);
+
+        let args = Args::parse_from(["json2toon", "test.json", "--error-format", "json"]);
+        assert_eq!(args.error_format, ErrorFormat::Json);
+    }
+
+    #[test]
+    fn test_checksum_file_requires_checksum() {
+        assert!(Args::try_parse_from(["json2toon", "--checksum-file", "test.json"]).is_err());
+
+        let args = Args::parse_from([
+            "json2toon",
+            "--checksum",
+            "sha256",
+            "--checksum-file",
+            "test.json",
+        ]);
+        assert_eq!(args.checksum, Some(ChecksumAlgorithm::Sha256));
+        assert!(args.checksum_file);
+        assert!(args.validate().is_ok());
+
+        let args = Args::parse_from(["json2toon", "--checksum", "md5", "--checksum-file", "-"]);
+        assert!(args.validate().is_err());
+        assert!(Args::parse_from(["json2toon", "--checksum", "md5", "-"]).validate().is_ok());
+    }
+
+    #[test]
+    fn test_preview_requires_dry_run() {
+        let args = Args::parse_from(["json2toon", "-n", "--preview", "5", "test.json"]);
+        assert_eq!(args.preview, Some(5));
+
+        assert!(Args::try_parse_from(["json2toon", "--preview", "5", "test.json"]).is_err());
+    }
+
+    #[test]
+    fn test_count_only_conflicts_with_dry_run() {
+        let result = Args::try_parse_from(["json2toon", "--count-only", "-n", "test.json"]);
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,278 @@
+//! Path-based type coercion rules for `--coerce` and `--types`
+
+use crate::prelude::*;
+use serde_json::{Number, Value};
+
+/// Type a matching value is reinterpreted as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoerceType {
+    String,
+    /// An integer when the text is integral, otherwise a float
+    Number,
+    Integer,
+    Float,
+    Bool,
+}
+
+impl CoerceType {
+    /// Parse a type name such as `number`
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "string" => Ok(CoerceType::String),
+            "number" => Ok(CoerceType::Number),
+            "integer" => Ok(CoerceType::Integer),
+            "float" => Ok(CoerceType::Float),
+            "bool" => Ok(CoerceType::Bool),
+            other => Err(format!(
+                "unknown coercion type '{}' (expected string, number, integer, float or bool)",
+                other
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CoerceType::String => "string",
+            CoerceType::Number => "number",
+            CoerceType::Integer => "integer",
+            CoerceType::Float => "float",
+            CoerceType::Bool => "bool",
+        }
+    }
+
+    /// Reinterpret a value as this type, or `None` if it cannot be
+    ///
+    /// Nulls are left as they are, and containers never coerce.
+    pub fn apply(self, value: &Value) -> Option<Value> {
+        if value.is_null() {
+            return Some(Value::Null);
+        }
+        match (self, value) {
+            (_, Value::Array(_) | Value::Object(_)) => None,
+            (CoerceType::String, Value::String(_)) => Some(value.clone()),
+            (CoerceType::String, _) => Some(Value::String(value.to_string())),
+            (CoerceType::Number, Value::Number(_)) => Some(value.clone()),
+            (CoerceType::Number, Value::String(s)) => parse_integer(s).or_else(|| parse_float(s)),
+            (CoerceType::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => {
+                Some(value.clone())
+            }
+            (CoerceType::Integer, Value::Number(n)) => n.as_f64().and_then(whole_number),
+            (CoerceType::Integer, Value::String(s)) => parse_integer(s),
+            (CoerceType::Float, Value::Number(n)) => {
+                n.as_f64().and_then(Number::from_f64).map(Value::Number)
+            }
+            (CoerceType::Float, Value::String(s)) => parse_float(s),
+            (CoerceType::Bool, Value::Bool(_)) => Some(value.clone()),
+            (CoerceType::Bool, Value::String(s)) => match s.as_str() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn parse_integer(s: &str) -> Option<Value> {
+    s.parse::<i64>()
+        .map(Value::from)
+        .or_else(|_| s.parse::<u64>().map(Value::from))
+        .ok()
+}
+
+fn parse_float(s: &str) -> Option<Value> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(Number::from_f64)
+        .map(Value::Number)
+}
+
+/// A float with no fractional part as an integer
+fn whole_number(f: f64) -> Option<Value> {
+    // 2^63, the first value past i64::MAX; i64 casts saturate
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    let i = f as i64;
+    (f < LIMIT && i as f64 == f).then(|| Value::from(i))
+}
+
+/// One `<glob>:<type>` rule, e.g. `config.port:number`
+///
+/// The glob is matched against the whole flattened key path of each scalar
+/// (including a JSON Lines record index), segment by segment:
+/// `*` and `?` match within a segment, and a `**` segment matches any number
+/// of segments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoercionRule {
+    pattern: String,
+    target: CoerceType,
+}
+
+impl CoercionRule {
+    /// Parse a `<glob>:<type>` rule; the type follows the last `:`
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let Some((pattern, target)) = rule.rsplit_once(':') else {
+            return Err("expected <glob>:<type>".to_string());
+        };
+        if pattern.is_empty() {
+            return Err("coercion glob is empty".to_string());
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            target: CoerceType::parse(target)?,
+        })
+    }
+
+    /// Parse a `--types` file: a JSON object mapping globs to type names,
+    /// e.g. `{"config.port": "integer"}`, as rules in file order
+    #[cfg(feature = "std")]
+    pub fn parse_types(json: &str) -> Result<Vec<Self>, String> {
+        let Value::Object(types) = serde_json::from_str(json).map_err(|e| e.to_string())? else {
+            return Err("expected an object mapping globs to types".to_string());
+        };
+        types
+            .into_iter()
+            .map(|(pattern, target)| {
+                if pattern.is_empty() {
+                    return Err("type glob is empty".to_string());
+                }
+                let Value::String(target) = target else {
+                    return Err(format!("type of '{}' is not a string", pattern));
+                };
+                Ok(Self {
+                    target: CoerceType::parse(&target)?,
+                    pattern,
+                })
+            })
+            .collect()
+    }
+
+    pub fn target(&self) -> CoerceType {
+        self.target
+    }
+
+    /// Whether the rule applies to a flattened key joined with `separator`
+    pub fn matches(&self, key: &str, separator: char) -> bool {
+        let pattern: Vec<&str> = self.pattern.split(separator).collect();
+        let key: Vec<&str> = key.split(separator).collect();
+        match_segments(&pattern, &key)
+    }
+}
+
+fn match_segments(pattern: &[&str], key: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => key.is_empty(),
+        Some((&"**", rest)) => (0..=key.len()).any(|skip| match_segments(rest, &key[skip..])),
+        Some((glob, rest)) => key.split_first().is_some_and(|(segment, key_rest)| {
+            match_glob(glob, segment) && match_segments(rest, key_rest)
+        }),
+    }
+}
+
+/// Match one segment against a glob with `*` and `?` wildcards
+fn match_glob(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut g, mut t) = (0, 0);
+    // Position of the last `*` and the text position it is currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some(&ch) if ch == '?' || ch == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` absorb one more character
+                Some((star_g, star_t)) => {
+                    g = star_g + 1;
+                    t = star_t + 1;
+                    star = Some((star_g, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&ch| ch == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse() {
+        let rule = CoercionRule::parse("config.port:number").unwrap();
+        assert_eq!(rule.target(), CoerceType::Number);
+        assert!(rule.matches("config.port", '.'));
+
+        // The type follows the last colon, so globs may contain colons
+        let rule = CoercionRule::parse("a:b:bool").unwrap();
+        assert!(rule.matches("a:b", '.'));
+
+        for bad in ["port", ":number", "port:decimal"] {
+            assert!(
+                CoercionRule::parse(bad).is_err(),
+                "{:?} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_types() {
+        let rules = CoercionRule::parse_types(r#"{"config.port": "integer", "**.enabled": "bool"}"#).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].target(), CoerceType::Integer);
+        assert!(rules[0].matches("config.port", '.'));
+        assert!(rules[1].matches("a.b.enabled", '.'));
+
+        for bad in [r#"["port"]"#, r#"{"port": 1}"#, r#"{"port": "decimal"}"#, r#"{"": "bool"}"#, "{"] {
+            assert!(
+                CoercionRule::parse_types(bad).is_err(),
+                "{:?} should be rejected",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let rule = |glob: &str| CoercionRule::parse(&format!("{}:string", glob)).unwrap();
+
+        assert!(rule("servers.*.port").matches("servers.0.port", '.'));
+        assert!(!rule("servers.*.port").matches("servers.0.tls.port", '.'));
+        assert!(rule("**.port").matches("servers.0.tls.port", '.'));
+        assert!(rule("**.port").matches("port", '.'));
+        assert!(rule("*_id").matches("user_id", '.'));
+        assert!(!rule("*_id").matches("user.id", '.'));
+        assert!(rule("v?").matches("v1", '.'));
+        assert!(!rule("v?").matches("v10", '.'));
+        assert!(rule("a/*").matches("a/b", '/'));
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(CoerceType::Number.apply(&json!("8080")), Some(json!(8080)));
+        assert_eq!(CoerceType::Number.apply(&json!("98.5")), Some(json!(98.5)));
+        assert_eq!(CoerceType::Number.apply(&json!("port")), None);
+        assert_eq!(CoerceType::Integer.apply(&json!(3.0)), Some(json!(3)));
+        assert_eq!(CoerceType::Integer.apply(&json!(3.5)), None);
+        assert_eq!(CoerceType::Float.apply(&json!(3)), Some(json!(3.0)));
+        assert_eq!(CoerceType::Float.apply(&json!("nan")), None);
+        assert_eq!(CoerceType::Bool.apply(&json!("true")), Some(json!(true)));
+        assert_eq!(CoerceType::Bool.apply(&json!(1)), None);
+        assert_eq!(CoerceType::String.apply(&json!(8080)), Some(json!("8080")));
+        assert_eq!(
+            CoerceType::String.apply(&json!(false)),
+            Some(json!("false"))
+        );
+        assert_eq!(CoerceType::Number.apply(&Value::Null), Some(Value::Null));
+        assert_eq!(CoerceType::String.apply(&json!({"a": 1})), None);
+    }
+}
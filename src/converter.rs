@@ -1,268 +1,3474 @@
-use crate::toon::ToonWriter;
-use anyhow::{Context, Result};
-use serde_json::Value;
+use crate::case::KeyCase;
+use crate::coerce::CoercionRule;
+#[cfg(feature = "std")]
+use crate::delimited::DelimitedWriter;
+use crate::error::{ConvertError, Result};
+#[cfg(feature = "std")]
+use crate::input;
+#[cfg(feature = "std")]
+use crate::interpolate::Interpolation;
+use crate::merge;
+use crate::pointer::{self, PointerWriter};
+use crate::prelude::*;
+#[cfg(feature = "std")]
+use crate::profile::{self, KeyTiming, Profile, ProfilingSink};
+use crate::relaxed;
+use crate::spans::{self, PathSegment};
+use crate::toon::{self, Cell, FloatStyle, OutputFormat, ToonOptions, ToonWriter};
+use alloc::borrow::Cow;
+use alloc::collections::{BTreeMap, BTreeSet};
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::{Map, Value};
+#[cfg(feature = "std")]
+use std::time::Instant;
 
+/// Receiver for the flattened key-value entries produced by the traversal
+///
+/// The built-in output formats are implementations of this trait; implement
+/// it to produce another format and pass the sink to
+/// [`Converter::write_entries`], as a concrete type or a `&mut dyn ValueSink`.
+pub trait ValueSink {
+    fn write_string(&mut self, key: &str, value: &str);
+    fn write_number(&mut self, key: &str, value: f64);
+
+    /// Receive a number that is integral in the source JSON
+    ///
+    /// Defaults to `write_number`; sinks that keep integers exact override it.
+    fn write_integer(&mut self, key: &str, value: i128) {
+        self.write_number(key, value as f64);
+    }
+
+    fn write_bool(&mut self, key: &str, value: bool);
+    fn write_null(&mut self, key: &str);
+    fn write_empty_array(&mut self, key: &str);
+    fn write_empty_object(&mut self, key: &str);
+
+    /// Separate groups of entries; sinks that don't care can ignore it
+    fn write_blank_line(&mut self) {}
+
+    /// Number of key segments of the entries that follow; ignored by default
+    fn set_depth(&mut self, _depth: usize) {}
+
+    /// Bytes of output produced so far, checked against `max_output_size` as
+    /// the output grows; `None` (the default) for sinks that produce none
+    fn output_len(&self) -> Option<usize> {
+        None
+    }
+
+    /// The entries that follow belong to this top-level key or array index;
+    /// ignored by default
+    fn start_top_level(&mut self, _key: &str) {}
+
+    /// The entries that follow, up to the matching `end_array`, are the items
+    /// of the non-empty array at `key`; ignored by default
+    ///
+    /// Arrays written through `write_table`, `write_scalar_array` or as one
+    /// inline entry are not bracketed.
+    fn begin_array(&mut self, _key: &str) {}
+
+    fn end_array(&mut self, _key: &str) {}
+
+    /// Like `begin_array`, for the fields of a non-empty object
+    fn begin_object(&mut self, _key: &str) {}
+
+    fn end_object(&mut self, _key: &str) {}
+
+    /// Receive an array of uniform flat objects as rows of cells under `columns`
+    ///
+    /// Every row has one cell per column. Returns whether the table was
+    /// written; by default it is not, and the array is flattened into
+    /// ordinary `key.index.column` entries instead.
+    fn write_table(&mut self, _key: &str, _columns: &[&str], _rows: &[Vec<Cell>]) -> bool {
+        false
+    }
+
+    /// Receive an array of only scalars as one list of cells
+    ///
+    /// Returns whether the list was written; by default it is not, and the
+    /// array is flattened into ordinary `key.index` entries instead.
+    fn write_scalar_array(&mut self, _key: &str, _items: &[Cell]) -> bool {
+        false
+    }
+}
+
+impl ValueSink for ToonWriter {
+    fn write_string(&mut self, key: &str, value: &str) {
+        ToonWriter::write_string(self, key, value);
+    }
+
+    fn write_number(&mut self, key: &str, value: f64) {
+        ToonWriter::write_number(self, key, value);
+    }
+
+    fn write_integer(&mut self, key: &str, value: i128) {
+        ToonWriter::write_integer(self, key, value);
+    }
+
+    fn write_bool(&mut self, key: &str, value: bool) {
+        ToonWriter::write_bool(self, key, value);
+    }
+
+    fn write_null(&mut self, key: &str) {
+        ToonWriter::write_null(self, key);
+    }
+
+    fn write_empty_array(&mut self, key: &str) {
+        ToonWriter::write_empty_array(self, key);
+    }
+
+    fn write_empty_object(&mut self, key: &str) {
+        ToonWriter::write_empty_object(self, key);
+    }
+
+    fn write_blank_line(&mut self) {
+        ToonWriter::write_blank_line(self);
+    }
+
+    fn set_depth(&mut self, depth: usize) {
+        ToonWriter::set_depth(self, depth);
+    }
+
+    fn output_len(&self) -> Option<usize> {
+        Some(self.len())
+    }
+
+    fn write_table(&mut self, key: &str, columns: &[&str], rows: &[Vec<Cell>]) -> bool {
+        self.write_table_header(key, columns, rows.len());
+        for row in rows {
+            self.write_table_row(row);
+        }
+        true
+    }
+
+    fn write_scalar_array(&mut self, key: &str, items: &[Cell]) -> bool {
+        ToonWriter::write_scalar_array(self, key, items);
+        true
+    }
+}
+
+/// Sink that only counts entries without formatting them
+#[derive(Default)]
+struct EntryCounter {
+    count: usize,
+}
+
+impl ValueSink for EntryCounter {
+    fn write_string(&mut self, _key: &str, _value: &str) {
+        self.count += 1;
+    }
+
+    fn write_number(&mut self, _key: &str, _value: f64) {
+        self.count += 1;
+    }
+
+    fn write_bool(&mut self, _key: &str, _value: bool) {
+        self.count += 1;
+    }
+
+    fn write_null(&mut self, _key: &str) {
+        self.count += 1;
+    }
+
+    fn write_empty_array(&mut self, _key: &str) {
+        self.count += 1;
+    }
+
+    fn write_empty_object(&mut self, _key: &str) {
+        self.count += 1;
+    }
+}
+
+/// Syntax of the input document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    #[default]
+    Json,
+    #[cfg(feature = "std")]
+    Toml,
+    #[cfg(feature = "std")]
+    Yaml,
+}
+
+/// Default for `ConvertOptions::max_recursion`
+pub const DEFAULT_MAX_RECURSION: usize = 1000;
+
+/// Largest `ConvertOptions::max_recursion` the CLI accepts
+pub const MAX_RECURSION_LIMIT: usize = 10_000;
+
+/// Stack a conversion may use per level of nesting, with room to spare, in
+/// an unoptimized build; parsing and traversal both recurse
+pub const STACK_PER_LEVEL: usize = 16 << 10;
+
+/// Last key segment of the markers written for `ConvertOptions::presence_keys`
+pub const PRESENCE_SEGMENT: &str = "__present";
+
+/// Last key segment of the marker written after an array cut short by `ConvertOptions::sample`
+pub const TRUNCATED_SEGMENT: &str = "__truncated";
+
+/// Options controlling how the input is parsed and the JSON tree is traversed
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// Syntax of the input; TOML and YAML are parsed into the same JSON tree
+    pub input_format: InputFormat,
+    /// Treat empty or whitespace-only input as an empty document instead of an error
+    pub allow_empty: bool,
+    /// When JSON fails to parse, retry it as relaxed JSON (comments, trailing
+    /// commas, single-quoted strings; see [`relaxed::to_strict`]) before failing
+    pub try_relaxed: bool,
+    /// Insert a blank line whenever the top-level key changes
+    pub group_by_toplevel: bool,
+    /// Omit null values, including nulls inside arrays (other items keep their indices)
+    pub skip_nulls: bool,
+    /// Omit empty string values
+    pub skip_empty_strings: bool,
+    /// Omit empty arrays instead of writing the `[]` marker
+    pub skip_empty_arrays: bool,
+    /// Omit empty objects instead of writing the `{}` marker
+    pub skip_empty_objects: bool,
+    /// Transform object keys (never array indices) to this case
+    pub key_case: Option<KeyCase>,
+    /// Containers at levels below this one (top-level keys are level 0) are
+    /// written as one JSON string entry holding their scalar members, and only
+    /// their nested containers are flattened; 0 flattens everything
+    pub flatten_from: usize,
+    /// Reinterpret values at matching key paths as another type; the first matching rule wins
+    pub coerce: Vec<CoercionRule>,
+    /// Expected types of values at matching key paths, e.g. from a `--types` file:
+    /// values are coerced like `coerce`, failing with `WrongType` when they
+    /// cannot be (containers included); `coerce` rules take precedence
+    pub types: Vec<CoercionRule>,
+    /// Write arrays of more than this many items as one entry holding the
+    /// array as compact JSON, e.g. `ids="[1,2,3]"`, instead of an entry per item
+    pub array_threshold: Option<usize>,
+    /// Keep only the first N items of longer arrays, followed by a
+    /// `<key>.__truncated=true` entry, for a bounded look at large documents
+    pub sample: Option<usize>,
+    /// Also write `<key>.__present=true` before the entries of every object
+    /// field, even one that is null or skipped, so readers can tell which keys
+    /// existed; only for objects flattened into entries, not table rows or
+    /// objects written as JSON
+    pub presence_keys: bool,
+    /// Deepest container nesting accepted before failing with `TooDeep`,
+    /// checked before parsing so deep input cannot exhaust the stack; a
+    /// conversion needs up to `max_recursion * STACK_PER_LEVEL` bytes of stack
+    pub max_recursion: usize,
+    /// Key for entries of a top-level scalar or empty container, which otherwise have an empty key
+    pub root_key: String,
+    /// Sort the fields of objects whose keys are all integers numerically
+    /// (`2` before `10`) instead of keeping source order
+    pub numeric_key_order: bool,
+    /// Key segment a top-level array is nested under, giving `items.0`, `items.1`, ...
+    /// instead of `0`, `1`, ...; empty leaves its indices at the top level
+    pub array_root_key: String,
+    /// Leading key segments dropped from every entry key that starts with them,
+    /// e.g. `config` turns `config.port` into `port`; written with the output
+    /// separator (`/` between segments for JSON Pointers, without the leading
+    /// `/`), and empty to keep keys whole
+    pub strip_prefix: String,
+    /// JSON Merge Patches (RFC 7386) applied in order to each parsed document,
+    /// before `expand_env`; a `null` member deletes the key
+    pub patches: Vec<Value>,
+    /// Flatten string values holding a JSON object or array (e.g. `"{\"a\": 1}"`)
+    /// as if the JSON had been embedded directly, repeatedly for double-encoded values
+    pub parse_embedded_json: bool,
+    /// Fail with `OutputTooLarge` rather than produce more than this many bytes
+    pub max_output_size: Option<usize>,
+    /// Note the line of the JSON source each entry's value starts on, e.g.
+    /// `port=8080  # L42` (TOON output of JSON input only)
+    pub source_lines: bool,
+    /// Write each string value that repeats across entries once, in a table of
+    /// `&v1="..."` references at the top, and the entries as `key=&v1`
+    /// (TOON output only; table cells and folded arrays are left as they are)
+    pub dedup_values: bool,
+    /// Begin the output with a `#root=array` line naming the type of a
+    /// top-level value that is not an object (TOON output only), so `0=1`
+    /// from `[1]` cannot be read back as `{"0": 1}`
+    pub emit_root_type: bool,
+    /// Substitute `${VAR}` placeholders from the environment after parsing
+    #[cfg(feature = "std")]
+    pub expand_env: Option<Interpolation>,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            input_format: InputFormat::default(),
+            allow_empty: false,
+            try_relaxed: false,
+            group_by_toplevel: false,
+            skip_nulls: false,
+            skip_empty_strings: false,
+            skip_empty_arrays: false,
+            skip_empty_objects: false,
+            key_case: None,
+            flatten_from: 0,
+            coerce: Vec::new(),
+            types: Vec::new(),
+            array_threshold: None,
+            sample: None,
+            presence_keys: false,
+            max_recursion: DEFAULT_MAX_RECURSION,
+            root_key: String::new(),
+            numeric_key_order: false,
+            array_root_key: String::new(),
+            strip_prefix: String::new(),
+            patches: Vec::new(),
+            parse_embedded_json: false,
+            max_output_size: None,
+            source_lines: false,
+            dedup_values: false,
+            emit_root_type: false,
+            #[cfg(feature = "std")]
+            expand_env: None,
+        }
+    }
+}
+
+/// JSON text a document was parsed from, for options that look back at it
+#[derive(Clone, Copy)]
+struct Source<'a> {
+    text: &'a str,
+    /// Whether the text begins the input, so its line numbers are the input's
+    starts_input: bool,
+}
+
+impl<'a> Source<'a> {
+    /// The whole input
+    fn input(text: &'a str) -> Self {
+        Self {
+            text,
+            starts_input: true,
+        }
+    }
+
+    /// A record or document further into the input, whose lines cannot be numbered
+    fn part(text: &'a str) -> Self {
+        Self {
+            text,
+            starts_input: false,
+        }
+    }
+}
+
+/// Summary of a conversion for machine-readable reports
+///
+/// `Converter::stats` fills in the entry counts; the caller sets
+/// `output_bytes` and `duration_ms` once the output has been produced.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConversionStats {
+    pub input_bytes: usize,
+    pub output_bytes: usize,
+    /// Number of key-value entries
+    pub key_count: usize,
+    /// Largest number of key segments of any entry
+    pub max_depth: usize,
+    pub duration_ms: f64,
+    /// Entries per value type; `array` and `object` count empty markers
+    pub types: TypeCounts,
+}
+
+/// Entry counts by the JSON type of the value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TypeCounts {
+    pub string: usize,
+    pub number: usize,
+    pub bool: usize,
+    pub null: usize,
+    pub array: usize,
+    pub object: usize,
+}
+
+impl ConversionStats {
+    /// Fold in the stats of another document, e.g. the next JSON Lines record
+    pub fn add(&mut self, other: &ConversionStats) {
+        self.input_bytes += other.input_bytes;
+        self.output_bytes += other.output_bytes;
+        self.key_count += other.key_count;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.duration_ms += other.duration_ms;
+        let types = &mut self.types;
+        types.string += other.types.string;
+        types.number += other.types.number;
+        types.bool += other.types.bool;
+        types.null += other.types.null;
+        types.array += other.types.array;
+        types.object += other.types.object;
+    }
+}
+
+/// Sink that tallies entries by type and depth
+#[derive(Default)]
+struct StatsCollector {
+    stats: ConversionStats,
+    depth: usize,
+}
+
+impl StatsCollector {
+    fn record(&mut self) -> &mut TypeCounts {
+        self.stats.key_count += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.depth);
+        &mut self.stats.types
+    }
+}
+
+impl ValueSink for StatsCollector {
+    fn write_string(&mut self, _key: &str, _value: &str) {
+        self.record().string += 1;
+    }
+
+    fn write_number(&mut self, _key: &str, _value: f64) {
+        self.record().number += 1;
+    }
+
+    fn write_bool(&mut self, _key: &str, _value: bool) {
+        self.record().bool += 1;
+    }
+
+    fn write_null(&mut self, _key: &str) {
+        self.record().null += 1;
+    }
+
+    fn write_empty_array(&mut self, _key: &str) {
+        self.record().array += 1;
+    }
+
+    fn write_empty_object(&mut self, _key: &str) {
+        self.record().object += 1;
+    }
+
+    fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+}
+
+/// Sink that counts each string entry value, in order of first occurrence
+///
+/// Tables and folded scalar arrays are accepted without counting their
+/// cells, since only whole entries can be written as references.
+#[derive(Default)]
+struct StringCounter {
+    /// Index of first occurrence and number of occurrences of each value
+    counts: BTreeMap<String, (usize, usize)>,
+}
+
+impl ValueSink for StringCounter {
+    fn write_string(&mut self, _key: &str, value: &str) {
+        let next = self.counts.len();
+        match self.counts.get_mut(value) {
+            Some((_, count)) => *count += 1,
+            None => {
+                self.counts.insert(value.to_string(), (next, 1));
+            }
+        }
+    }
+
+    fn write_number(&mut self, _key: &str, _value: f64) {}
+
+    fn write_bool(&mut self, _key: &str, _value: bool) {}
+
+    fn write_null(&mut self, _key: &str) {}
+
+    fn write_empty_array(&mut self, _key: &str) {}
+
+    fn write_empty_object(&mut self, _key: &str) {}
+
+    fn write_table(&mut self, _key: &str, _columns: &[&str], _rows: &[Vec<Cell>]) -> bool {
+        true
+    }
+
+    fn write_scalar_array(&mut self, _key: &str, _items: &[Cell]) -> bool {
+        true
+    }
+}
+
+/// Sink forwarding to another while remembering the first key written twice
+///
+/// Distinct source keys can flatten to the same key, e.g. `{"a": {"b": 1}}`
+/// and `{"a.b": 2}`, or `A` and `a` with `key_case`; the sink methods cannot
+/// fail, so `convert_root` reports the clash once the traversal returns.
+struct UniqueKeys<'a, S: ?Sized> {
+    inner: &'a mut S,
+    seen: BTreeSet<String>,
+    duplicate: Option<String>,
+}
+
+impl<'a, S: ValueSink + ?Sized> UniqueKeys<'a, S> {
+    fn new(inner: &'a mut S) -> Self {
+        Self {
+            inner,
+            seen: BTreeSet::new(),
+            duplicate: None,
+        }
+    }
+
+    fn record(&mut self, key: &str) {
+        if self.duplicate.is_none() && !self.seen.insert(key.to_string()) {
+            self.duplicate = Some(key.to_string());
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self.duplicate {
+            Some(key) => Err(ConvertError::DuplicateKey { key }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<S: ValueSink + ?Sized> ValueSink for UniqueKeys<'_, S> {
+    fn write_string(&mut self, key: &str, value: &str) {
+        self.record(key);
+        self.inner.write_string(key, value);
+    }
+
+    fn write_number(&mut self, key: &str, value: f64) {
+        self.record(key);
+        self.inner.write_number(key, value);
+    }
+
+    fn write_integer(&mut self, key: &str, value: i128) {
+        self.record(key);
+        self.inner.write_integer(key, value);
+    }
+
+    fn write_bool(&mut self, key: &str, value: bool) {
+        self.record(key);
+        self.inner.write_bool(key, value);
+    }
+
+    fn write_null(&mut self, key: &str) {
+        self.record(key);
+        self.inner.write_null(key);
+    }
+
+    fn write_empty_array(&mut self, key: &str) {
+        self.record(key);
+        self.inner.write_empty_array(key);
+    }
+
+    fn write_empty_object(&mut self, key: &str) {
+        self.record(key);
+        self.inner.write_empty_object(key);
+    }
+
+    fn write_blank_line(&mut self) {
+        self.inner.write_blank_line();
+    }
+
+    fn set_depth(&mut self, depth: usize) {
+        self.inner.set_depth(depth);
+    }
+
+    fn output_len(&self) -> Option<usize> {
+        self.inner.output_len()
+    }
+
+    fn start_top_level(&mut self, key: &str) {
+        self.inner.start_top_level(key);
+    }
+
+    fn begin_array(&mut self, key: &str) {
+        self.inner.begin_array(key);
+    }
+
+    fn end_array(&mut self, key: &str) {
+        self.inner.end_array(key);
+    }
+
+    fn begin_object(&mut self, key: &str) {
+        self.inner.begin_object(key);
+    }
+
+    fn end_object(&mut self, key: &str) {
+        self.inner.end_object(key);
+    }
+
+    fn write_table(&mut self, key: &str, columns: &[&str], rows: &[Vec<Cell>]) -> bool {
+        let written = self.inner.write_table(key, columns, rows);
+        if written {
+            self.record(key);
+        }
+        written
+    }
+
+    fn write_scalar_array(&mut self, key: &str, items: &[Cell]) -> bool {
+        let written = self.inner.write_scalar_array(key, items);
+        if written {
+            self.record(key);
+        }
+        written
+    }
+}
+
+/// Sink that records the key paths of all entries
+#[derive(Default)]
+struct KeyCollector {
+    keys: BTreeSet<String>,
+}
+
+impl ValueSink for KeyCollector {
+    fn write_string(&mut self, key: &str, _value: &str) {
+        self.keys.insert(key.to_string());
+    }
+
+    fn write_number(&mut self, key: &str, _value: f64) {
+        self.keys.insert(key.to_string());
+    }
+
+    fn write_bool(&mut self, key: &str, _value: bool) {
+        self.keys.insert(key.to_string());
+    }
+
+    fn write_null(&mut self, key: &str) {
+        self.keys.insert(key.to_string());
+    }
+
+    fn write_empty_array(&mut self, key: &str) {
+        self.keys.insert(key.to_string());
+    }
+
+    fn write_empty_object(&mut self, key: &str) {
+        self.keys.insert(key.to_string());
+    }
+}
+
+/// Leaf values keyed by flattened path, in output order, as returned by [`Converter::flatten`]
+#[cfg(feature = "std")]
+pub type FlatMap = IndexMap<String, Value>;
+
+/// Leaf values keyed by flattened path, in output order, as returned by [`Converter::flatten`]
+///
+/// Without `std` there is no randomly seeded hasher, so keys are hashed with
+/// fixed-key SipHash.
+#[cfg(not(feature = "std"))]
+#[allow(deprecated)]
+pub type FlatMap = IndexMap<String, Value, core::hash::BuildHasherDefault<core::hash::SipHasher>>;
+
+/// Sink that records each entry as a leaf `Value` keyed by its path
+struct LeafCollector {
+    leaves: FlatMap,
+    /// Options of the output being mirrored, for which floats it writes as integers
+    format: ToonOptions,
+}
+
+impl ValueSink for LeafCollector {
+    fn write_string(&mut self, key: &str, value: &str) {
+        self.leaves.insert(key.to_string(), Value::from(value));
+    }
+
+    fn write_number(&mut self, key: &str, value: f64) {
+        // Mirror ToonWriter: whole numbers are integers
+        if toon::collapses_to_integer(value, &self.format) {
+            self.write_integer(key, value as i128);
+        } else {
+            self.leaves.insert(key.to_string(), Value::from(value));
+        }
+    }
+
+    fn write_integer(&mut self, key: &str, value: i128) {
+        let number = match i64::try_from(value) {
+            Ok(n) => Value::from(n),
+            Err(_) => u64::try_from(value).map_or(Value::from(value as f64), Value::from),
+        };
+        self.leaves.insert(key.to_string(), number);
+    }
+
+    fn write_bool(&mut self, key: &str, value: bool) {
+        self.leaves.insert(key.to_string(), Value::Bool(value));
+    }
+
+    fn write_null(&mut self, key: &str) {
+        self.leaves.insert(key.to_string(), Value::Null);
+    }
+
+    fn write_empty_array(&mut self, key: &str) {
+        self.leaves
+            .insert(key.to_string(), Value::Array(Vec::new()));
+    }
+
+    fn write_empty_object(&mut self, key: &str) {
+        self.leaves
+            .insert(key.to_string(), Value::Object(Default::default()));
+    }
+}
+
+#[derive(Default)]
 pub struct Converter {
-    verbose: bool,
+    options: ConvertOptions,
+    format: ToonOptions,
 }
 
-impl Converter {
-    pub fn new(verbose: bool) -> Self {
-        Self { verbose }
+impl Converter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the given traversal options
+    pub fn with_options(mut self, options: ConvertOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Use the given TOON formatting options for the output
+    pub fn with_format(mut self, format: ToonOptions) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Convert JSON string to TOON format
+    pub fn convert(&self, json: &str) -> Result<String> {
+        #[cfg(feature = "std")]
+        if log::log_enabled!(log::Level::Info) {
+            return self.convert_logged(json);
+        }
+
+        match self.parse(json)? {
+            Some(value) => self.write_document(&value, "", Some(Source::input(json))),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Flatten an already parsed document into `out` in the configured output format
+    ///
+    /// Skips parsing, so `patches` and `expand_env`, which apply to parsed
+    /// input, are not applied to `value`.
+    pub fn write_value(&self, value: &Value, out: &mut impl core::fmt::Write) -> Result<()> {
+        out.write_str(&self.write_document(value, "", None)?)?;
+        Ok(())
+    }
+
+    /// Feed the entries of an already parsed document to a custom sink
+    ///
+    /// The sink sees the same entries, in the same order, as the built-in
+    /// writers; like [`Converter::write_value`] no parse-time option applies.
+    pub fn write_entries<S: ValueSink + ?Sized>(&self, value: &Value, sink: &mut S) -> Result<()> {
+        self.convert_root(sink, "", value)
+    }
+
+    /// `convert`, logging progress and timings
+    #[cfg(feature = "std")]
+    fn convert_logged(&self, json: &str) -> Result<String> {
+        log::debug!("Parsing JSON...");
+        let parse_start = Instant::now();
+        let Some(value) = self.parse(json)? else {
+            return Ok(String::new());
+        };
+        log::info!("JSON parsed successfully ({:.2?})", parse_start.elapsed());
+
+        log::debug!("Converting to TOON format...");
+        let convert_start = Instant::now();
+        let toon = self.write_document(&value, "", Some(Source::input(json)))?;
+        log::info!("Conversion complete ({:.2?})", convert_start.elapsed());
+
+        Ok(toon)
+    }
+
+    /// Convert a single JSON Lines record, prefixing its keys with the record index
+    pub fn convert_record(&self, json: &str, index: usize) -> Result<String> {
+        match self.parse(json)? {
+            Some(value) => self.write_document(
+                &value,
+                &self.child_key("", &index.to_string()),
+                Some(Source::part(json)),
+            ),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Convert a document with its keys nested under one more segment,
+    /// e.g. `app` giving `app.port=8080` instead of `port=8080`
+    pub fn convert_prefixed(&self, input: &str, segment: &str) -> Result<String> {
+        match self.parse(input)? {
+            Some(value) => self.write_document(
+                &value,
+                &self.child_key("", segment),
+                Some(Source::input(input)),
+            ),
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Convert JSON Lines input, keying each record by its index (`0.name`, `1.name`, ...)
+    pub fn convert_jsonl(&self, jsonl: &str) -> Result<String> {
+        let mut toon = String::new();
+        for (index, (line_no, record)) in jsonl_records(jsonl).enumerate() {
+            let converted =
+                self.convert_record(record, index)
+                    .map_err(|e| ConvertError::Record {
+                        line: line_no,
+                        source: Box::new(e),
+                    })?;
+            toon.push_str(&converted);
+            self.check_output_len(toon.len())?;
+        }
+        Ok(toon)
+    }
+
+    /// Convert JSON documents written back to back, e.g. `{"a":1}{"a":2}`,
+    /// keying each by its index like JSON Lines records; returns the output
+    /// and the number of documents found
+    ///
+    /// Documents may be separated by any whitespace, or by none at all.
+    pub fn convert_multi_doc(&self, input: &str) -> Result<(String, usize)> {
+        if input.trim().is_empty() {
+            if self.options.allow_empty {
+                return Ok((String::new(), 0));
+            }
+            return Err(ConvertError::Empty);
+        }
+        let documents = self.parse_json_documents(input)?;
+        let count = documents.len();
+        let mut toon = String::new();
+        for (index, (value, text)) in documents.into_iter().enumerate() {
+            let converted = self
+                .prepare(value)
+                .and_then(|value| {
+                    self.write_document(
+                        &value,
+                        &self.child_key("", &index.to_string()),
+                        Some(Source::part(text)),
+                    )
+                })
+                .map_err(|e| ConvertError::Document {
+                    index,
+                    source: Box::new(e),
+                })?;
+            toon.push_str(&converted);
+            self.check_output_len(toon.len())?;
+        }
+        Ok((toon, count))
+    }
+
+    /// Check that the input parses, without converting it
+    pub fn validate(&self, input: &str) -> Result<()> {
+        self.parse(input).map(|_| ())
+    }
+
+    /// Check that every JSON Lines record parses, reporting the first bad line
+    pub fn validate_jsonl(&self, jsonl: &str) -> Result<()> {
+        for (line_no, record) in jsonl_records(jsonl) {
+            self.validate(record).map_err(|e| ConvertError::Record {
+                line: line_no,
+                source: Box::new(e),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Parse the input into a JSON tree according to the input format
+    ///
+    /// Empty or whitespace-only input is an error unless `allow_empty` is
+    /// set, in which case it yields `None` and converts to empty output.
+    fn parse(&self, input: &str) -> Result<Option<Value>> {
+        if input.trim().is_empty() {
+            if self.options.allow_empty {
+                return Ok(None);
+            }
+            return Err(ConvertError::Empty);
+        }
+
+        let value = match self.options.input_format {
+            InputFormat::Json => self.parse_json(input),
+            #[cfg(feature = "std")]
+            InputFormat::Toml => input::parse_toml(input),
+            #[cfg(feature = "std")]
+            InputFormat::Yaml => input::parse_yaml(input),
+        }?;
+        self.prepare(value).map(Some)
+    }
+
+    /// Apply the `patches` and `expand_env` to a parsed document
+    fn prepare(&self, mut value: Value) -> Result<Value> {
+        for patch in &self.options.patches {
+            merge::merge_patch(&mut value, patch);
+        }
+        #[cfg(feature = "std")]
+        let value = self.expand_env(value)?;
+        Ok(value)
+    }
+
+    /// Apply `expand_env`, looking variables up in the process environment
+    #[cfg(feature = "std")]
+    fn expand_env(&self, mut value: Value) -> Result<Value> {
+        if let Some(interpolation) = &self.options.expand_env {
+            interpolation.apply(&mut value, self.format.separator, &|name| {
+                std::env::var(name).ok()
+            })?;
+        }
+        Ok(value)
+    }
+
+    /// Parse JSON, falling back to relaxed syntax for `try_relaxed`
+    ///
+    /// When the relaxed retry fails too, the strict parser's error is
+    /// returned, since its position refers to the input as written.
+    fn parse_json(&self, input: &str) -> Result<Value> {
+        match self.parse_strict_json(input) {
+            Err(err @ ConvertError::Parse { .. }) if self.options.try_relaxed => {
+                let value = self
+                    .parse_strict_json(&relaxed::to_strict(input))
+                    .map_err(|_| err)?;
+                log::info!("Input is not strict JSON, parsed it with relaxed syntax");
+                Ok(value)
+            }
+            result => result,
+        }
+    }
+
+    /// Parse JSON, limited by `max_recursion` rather than serde_json's fixed 128 levels
+    fn parse_strict_json(&self, input: &str) -> Result<Value> {
+        let limit = self.options.max_recursion;
+        if nesting_depth(input) > limit {
+            return Err(ConvertError::TooDeep { limit });
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_str(input);
+        deserializer.disable_recursion_limit();
+        let value = serde::Deserialize::deserialize(&mut deserializer)
+            .and_then(|value| deserializer.end().map(|()| value))
+            .map_err(|e| ConvertError::parse("JSON", e))?;
+        Ok(value)
+    }
+
+    /// Parse every top-level JSON value of the input, in order, each with the
+    /// text it was parsed from
+    fn parse_json_documents<'a>(&self, input: &'a str) -> Result<Vec<(Value, &'a str)>> {
+        let limit = self.options.max_recursion;
+        if nesting_depth(input) > limit {
+            return Err(ConvertError::TooDeep { limit });
+        }
+
+        let mut deserializer = serde_json::Deserializer::from_str(input);
+        deserializer.disable_recursion_limit();
+        let mut documents = deserializer.into_iter();
+        let (mut parsed, mut start) = (Vec::new(), 0);
+        while let Some(value) = documents.next() {
+            let value = value.map_err(|e| ConvertError::parse("JSON", e))?;
+            let end = documents.byte_offset();
+            parsed.push((value, &input[start..end]));
+            start = end;
+        }
+        Ok(parsed)
+    }
+
+    /// Parse the input and feed its entries to the sink
+    fn walk<S: ValueSink + ?Sized>(&self, sink: &mut S, input: &str) -> Result<()> {
+        match self.parse(input)? {
+            Some(value) => self.convert_root(sink, "", &value),
+            None => Ok(()),
+        }
+    }
+
+    /// Flatten a parsed value into the output format under the given key prefix
+    ///
+    /// `source` is the text the value was parsed from, if known, for `source_lines`
+    /// and `FloatStyle::Preserve`.
+    fn write_document(
+        &self,
+        value: &Value,
+        prefix: &str,
+        source: Option<Source>,
+    ) -> Result<String> {
+        let output = self.render_document(value, prefix, source)?;
+        self.check_output_len(output.len())?;
+        Ok(output)
+    }
+
+    fn render_document(
+        &self,
+        value: &Value,
+        prefix: &str,
+        source: Option<Source>,
+    ) -> Result<String> {
+        #[cfg(feature = "std")]
+        if let Some(delimiter) = self.delimiter() {
+            let mut writer = DelimitedWriter::new(delimiter, self.format.clone());
+            self.convert_root(&mut writer, prefix, value)?;
+            return Ok(writer.finish());
+        }
+
+        if self.format.output_format == OutputFormat::JsonPointer {
+            let mut writer = PointerWriter::new();
+            self.convert_root(&mut writer, prefix, value)?;
+            return Ok(writer.finish());
+        }
+
+        let mut writer = self.toon_writer(value, prefix, source)?;
+        self.convert_root(&mut writer, prefix, value)?;
+        Ok(writer.finish())
+    }
+
+    /// A TOON writer sized for the document, with its reference table written when deduplicating
+    /// and the source lines or number literals of a JSON `source` set when it uses them
+    fn toon_writer(
+        &self,
+        value: &Value,
+        prefix: &str,
+        source: Option<Source>,
+    ) -> Result<ToonWriter> {
+        let capacity = self.estimate_value_size(value, self.root_prefix_len(value, prefix.len()));
+        let mut writer = ToonWriter::with_capacity(self.format.clone(), capacity);
+        if self.options.emit_root_type
+            && prefix.is_empty()
+            && let Some(type_name) = root_type_name(value)
+        {
+            writer.write_root_type(type_name);
+        }
+        if self.options.dedup_values {
+            writer.write_references(self.repeated_strings(value, prefix)?);
+        }
+        if self.options.source_lines
+            && self.options.input_format == InputFormat::Json
+            && let Some(Source {
+                text,
+                starts_input: true,
+            }) = source
+        {
+            writer.set_source_lines(self.source_keyed(value, prefix, spans::value_lines(text)));
+        }
+        if self.format.float_style == FloatStyle::Preserve
+            && self.options.input_format == InputFormat::Json
+            && let Some(Source { text, .. }) = source
+        {
+            // Relaxed input is scanned as the strict text it was parsed from
+            let strict = self.options.try_relaxed.then(|| relaxed::to_strict(text));
+            let literals = spans::number_literals(strict.as_deref().unwrap_or(text))
+                .into_iter()
+                .map(|(path, text)| (path, text.to_string()));
+            writer.set_number_literals(self.source_keyed(value, prefix, literals));
+        }
+        Ok(writer)
+    }
+
+    /// Key each item found in the JSON source by the entry key its value would be written under
+    ///
+    /// Keys are built like `convert_root` builds them, so entries added by
+    /// `patches` have no item and keys renamed by `expand_env` lose theirs.
+    fn source_keyed<T>(
+        &self,
+        value: &Value,
+        prefix: &str,
+        items: impl IntoIterator<Item = (Vec<PathSegment>, T)>,
+    ) -> BTreeMap<String, T> {
+        let array_root_key = &self.options.array_root_key;
+        let root = if value.is_array() && !array_root_key.is_empty() {
+            self.child_key(prefix, array_root_key)
+        } else {
+            prefix.to_string()
+        };
+        items
+            .into_iter()
+            .map(|(path, item)| {
+                let key = path
+                    .iter()
+                    .fold(root.clone(), |key, segment| match segment {
+                        PathSegment::Key(field) => self.child_key(&key, &self.key_segment(field)),
+                        PathSegment::Index(i) => self.index_key(&key, *i),
+                    });
+                (self.entry_key(&key).to_string(), item)
+            })
+            .collect()
+    }
+
+    /// String entry values worth writing as references, in order of first occurrence
+    ///
+    /// A value qualifies if it is written more than once and is longer than
+    /// the `&vN` token that would replace it.
+    fn repeated_strings(&self, value: &Value, prefix: &str) -> Result<Vec<String>> {
+        let mut counter = StringCounter::default();
+        self.convert_root(&mut counter, prefix, value)?;
+        let mut repeated: Vec<_> = counter
+            .counts
+            .into_iter()
+            .filter(|(_, (_, count))| *count > 1)
+            .map(|(value, (first, _))| (first, value))
+            .collect();
+        repeated.sort_unstable();
+
+        let mut references = Vec::new();
+        for (_, value) in repeated {
+            let token_len = toon::REFERENCE_PREFIX.len() + decimal_len(references.len() + 1);
+            if value.len() > token_len {
+                references.push(value);
+            }
+        }
+        Ok(references)
+    }
+
+    /// Field delimiter of the CSV/TSV output formats
+    #[cfg(feature = "std")]
+    fn delimiter(&self) -> Option<u8> {
+        match self.format.output_format {
+            OutputFormat::Toon | OutputFormat::JsonPointer => None,
+            OutputFormat::Csv => Some(b','),
+            OutputFormat::Tsv => Some(b'\t'),
+        }
+    }
+
+    /// `convert`, timing the parse and traversal and each top-level key
+    #[cfg(feature = "std")]
+    pub fn convert_profiled(&self, json: &str) -> Result<(String, Profile)> {
+        let parse_start = Instant::now();
+        let parsed = self.parse(json)?;
+        let mut profile = Profile {
+            parse_ms: profile::millis(parse_start.elapsed()),
+            ..Default::default()
+        };
+        let Some(value) = parsed else {
+            return Ok((String::new(), profile));
+        };
+
+        let traverse_start = Instant::now();
+        let toon = if let Some(delimiter) = self.delimiter() {
+            let mut writer = DelimitedWriter::new(delimiter, self.format.clone());
+            profile.top_level_keys = self.traverse_profiled(&mut writer, &value)?;
+            writer.finish()
+        } else if self.format.output_format == OutputFormat::JsonPointer {
+            let mut writer = PointerWriter::new();
+            profile.top_level_keys = self.traverse_profiled(&mut writer, &value)?;
+            writer.finish()
+        } else {
+            let mut writer = self.toon_writer(&value, "", Some(Source::input(json)))?;
+            profile.top_level_keys = self.traverse_profiled(&mut writer, &value)?;
+            writer.finish()
+        };
+        profile.traverse_ms = profile::millis(traverse_start.elapsed());
+        self.check_output_len(toon.len())?;
+        Ok((toon, profile))
+    }
+
+    #[cfg(feature = "std")]
+    fn traverse_profiled<S: ValueSink>(
+        &self,
+        writer: &mut S,
+        value: &Value,
+    ) -> Result<Vec<KeyTiming>> {
+        let mut sink = ProfilingSink::new(writer);
+        self.convert_root(&mut sink, "", value)?;
+        Ok(sink.finish())
+    }
+
+    /// Count the key-value pairs the TOON output would contain
+    pub fn count_entries(&self, json: &str) -> Result<usize> {
+        let mut counter = EntryCounter::default();
+        self.walk(&mut counter, json)?;
+        Ok(counter.count)
+    }
+
+    /// Collect the sorted, de-duplicated set of flattened key paths
+    pub fn collect_keys(&self, json: &str) -> Result<BTreeSet<String>> {
+        let mut collector = KeyCollector::default();
+        self.walk(&mut collector, json)?;
+        Ok(collector.keys)
+    }
+
+    /// Count entries by type and find the deepest key for a summary report
+    pub fn stats(&self, json: &str) -> Result<ConversionStats> {
+        let mut collector = StatsCollector::default();
+        self.walk(&mut collector, json)?;
+        collector.stats.input_bytes = json.len();
+        Ok(collector.stats)
+    }
+
+    /// Flatten a JSON document into its leaf values keyed by flattened path
+    ///
+    /// Leaves are scalars and the empty arrays and objects written as markers,
+    /// in the order the TOON output would list them. Distinct source keys
+    /// that flatten to the same key fail with `DuplicateKey`.
+    pub fn flatten(&self, json: &str) -> Result<FlatMap> {
+        let mut collector = LeafCollector {
+            leaves: FlatMap::default(),
+            format: self.format.clone(),
+        };
+        self.walk(&mut collector, json)?;
+        Ok(collector.leaves)
+    }
+
+    /// Convert a whole document under `prefix`, nesting a top-level array under `array_root_key`
+    ///
+    /// Fails with `DuplicateKey` if two entries of the document share a key.
+    fn convert_root<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        value: &Value,
+    ) -> Result<()> {
+        if !self.keys_can_collide(value) {
+            return self.convert_document(writer, prefix, value);
+        }
+        let mut writer = UniqueKeys::new(writer);
+        self.convert_document(&mut writer, prefix, value)?;
+        writer.finish()
+    }
+
+    /// Whether distinct paths of a document can flatten to the same key, so
+    /// `convert_root` has to track the keys it writes
+    ///
+    /// Without options that rewrite keys, that takes an object key holding
+    /// the separator, e.g. `a.b` beside `a` holding `b`; JSON Pointer keys
+    /// escape theirs.
+    fn keys_can_collide(&self, value: &Value) -> bool {
+        let options = &self.options;
+        if options.key_case.is_some()
+            || !options.strip_prefix.is_empty()
+            || !options.array_root_key.is_empty()
+            || options.presence_keys
+            || options.parse_embedded_json
+            || self.format.quote_numeric_keys
+        {
+            return true;
+        }
+        if self.format.output_format == OutputFormat::JsonPointer {
+            return false;
+        }
+        let (separator, array_separator) = (self.format.separator, self.format.array_separator);
+        // Iterative, since the value may be nested up to `max_recursion` levels
+        let mut pending = Vec::from([value]);
+        while let Some(value) = pending.pop() {
+            match value {
+                Value::Object(obj) => {
+                    for (key, value) in obj {
+                        if key.contains(separator)
+                            || array_separator.is_some_and(|sep| key.contains(sep))
+                        {
+                            return true;
+                        }
+                        pending.push(value);
+                    }
+                }
+                Value::Array(arr) => pending.extend(arr),
+                _ => {}
+            }
+        }
+        false
+    }
+
+    fn convert_document<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        value: &Value,
+    ) -> Result<()> {
+        let depth = prefix_depth(prefix);
+        let array_root_key = &self.options.array_root_key;
+        if !value.is_array() || array_root_key.is_empty() {
+            return self.convert_value(writer, prefix, value, depth);
+        }
+        if prefix.is_empty() {
+            writer.start_top_level(array_root_key);
+        }
+        let key = self.child_key(prefix, array_root_key);
+        self.convert_value(writer, &key, value, depth + 1)
+    }
+
+    /// Fail once `len` bytes of output exceed `max_output_size`
+    pub fn check_output_len(&self, len: usize) -> Result<()> {
+        match self.options.max_output_size {
+            Some(limit) if len > limit => Err(ConvertError::OutputTooLarge { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Length of the key prefix `convert_root` traverses a document under
+    fn root_prefix_len(&self, value: &Value, prefix_len: usize) -> usize {
+        let array_root_key = &self.options.array_root_key;
+        if value.is_array() && !array_root_key.is_empty() {
+            self.child_key_len(prefix_len, array_root_key.len())
+        } else {
+            prefix_len
+        }
+    }
+
+    /// Recursively convert a JSON value to TOON format
+    ///
+    /// `depth` is the number of key segments in `prefix`.
+    fn convert_value<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        value: &Value,
+        depth: usize,
+    ) -> Result<()> {
+        if let Some(len) = writer.output_len() {
+            self.check_output_len(len)?;
+        }
+        writer.set_depth(depth);
+        let key = self.entry_key(prefix);
+        // Parsers other than JSON are not pre-checked, so guard the recursion here too
+        if (value.is_array() || value.is_object()) && depth >= self.options.max_recursion {
+            return Err(ConvertError::TooDeep {
+                limit: self.options.max_recursion,
+            });
+        }
+        if let Value::String(text) = value
+            && let Some(embedded) = self.embedded_json(text)
+        {
+            // Flattened under the same key; nesting still counts towards max_recursion
+            return self.convert_value(writer, prefix, &embedded, depth);
+        }
+        let coerced;
+        let value = if let Some(rule) = self.coercion_rule(key, value) {
+            let target = rule.target();
+            coerced = target.apply(value).ok_or_else(|| ConvertError::Coerce {
+                key: key.to_string(),
+                value: value.to_string(),
+                target: target.name(),
+            })?;
+            &coerced
+        } else if let Some(rule) = self.matching_rule(&self.options.types, key) {
+            let target = rule.target();
+            coerced = target.apply(value).ok_or_else(|| ConvertError::WrongType {
+                key: key.to_string(),
+                expected: target.name(),
+                found: match value {
+                    Value::Array(_) => "an array".to_string(),
+                    Value::Object(_) => "an object".to_string(),
+                    _ => value.to_string(),
+                },
+            })?;
+            &coerced
+        } else {
+            value
+        };
+        match value {
+            _ if self.is_skipped(value) => {}
+            Value::Null => {
+                writer.write_null(key);
+            }
+            Value::Bool(b) => {
+                writer.write_bool(key, *b);
+            }
+            Value::Number(n) => {
+                // Use the source representation, not `fract()`, to tell integers apart
+                if let Some(i) = json_integer(n) {
+                    writer.write_integer(key, i);
+                } else if let Some(f) = n.as_f64() {
+                    writer.write_number(key, f);
+                } else {
+                    return Err(ConvertError::NonFiniteNumber {
+                        key: key.to_string(),
+                        value: n.to_string(),
+                    });
+                }
+            }
+            Value::String(s) => {
+                writer.write_string(key, s);
+            }
+            Value::Array(arr) => self.convert_array(writer, prefix, arr, depth)?,
+            Value::Object(obj) => self.convert_object(writer, prefix, obj, depth)?,
+        }
+        Ok(())
+    }
+
+    /// Convert array items using indexed keys (`prefix.0`, `prefix.1`, ...)
+    fn convert_array<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        arr: &[Value],
+        depth: usize,
+    ) -> Result<()> {
+        let Some(sample) = self.options.sample.filter(|&sample| arr.len() > sample) else {
+            return self.convert_items(writer, prefix, arr, depth);
+        };
+        self.convert_items(writer, prefix, &arr[..sample], depth)?;
+
+        if prefix.is_empty() {
+            self.write_group_break(writer, prefix);
+            writer.start_top_level(TRUNCATED_SEGMENT);
+        }
+        writer.set_depth(depth + 1);
+        writer.write_bool(
+            self.entry_key(&self.child_key(prefix, TRUNCATED_SEGMENT)),
+            true,
+        );
+        Ok(())
+    }
+
+    /// Write the items of an array, after `convert_array` has applied the `sample`
+    fn convert_items<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        arr: &[Value],
+        depth: usize,
+    ) -> Result<()> {
+        if arr.is_empty() {
+            // Represent empty array with a special marker
+            writer.write_empty_array(self.entry_key(prefix));
+            return Ok(());
+        }
+
+        if self
+            .options
+            .array_threshold
+            .is_some_and(|threshold| arr.len() > threshold)
+        {
+            let json = serde_json::to_string(arr).expect("JSON values always serialize");
+            writer.write_string(self.entry_key(prefix), &json);
+            return Ok(());
+        }
+
+        // Items can only be inlined together, or the indices would no longer match
+        if self.is_inline_level(depth) && arr.iter().all(is_leaf) {
+            let items: Vec<Value> = arr
+                .iter()
+                .filter(|item| !self.is_skipped(item))
+                .cloned()
+                .collect();
+            if !items.is_empty() {
+                writer.write_string(self.entry_key(prefix), &Value::Array(items).to_string());
+            }
+            return Ok(());
+        }
+
+        if self.format.tabular
+            && let Some(columns) = table_columns(arr)
+            && self.write_table(writer, self.entry_key(prefix), &columns, arr)
+        {
+            return Ok(());
+        }
+
+        if self.format.fold_scalar_arrays && arr.iter().all(is_table_scalar) {
+            let items: Vec<Cell> = arr
+                .iter()
+                .filter(|item| !self.is_skipped(item))
+                .map(table_cell)
+                .collect();
+            if !items.is_empty() && writer.write_scalar_array(self.entry_key(prefix), &items) {
+                return Ok(());
+            }
+        }
+
+        // Skipped items keep their original indices
+        let items = arr
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| !self.is_skipped(item));
+        writer.begin_array(self.entry_key(prefix));
+        for (n, (i, item)) in items.enumerate() {
+            if n > 0 {
+                self.write_group_break(writer, prefix);
+            }
+            if prefix.is_empty() {
+                writer.start_top_level(&i.to_string());
+            }
+            let key = self.index_key(prefix, i);
+            self.convert_value(writer, &key, item, depth + 1)?;
+        }
+        writer.end_array(self.entry_key(prefix));
+        Ok(())
+    }
+
+    /// Offer a qualifying array to the sink as a table, with column names in the key case
+    ///
+    /// Rows keep the order of the array's elements, which output stability relies on.
+    fn write_table<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        columns: &[&str],
+        arr: &[Value],
+    ) -> bool {
+        let names: Vec<Cow<str>> = columns
+            .iter()
+            .map(|column| self.object_key(column))
+            .collect();
+        if !names.iter().all(|name| toon::is_table_column(name)) {
+            return false;
+        }
+        let names: Vec<&str> = names.iter().map(|name| name.as_ref()).collect();
+
+        let rows: Vec<Vec<Cell>> = arr
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|column| table_cell(&row[*column]))
+                    .collect()
+            })
+            .collect();
+        writer.write_table(prefix, &names, &rows)
+    }
+
+    /// The object or array a string holds as JSON text, with `parse_embedded_json`
+    fn embedded_json(&self, text: &str) -> Option<Value> {
+        if !self.options.parse_embedded_json || !text.trim_start().starts_with(['{', '[']) {
+            return None;
+        }
+        serde_json::from_str(text)
+            .ok()
+            .filter(|value: &Value| value.is_object() || value.is_array())
+    }
+
+    /// An object key as it appears in the output, after any case transformation
+    fn object_key<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self.options.key_case {
+            Some(case) => case.apply(key),
+            None => Cow::Borrowed(key),
+        }
+    }
+
+    /// An object key as a segment of flattened keys: the output key, quoted
+    /// like `"0"` if it could pass for an array index under `quote_numeric_keys`
+    fn key_segment<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        let key = self.object_key(key);
+        if self.format.quote_numeric_keys
+            && !key.is_empty()
+            && key.bytes().all(|b| b.is_ascii_digit())
+        {
+            Cow::Owned(format!("{}{}{}", toon::QUOTE, key, toon::QUOTE))
+        } else {
+            key
+        }
+    }
+
+    /// Convert object fields using dotted keys (`prefix.field`)
+    fn convert_object<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        obj: &Map<String, Value>,
+        depth: usize,
+    ) -> Result<()> {
+        if obj.is_empty() {
+            // Represent empty object with a special marker
+            writer.write_empty_object(self.entry_key(prefix));
+            return Ok(());
+        }
+
+        if self.is_inline_level(depth) {
+            return self.convert_inline_object(writer, prefix, obj, depth);
+        }
+
+        writer.begin_object(self.entry_key(prefix));
+        let mut written = 0;
+        for (key, val) in self.fields(obj) {
+            // A skipped field still has its presence marker
+            let skipped = self.is_skipped(val);
+            if skipped && !self.options.presence_keys {
+                continue;
+            }
+            if written > 0 {
+                self.write_group_break(writer, prefix);
+            }
+            written += 1;
+            let segment = self.key_segment(key);
+            if prefix.is_empty() {
+                writer.start_top_level(&segment);
+            }
+            let key = self.child_key(prefix, &segment);
+            if self.options.presence_keys {
+                writer.set_depth(depth + 2);
+                writer.write_bool(
+                    self.entry_key(&self.child_key(&key, PRESENCE_SEGMENT)),
+                    true,
+                );
+            }
+            if !skipped {
+                self.convert_value(writer, &key, val, depth + 1)?;
+            }
+        }
+        writer.end_object(self.entry_key(prefix));
+        Ok(())
+    }
+
+    /// Write the leaf fields of an object above `flatten_from` as one JSON
+    /// string, then flatten the rest
+    fn convert_inline_object<S: ValueSink + ?Sized>(
+        &self,
+        writer: &mut S,
+        prefix: &str,
+        obj: &Map<String, Value>,
+        depth: usize,
+    ) -> Result<()> {
+        let mut inline = Map::new();
+        let mut nested = Vec::new();
+        for (key, val) in self.fields(obj).filter(|(_, val)| !self.is_skipped(val)) {
+            if is_leaf(val) {
+                inline.insert(self.object_key(key).into_owned(), val.clone());
+            } else {
+                nested.push((self.key_segment(key), val));
+            }
+        }
+
+        if !inline.is_empty() {
+            writer.write_string(self.entry_key(prefix), &Value::Object(inline).to_string());
+        }
+        for (key, val) in nested {
+            let key = self.child_key(prefix, &key);
+            self.convert_value(writer, &key, val, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Fields of an object in output order
+    ///
+    /// Source order, except that with `numeric_key_order` an object whose keys
+    /// are all integers is sorted by their value (`2` before `10`).
+    fn fields<'a>(
+        &self,
+        obj: &'a Map<String, Value>,
+    ) -> impl Iterator<Item = (&'a String, &'a Value)> {
+        let numeric =
+            self.options.numeric_key_order && obj.keys().all(|key| integer_key(key).is_some());
+        let mut sorted: Vec<_> = if numeric {
+            obj.iter().collect()
+        } else {
+            Vec::new()
+        };
+        // Stable, so keys of equal value such as `1` and `01` keep their source order
+        sorted.sort_by_cached_key(|(key, _)| integer_key(key));
+        let source = (!numeric).then(|| obj.iter());
+        sorted.into_iter().chain(source.into_iter().flatten())
+    }
+
+    /// Join a key prefix and a child segment in the output's key syntax
+    ///
+    /// JSON Pointer output starts every key with `/` and escapes each segment.
+    fn child_key(&self, prefix: &str, segment: &str) -> String {
+        if self.format.output_format == OutputFormat::JsonPointer {
+            format!("{}/{}", prefix, pointer::escape_segment(segment))
+        } else {
+            child_key(prefix, segment, self.format.separator)
+        }
+    }
+
+    /// Key of item `index` of the array at `prefix`, joined by the `array_separator` if set
+    fn index_key(&self, prefix: &str, index: usize) -> String {
+        match self.format.array_separator {
+            Some(separator) if self.format.output_format != OutputFormat::JsonPointer => {
+                child_key(prefix, &index.to_string(), separator)
+            }
+            _ => self.child_key(prefix, &index.to_string()),
+        }
+    }
+
+    /// Key of an entry written for the value at `prefix`: `root_key` for the top-level value
+    fn entry_key<'a>(&'a self, prefix: &'a str) -> &'a str {
+        if prefix.is_empty() {
+            &self.options.root_key
+        } else {
+            self.strip_prefix(prefix)
+        }
+    }
+
+    /// A key without the `strip_prefix` segments, or unchanged if it does not start with them
+    fn strip_prefix<'a>(&self, key: &'a str) -> &'a str {
+        let strip = &self.options.strip_prefix;
+        if strip.is_empty() {
+            return key;
+        }
+        if self.format.output_format == OutputFormat::JsonPointer {
+            // Keep the leading `/` of the remaining pointer
+            return key
+                .strip_prefix('/')
+                .and_then(|rest| rest.strip_prefix(strip.as_str()))
+                .filter(|rest| rest.starts_with('/'))
+                .unwrap_or(key);
+        }
+        key.strip_prefix(strip.as_str())
+            .and_then(|rest| rest.strip_prefix(self.format.separator))
+            .unwrap_or(key)
+    }
+
+    /// The first `--coerce` rule matching a scalar at this key; containers are walked instead
+    fn coercion_rule(&self, key: &str, value: &Value) -> Option<&CoercionRule> {
+        if value.is_array() || value.is_object() {
+            return None;
+        }
+        self.matching_rule(&self.options.coerce, key)
+    }
+
+    /// The first of `rules` whose glob matches this key
+    fn matching_rule<'a>(&self, rules: &'a [CoercionRule], key: &str) -> Option<&'a CoercionRule> {
+        // Globs are written without the leading `/` of a JSON Pointer
+        let (key, separator) = match self.format.output_format {
+            OutputFormat::JsonPointer => (key.strip_prefix('/').unwrap_or(key), '/'),
+            _ => (key, self.format.separator),
+        };
+        rules.iter().find(|rule| rule.matches(key, separator))
+    }
+
+    /// Whether a container with this many key segments is above `flatten_from`
+    fn is_inline_level(&self, depth: usize) -> bool {
+        depth > 0 && depth <= self.options.flatten_from
+    }
+
+    /// Whether a value is omitted entirely from the output
+    fn is_skipped(&self, value: &Value) -> bool {
+        let options = &self.options;
+        match value {
+            Value::Null => options.skip_nulls,
+            Value::String(s) => options.skip_empty_strings && s.is_empty(),
+            Value::Array(arr) => options.skip_empty_arrays && arr.is_empty(),
+            Value::Object(obj) => options.skip_empty_objects && obj.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Separate top-level groups with a blank line when grouping is enabled
+    fn write_group_break<S: ValueSink + ?Sized>(&self, writer: &mut S, prefix: &str) {
+        if self.options.group_by_toplevel && prefix.is_empty() {
+            writer.write_blank_line();
+        }
+    }
+
+    /// Estimate the size of the TOON output
+    pub fn estimate_size(&self, json: &str) -> Result<usize> {
+        let size = self.parse(json)?.map_or(0, |value| {
+            self.estimate_value_size(&value, self.root_prefix_len(&value, 0))
+        });
+        Ok(size)
+    }
+
+    /// Length of a string value once escaped for the output
+    fn escaped_len(&self, s: &str) -> usize {
+        let separator = self
+            .format
+            .escape_separator
+            .then_some(self.format.separator);
+        toon::escaped_len(s, separator)
+    }
+
+    /// Length of `prefix.segment`, or just `segment` when the prefix is empty
+    fn child_key_len(&self, prefix_len: usize, segment_len: usize) -> usize {
+        if prefix_len == 0 {
+            segment_len
+        } else {
+            prefix_len + self.format.separator.len_utf8() + segment_len
+        }
+    }
+
+    /// Estimate output size given the length of the key prefix
+    ///
+    /// Works on key lengths rather than building key strings so it stays
+    /// cheap enough to run before every conversion.
+    fn estimate_value_size(&self, value: &Value, prefix_len: usize) -> usize {
+        match value {
+            Value::Null => prefix_len + 6,       // "key=null\n"
+            Value::Bool(_) => prefix_len + 7,    // "key=false\n" (worst case)
+            Value::Number(_) => prefix_len + 25, // Allow for large numbers
+            Value::String(s) => prefix_len + self.escaped_len(s) + 4, // "key=\"val\"\n"
+            Value::Array(arr) => {
+                if arr.is_empty() {
+                    prefix_len + self.format.empty_array_token.len() + 2 // "key=[]\n"
+                } else {
+                    arr.iter()
+                        .enumerate()
+                        .map(|(i, item)| {
+                            let key_len = self.child_key_len(prefix_len, decimal_len(i));
+                            self.estimate_value_size(item, key_len)
+                        })
+                        .sum()
+                }
+            }
+            Value::Object(obj) => {
+                if obj.is_empty() {
+                    prefix_len + self.format.empty_object_token.len() + 2 // "key={}\n"
+                } else {
+                    obj.iter()
+                        .map(|(key, val)| {
+                            let key_len = self.child_key_len(prefix_len, key.len());
+                            self.estimate_value_size(val, key_len)
+                        })
+                        .sum()
+                }
+            }
+        }
+    }
+}
+
+/// Column names if `arr` is a non-empty array of objects with the same keys
+/// and only scalar values, in the key order of the first object
+fn table_columns(arr: &[Value]) -> Option<Vec<&str>> {
+    let first = arr.first()?.as_object()?;
+    if first.is_empty() {
+        return None;
+    }
+
+    let uniform = arr.iter().all(|row| {
+        row.as_object().is_some_and(|obj| {
+            obj.len() == first.len()
+                && first
+                    .keys()
+                    .all(|key| obj.get(key).is_some_and(is_table_scalar))
+        })
+    });
+    uniform.then(|| first.keys().map(String::as_str).collect())
+}
+
+/// The value of an integral JSON number (`is_i64` or `is_u64`)
+fn json_integer(n: &serde_json::Number) -> Option<i128> {
+    n.as_i64()
+        .map(i128::from)
+        .or_else(|| n.as_u64().map(i128::from))
+}
+
+/// Deepest bracket nesting of JSON text, ignoring brackets inside strings
+///
+/// A single iterative pass, so it is safe on input too deep to parse recursively.
+fn nesting_depth(json: &str) -> usize {
+    let (mut depth, mut max) = (0usize, 0);
+    let (mut in_string, mut escaped) = (false, false);
+    for byte in json.bytes() {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                max = max.max(depth);
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max
+}
+
+/// Whether a value has no members to flatten: a scalar or an empty container
+fn is_leaf(value: &Value) -> bool {
+    match value {
+        Value::Array(arr) => arr.is_empty(),
+        Value::Object(obj) => obj.is_empty(),
+        _ => true,
+    }
+}
+
+/// Whether a value can be written as a table cell
+fn is_table_scalar(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(_) | Value::String(_) => true,
+        Value::Number(n) => n.as_f64().is_some(),
+        Value::Array(_) | Value::Object(_) => false,
+    }
+}
+
+/// Cell for a value accepted by `is_table_scalar`
+fn table_cell(value: &Value) -> Cell<'_> {
+    match value {
+        Value::String(s) => Cell::String(s),
+        Value::Number(n) => match json_integer(n) {
+            Some(i) => Cell::Integer(i),
+            None => Cell::Number(n.as_f64().expect("checked by is_table_scalar")),
+        },
+        Value::Bool(b) => Cell::Bool(*b),
+        _ => Cell::Null,
+    }
+}
+
+/// Iterate over the non-blank lines of JSON Lines input as `(line_number, record)`
+pub fn jsonl_records(jsonl: &str) -> impl Iterator<Item = (usize, &str)> {
+    jsonl
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| (i + 1, line))
+}
+
+/// Value of an object key written as a plain integer, e.g. `42` or `-7`
+fn integer_key(key: &str) -> Option<i128> {
+    let digits = key.strip_prefix('-').unwrap_or(key);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    key.parse().ok()
+}
+
+/// Join a key prefix and a child segment, e.g. `user` + `name` -> `user.name`
+pub(crate) fn child_key(prefix: &str, segment: &str, separator: char) -> String {
+    if prefix.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}{}{}", prefix, separator, segment)
+    }
+}
+
+/// Depth of a document written under `prefix`: 0 at the root, 1 under a JSONL record index
+fn prefix_depth(prefix: &str) -> usize {
+    usize::from(!prefix.is_empty())
+}
+
+/// Type named by the `--emit-root-type` line, `None` for an object
+fn root_type_name(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::Object(_) => None,
+        Value::Array(_) => Some("array"),
+        Value::String(_) => Some("string"),
+        Value::Number(_) => Some("number"),
+        Value::Bool(_) => Some("bool"),
+        Value::Null => Some("null"),
+    }
+}
+
+/// Number of decimal digits needed to print an array index
+fn decimal_len(n: usize) -> usize {
+    n.checked_ilog10().map_or(1, |digits| digits as usize + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_simple_types() {
+        let converter = Converter::new();
+
+        let json = r#"{"name": "Alice", "age": 30, "active": true, "middle": null}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("name=\"Alice\"\n"));
+        assert!(toon.contains("age=30\n"));
+        assert!(toon.contains("active=true\n"));
+        assert!(toon.contains("middle=null\n"));
+    }
+
+    #[test]
+    fn test_convert_nested_object() {
+        let converter = Converter::new();
+
+        let json = r#"{"user": {"name": "Bob", "age": 25}}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("user.name=\"Bob\"\n"));
+        assert!(toon.contains("user.age=25\n"));
+    }
+
+    #[test]
+    fn test_preserves_source_key_order() {
+        let converter = Converter::new();
+
+        let json = r#"{"zeta": 1, "alpha": {"y": 2, "b": 3}, "mid": 4}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert_eq!(toon, "zeta=1\nalpha.y=2\nalpha.b=3\nmid=4\n");
+    }
+
+    #[test]
+    fn test_convert_array() {
+        let converter = Converter::new();
+
+        let json = r#"{"items": ["apple", "banana", "cherry"]}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("items.0=\"apple\"\n"));
+        assert!(toon.contains("items.1=\"banana\"\n"));
+        assert!(toon.contains("items.2=\"cherry\"\n"));
+    }
+
+    #[test]
+    fn test_convert_empty_array() {
+        let converter = Converter::new();
+
+        let json = r#"{"items": []}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("items=[]\n"));
+    }
+
+    #[test]
+    fn test_convert_empty_object() {
+        let converter = Converter::new();
+
+        let json = r#"{"data": {}}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("data={}\n"));
+    }
+
+    /// Read a flattened `key=value` line back into its key and JSON value
+    fn read_entry<'a>(line: &'a str, format: &ToonOptions) -> (&'a str, Value) {
+        let (key, value) = line.split_once('=').unwrap();
+        let value = if value == format.empty_array_token {
+            Value::Array(Vec::new())
+        } else if value == format.empty_object_token {
+            Value::Object(Map::new())
+        } else if value.starts_with('"') {
+            serde_json::from_str(value).unwrap()
+        } else {
+            // Other bare values are scalars or unquoted strings
+            match serde_json::from_str(value) {
+                Ok(scalar @ (Value::Null | Value::Bool(_) | Value::Number(_))) => scalar,
+                _ => Value::from(value),
+            }
+        };
+        (key, value)
+    }
+
+    #[test]
+    fn test_empty_collection_round_trip() {
+        let json = r#"{"a": [], "b": "[]", "c": {}, "d": "{}", "e": "-", "f": "none"}"#;
+        let expected: Map<String, Value> = serde_json::from_str(json).unwrap();
+
+        let custom = ToonOptions {
+            empty_array_token: "-".to_string(),
+            empty_object_token: "none".to_string(),
+            unquoted_safe_strings: true,
+            ..Default::default()
+        };
+        for format in [ToonOptions::default(), custom] {
+            let toon = Converter::new()
+                .with_format(format.clone())
+                .convert(json)
+                .unwrap();
+            let entries: Map<String, Value> = toon
+                .lines()
+                .map(|line| {
+                    let (key, value) = read_entry(line, &format);
+                    (key.to_string(), value)
+                })
+                .collect();
+            assert_eq!(entries, expected, "{}", toon);
+        }
+    }
+
+    #[test]
+    fn test_convert_nested_array() {
+        let converter = Converter::new();
+
+        let json = r#"{"matrix": [[1, 2], [3, 4]]}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("matrix.0.0=1\n"));
+        assert!(toon.contains("matrix.0.1=2\n"));
+        assert!(toon.contains("matrix.1.0=3\n"));
+        assert!(toon.contains("matrix.1.1=4\n"));
+    }
+
+    #[test]
+    fn test_convert_complex_structure() {
+        let converter = Converter::new();
+
+        let json = r#"{
+            "name": "Project",
+            "version": "1.0.0",
+            "authors": ["Alice", "Bob"],
+            "config": {
+                "debug": true,
+                "timeout": 30
+            }
+        }"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("name=\"Project\"\n"));
+        assert!(toon.contains("version=\"1.0.0\"\n"));
+        assert!(toon.contains("authors.0=\"Alice\"\n"));
+        assert!(toon.contains("authors.1=\"Bob\"\n"));
+        assert!(toon.contains("config.debug=true\n"));
+        assert!(toon.contains("config.timeout=30\n"));
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let converter = Converter::new();
+
+        let json = r#"{"invalid": json}"#;
+        let result = converter.convert(json);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_top_level_array() {
+        let converter = Converter::new();
+
+        let json = r#"[1, 2, 3]"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("0=1\n"));
+        assert!(toon.contains("1=2\n"));
+        assert!(toon.contains("2=3\n"));
+    }
+
+    #[test]
+    fn test_top_level_primitive() {
+        let converter = Converter::new();
+
+        let json = r#""hello""#;
+        let toon = converter.convert(json).unwrap();
+
+        assert_eq!(toon, "=\"hello\"\n");
+    }
+
+    #[test]
+    fn test_root_key() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            root_key: "value".to_string(),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            converter.convert(r#""hello""#).unwrap(),
+            "value=\"hello\"\n"
+        );
+        assert_eq!(converter.convert("[]").unwrap(), "value=[]\n");
+        // Keys below the root never include it
+        assert_eq!(converter.convert(r#"{"a": 1}"#).unwrap(), "a=1\n");
+        assert_eq!(converter.convert("[1]").unwrap(), "0=1\n");
+    }
+
+    #[test]
+    fn test_duplicate_flattened_keys() {
+        let json = r#"{"a": {"b": 1}, "a.b": 2}"#;
+        let converter = Converter::new();
+        assert!(
+            matches!(converter.convert(json), Err(ConvertError::DuplicateKey { ref key }) if key == "a.b")
+        );
+        assert!(matches!(
+            converter.flatten(json),
+            Err(ConvertError::DuplicateKey { .. })
+        ));
+
+        let converter = Converter::new().with_options(ConvertOptions {
+            key_case: Some(KeyCase::Lower),
+            ..Default::default()
+        });
+        assert!(
+            matches!(converter.convert(r#"{"A": 1, "a": 2}"#), Err(ConvertError::DuplicateKey { ref key }) if key == "a")
+        );
+        // A key that is only a prefix of another is not a duplicate
+        assert_eq!(
+            Converter::new().convert(r#"{"a": 1, "a.b": 2}"#).unwrap(),
+            "a=1\na.b=2\n"
+        );
+
+        let converter = Converter::new().with_options(ConvertOptions {
+            presence_keys: true,
+            ..Default::default()
+        });
+        assert!(matches!(
+            converter.convert(r#"{"a": {"__present": 1}}"#),
+            Err(ConvertError::DuplicateKey { .. })
+        ));
+        let converter = Converter::new().with_format(ToonOptions {
+            array_separator: Some('#'),
+            ..Default::default()
+        });
+        assert!(matches!(
+            converter.convert(r#"{"a": [1], "a#0": 2}"#),
+            Err(ConvertError::DuplicateKey { .. })
+        ));
+    }
+
+    #[test]
+    fn test_keys_can_collide() {
+        let value = |json| serde_json::from_str::<Value>(json).unwrap();
+        let converter = Converter::new();
+        assert!(!converter.keys_can_collide(&value(r#"{"a": [{"b": 1}], "c": "x.y"}"#)));
+        assert!(converter.keys_can_collide(&value(r#"{"a": [{"b.c": 1}]}"#)));
+        let pointer = Converter::new().with_format(ToonOptions {
+            output_format: OutputFormat::JsonPointer,
+            ..Default::default()
+        });
+        assert!(!pointer.keys_can_collide(&value(r#"{"a/b": 1, "a.b": 2}"#)));
+        let converter = Converter::new().with_options(ConvertOptions {
+            key_case: Some(KeyCase::Lower),
+            ..Default::default()
+        });
+        assert!(converter.keys_can_collide(&value("{}")));
+    }
+
+    #[test]
+    fn test_convert_annotate_types() {
+        let converter = Converter::new().with_format(ToonOptions {
+            annotate_types: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"age": 30, "score": 98.5, "zip": "12345", "tags": [], "meta": {}}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("age:integer=30\n"));
+        assert!(toon.contains("score:float=98.5\n"));
+        assert!(toon.contains("zip:string=\"12345\"\n"));
+        assert!(toon.contains("tags:array=[]\n"));
+        assert!(toon.contains("meta:object={}\n"));
+    }
+
+    #[test]
+    fn test_convert_annotate_integer_boundaries() {
+        let converter = Converter::new().with_format(ToonOptions {
+            annotate_types: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"max": 9223372036854775807, "min": -9223372036854775808,
+            "umax": 18446744073709551615, "exact": 9007199254740993,
+            "whole": 30.0, "big": 1e20}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert!(toon.contains("max:integer=9223372036854775807\n"));
+        assert!(toon.contains("min:integer=-9223372036854775808\n"));
+        assert!(toon.contains("umax:integer=18446744073709551615\n"));
+        assert!(toon.contains("exact:integer=9007199254740993\n"));
+        // Written with a decimal point or exponent in the source, so a float
+        assert!(toon.contains("whole:float=30\n"));
+        assert!(toon.contains("big:float=1e20\n"));
+    }
+
+    #[test]
+    fn test_estimate_size_covers_output() {
+        let converter = Converter::new();
+
+        let json = r#"{"user": {"name": "Bob", "tags": ["a", "b"]}, "items": [[1, 2], []]}"#;
+        let toon = converter.convert(json).unwrap();
+        let estimate = converter.estimate_size(json).unwrap();
+
+        assert!(estimate >= toon.len());
+    }
+
+    #[test]
+    fn test_estimate_size_counts_escapes() {
+        let converter = Converter::new();
+
+        // Strings only, so the estimate should be exact
+        let json =
+            r#"{"q": "\"\"\"\"\"\"\"\"", "path": "C:\\dir\\file", "multi": "a\nb\tc\u0001"}"#;
+        let toon = converter.convert(json).unwrap();
+        assert_eq!(
+            converter.estimate_size(json).unwrap(),
+            toon.len(),
+            "{}",
+            toon
+        );
+    }
+
+    #[test]
+    fn test_decimal_len() {
+        assert_eq!(decimal_len(0), 1);
+        assert_eq!(decimal_len(9), 1);
+        assert_eq!(decimal_len(10), 2);
+        assert_eq!(decimal_len(12345), 5);
+    }
+
+    #[test]
+    fn test_convert_record() {
+        let converter = Converter::new();
+
+        let toon = converter
+            .convert_record(r#"{"id": 7, "tags": ["x"]}"#, 3)
+            .unwrap();
+        assert_eq!(toon, "3.id=7\n3.tags.0=\"x\"\n");
+
+        let toon = converter.convert_record("true", 0).unwrap();
+        assert_eq!(toon, "0=true\n");
+    }
+
+    #[test]
+    fn test_convert_jsonl() {
+        let converter = Converter::new();
+
+        let jsonl = "{\"name\": \"a\"}\n\n   \n{\"name\": \"b\"}\n";
+        let toon = converter.convert_jsonl(jsonl).unwrap();
+
+        assert_eq!(toon, "0.name=\"a\"\n1.name=\"b\"\n");
+    }
+
+    #[test]
+    fn test_convert_prefixed() {
+        let converter = Converter::new();
+        assert_eq!(
+            converter
+                .convert_prefixed(r#"{"config": {"port": 80}}"#, "app")
+                .unwrap(),
+            "app.config.port=80\n"
+        );
+        assert_eq!(
+            converter.convert_prefixed("[true]", "flags").unwrap(),
+            "flags.0=true\n"
+        );
+        assert_eq!(converter.convert_prefixed("7", "n").unwrap(), "n=7\n");
+    }
+
+    #[test]
+    fn test_convert_multi_doc() {
+        let converter = Converter::new();
+
+        let (toon, count) = converter
+            .convert_multi_doc("{\"a\": 1}{\"a\": 2}\n[true] \"x\"")
+            .unwrap();
+        assert_eq!(toon, "0.a=1\n1.a=2\n2.0=true\n3=\"x\"\n");
+        assert_eq!(count, 4);
+
+        let err = converter
+            .convert_multi_doc("{\"a\": 1}{\"a\": }")
+            .unwrap_err();
+        assert!(matches!(err, ConvertError::Parse { .. }));
+        assert!(matches!(
+            converter.convert_multi_doc(" \n"),
+            Err(ConvertError::Empty)
+        ));
+    }
+
+    #[test]
+    fn test_convert_multi_doc_reports_document() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            types: vec![CoercionRule::parse("*.n:integer").unwrap()],
+            ..Default::default()
+        });
+        let err = converter
+            .convert_multi_doc("{\"n\": 1}{\"n\": \"x\"}")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Failed to convert document 1");
+    }
+
+    #[test]
+    fn test_convert_jsonl_reports_line_number() {
+        let converter = Converter::new();
+
+        let jsonl = "{\"ok\": 1}\n\n{broken}\n";
+        let err = converter.convert_jsonl(jsonl).unwrap_err();
+
+        assert!(format!("{:#}", err).contains("line 3"));
+    }
+
+    #[test]
+    fn test_patches() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            patches: vec![
+                serde_json::json!({"db": {"host": "prod", "debug": null}}),
+                serde_json::json!({"db": {"host": "prod-2"}, "replicas": 3}),
+            ],
+            ..Default::default()
+        });
+        let toon = converter
+            .convert(r#"{"db": {"host": "dev", "port": 5432, "debug": true}}"#)
+            .unwrap();
+        assert_eq!(toon, "db.host=\"prod-2\"\ndb.port=5432\nreplicas=3\n");
+    }
+
+    #[test]
+    fn test_convert_profiled() {
+        let converter = Converter::new().with_format(ToonOptions {
+            tabular: true,
+            ..Default::default()
+        });
+        let json = r#"{"a": {"b": 1, "c": [true, null]}, "rows": [{"x": 1}, {"x": 2}], "d": "s"}"#;
+        let (toon, profile) = converter.convert_profiled(json).unwrap();
+        assert_eq!(toon, converter.convert(json).unwrap());
+
+        let keys: Vec<(&str, usize)> = profile
+            .top_level_keys
+            .iter()
+            .map(|timing| (timing.key.as_str(), timing.entries))
+            .collect();
+        assert_eq!(keys, [("a", 3), ("rows", 2), ("d", 1)]);
+        assert!(profile.parse_ms >= 0.0 && profile.traverse_ms >= 0.0);
+
+        let (_, profile) = converter.convert_profiled("[1, [2, 3]]").unwrap();
+        let keys: Vec<&str> = profile
+            .top_level_keys
+            .iter()
+            .map(|timing| timing.key.as_str())
+            .collect();
+        assert_eq!(keys, ["0", "1"]);
+    }
+
+    #[test]
+    fn test_validate() {
+        let converter = Converter::new();
+        assert!(converter.validate(r#"{"a": [1, 2]}"#).is_ok());
+
+        let err = converter.validate("{\"a\": 1,\n  oops}").unwrap_err();
+        let ConvertError::Parse { source, .. } = &err else {
+            panic!("expected a parse error, got {:?}", err);
+        };
+        assert!(source.to_string().contains("line 2 column 3"), "{}", source);
+
+        let err = converter.validate_jsonl("{\"ok\": 1}\n\n[\n").unwrap_err();
+        assert!(matches!(err, ConvertError::Record { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_group_by_toplevel() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            group_by_toplevel: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"a": {"x": 1, "y": 2}, "b": 3, "c": [true]}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert_eq!(toon, "a.x=1\na.y=2\n\nb=3\n\nc.0=true\n");
+    }
+
+    #[test]
+    fn test_group_by_toplevel_does_not_change_count() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            group_by_toplevel: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"a": 1, "b": 2}"#;
+        assert_eq!(converter.count_entries(json).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_collect_keys() {
+        let converter = Converter::new();
+
+        let json = r#"{"b": {"y": 1, "x": [true, null]}, "a": "", "c": {}}"#;
+        let keys: Vec<String> = converter.collect_keys(json).unwrap().into_iter().collect();
+
+        assert_eq!(keys, ["a", "b.x.0", "b.x.1", "b.y", "c"]);
+    }
+
+    #[test]
+    fn test_flatten() {
+        let converter = Converter::new();
+
+        let json = r#"{"user": {"name": "Bob", "age": 25}, "score": 9.5, "tags": []}"#;
+        let leaves = converter.flatten(json).unwrap();
+
+        assert_eq!(leaves.len(), 4);
+        assert_eq!(leaves["user.name"], Value::from("Bob"));
+        assert_eq!(leaves["user.age"], Value::from(25));
+        assert_eq!(leaves["score"], Value::from(9.5));
+        assert_eq!(leaves["tags"], Value::Array(Vec::new()));
+        // In document order, not sorted
+        assert_eq!(
+            leaves.keys().collect::<Vec<_>>(),
+            ["user.name", "user.age", "score", "tags"]
+        );
+    }
+
+    #[test]
+    fn test_convert_key_with_newline() {
+        let converter = Converter::new();
+
+        let toon = converter
+            .convert(r#"{"a\nb": 1, "outer": {"x\ry": "v"}}"#)
+            .unwrap();
+
+        assert_eq!(toon, "a\\nb=1\nouter.x\\ry=\"v\"\n");
+        assert_eq!(toon.lines().count(), 2);
+    }
+
+    #[test]
+    fn test_skip_nulls() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            skip_nulls: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"a": null, "b": [1, null, 3], "c": {"d": null}, "e": "x"}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert_eq!(toon, "b.0=1\nb.2=3\ne=\"x\"\n");
+        assert_eq!(converter.convert("null").unwrap(), "");
+    }
+
+    #[test]
+    fn test_skip_nulls_with_grouping() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            group_by_toplevel: true,
+            skip_nulls: true,
+            ..Default::default()
+        });
+
+        let toon = converter.convert(r#"{"a": 1, "b": null, "c": 2}"#).unwrap();
+
+        assert_eq!(toon, "a=1\n\nc=2\n");
+    }
+
+    #[test]
+    fn test_skip_empty() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            skip_empty_strings: true,
+            skip_empty_arrays: true,
+            skip_empty_objects: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"a": "", "b": [], "c": {}, "d": {"e": ""}, "f": [0, "", []], "g": "x"}"#;
+        let toon = converter.convert(json).unwrap();
+
+        assert_eq!(toon, "f.0=0\ng=\"x\"\n");
+        assert_eq!(converter.convert("{}").unwrap(), "");
     }
 
-    /// Convert JSON string to TOON format
-    pub fn convert(&self, json: &str) -> Result<String> {
-        if self.verbose {
-            println!("[INFO] Parsing JSON...");
-        }
+    #[test]
+    fn test_skip_empty_independent() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            skip_empty_arrays: true,
+            ..Default::default()
+        });
 
-        let value: Value = serde_json::from_str(json)
-            .context("Failed to parse JSON")?;
+        let toon = converter.convert(r#"{"a": "", "b": [], "c": {}}"#).unwrap();
 
-        if self.verbose {
-            println!("[INFO] JSON parsed successfully");
-            println!("[INFO] Converting to TOON format...");
-        }
+        assert_eq!(toon, "a=\"\"\nc={}\n");
+    }
 
-        let mut writer = ToonWriter::new();
-        self.convert_value(&mut writer, "", &value)?;
+    #[test]
+    fn test_convert_csv() {
+        let converter = Converter::new().with_format(ToonOptions {
+            output_format: OutputFormat::Csv,
+            ..Default::default()
+        });
 
-        if self.verbose {
-            println!("[INFO] Conversion complete");
-        }
+        let toon = converter.convert(r#"{"a": {"b": 1}, "c": "x,y"}"#).unwrap();
+        assert_eq!(toon, "a.b,1\nc,\"\"\"x,y\"\"\"\n");
 
-        Ok(writer.finish())
+        let jsonl = converter.convert_jsonl("{\"a\": 1}\n{\"a\": 2}\n").unwrap();
+        assert_eq!(jsonl, "0.a,1\n1.a,2\n");
     }
 
-    /// Recursively convert a JSON value to TOON format
-    fn convert_value(&self, writer: &mut ToonWriter, prefix: &str, value: &Value) -> Result<()> {
-        match value {
-            Value::Null => {
-                writer.write_null(prefix);
-            }
-            Value::Bool(b) => {
-                writer.write_bool(prefix, *b);
-            }
-            Value::Number(n) => {
-                if let Some(f) = n.as_f64() {
-                    writer.write_number(prefix, f);
-                } else {
-                    anyhow::bail!("Invalid number: {}", n);
-                }
-            }
-            Value::String(s) => {
-                writer.write_string(prefix, s);
-            }
-            Value::Array(arr) => {
-                if arr.is_empty() {
-                    // Represent empty array with a special marker
-                    writer.write_string(prefix, "[]");
-                } else {
-                    for (i, item) in arr.iter().enumerate() {
-                        let key = if prefix.is_empty() {
-                            format!("{}", i)
-                        } else {
-                            format!("{}.{}", prefix, i)
-                        };
-                        self.convert_value(writer, &key, item)?;
-                    }
-                }
-            }
-            Value::Object(obj) => {
-                if obj.is_empty() {
-                    // Represent empty object with a special marker
-                    writer.write_string(prefix, "{}");
-                } else {
-                    for (key, val) in obj.iter() {
-                        let full_key = if prefix.is_empty() {
-                            key.clone()
-                        } else {
-                            format!("{}.{}", prefix, key)
-                        };
-                        self.convert_value(writer, &full_key, val)?;
-                    }
-                }
-            }
-        }
-        Ok(())
+    #[test]
+    fn test_convert_json_pointer() {
+        let converter = Converter::new().with_format(ToonOptions {
+            output_format: OutputFormat::JsonPointer,
+            tabular: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"config": {"db": {"host": "x"}}, "a/b": [1, 2.5, null], "m~n": {}, "rows": [{"id": 1}]}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "/config/db/host \"x\"\n/a~1b/0 1\n/a~1b/1 2.5\n/a~1b/2 null\n/m~0n {}\n/rows/0/id 1\n"
+        );
+        assert_eq!(converter.convert("\"hi\"").unwrap(), " \"hi\"\n");
+
+        let jsonl = converter.convert_jsonl("{\"a\": 1}\n{\"a\": 2}\n").unwrap();
+        assert_eq!(jsonl, "/0/a 1\n/1/a 2\n");
+
+        let converter = Converter::new()
+            .with_format(ToonOptions {
+                output_format: OutputFormat::JsonPointer,
+                ..Default::default()
+            })
+            .with_options(ConvertOptions {
+                coerce: vec![CoercionRule::parse("config/*:number").unwrap()],
+                ..Default::default()
+            });
+        assert_eq!(
+            converter.convert(r#"{"config": {"port": "80"}}"#).unwrap(),
+            "/config/port 80\n"
+        );
     }
 
-    /// Estimate the size of the TOON output
-    pub fn estimate_size(&self, json: &str) -> Result<usize> {
-        let value: Value = serde_json::from_str(json)
-            .context("Failed to parse JSON")?;
-        Ok(self.estimate_value_size(&value, ""))
+    #[test]
+    fn test_convert_toml() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            input_format: InputFormat::Toml,
+            ..Default::default()
+        });
+
+        let toml = "name = \"app\"\n\n[db]\nport = 5432\nhosts = [\"a\", \"b\"]\n";
+        let toon = converter.convert(toml).unwrap();
+
+        assert_eq!(
+            toon,
+            "name=\"app\"\ndb.port=5432\ndb.hosts.0=\"a\"\ndb.hosts.1=\"b\"\n"
+        );
     }
 
-    fn estimate_value_size(&self, value: &Value, prefix: &str) -> usize {
-        match value {
-            Value::Null => prefix.len() + 6, // "key=null\n"
-            Value::Bool(_) => prefix.len() + 7, // "key=false\n" (worst case)
-            Value::Number(_) => prefix.len() + 25, // Allow for large numbers
-            Value::String(s) => prefix.len() + s.len() + 4, // "key=\"val\"\n"
-            Value::Array(arr) => {
-                if arr.is_empty() {
-                    prefix.len() + 5 // "key=[]\n"
-                } else {
-                    arr.iter()
-                        .enumerate()
-                        .map(|(i, item)| {
-                            let key = if prefix.is_empty() {
-                                format!("{}", i)
-                            } else {
-                                format!("{}.{}", prefix, i)
-                            };
-                            self.estimate_value_size(item, &key)
-                        })
-                        .sum()
-                }
-            }
-            Value::Object(obj) => {
-                if obj.is_empty() {
-                    prefix.len() + 5 // "key={}\n"
-                } else {
-                    obj.iter()
-                        .map(|(key, val)| {
-                            let full_key = if prefix.is_empty() {
-                                key.clone()
-                            } else {
-                                format!("{}.{}", prefix, key)
-                            };
-                            self.estimate_value_size(val, &full_key)
-                        })
-                        .sum()
-                }
-            }
-        }
+    #[test]
+    fn test_convert_yaml() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            input_format: InputFormat::Yaml,
+            ..Default::default()
+        });
+
+        let yaml = "name: app\ndb:\n  port: 5432\n  tls: true\n  tags: []\n";
+        let toon = converter.convert(yaml).unwrap();
+
+        assert_eq!(
+            toon,
+            "name=\"app\"\ndb.port=5432\ndb.tls=true\ndb.tags=[]\n"
+        );
+        assert!(converter.convert("a: [1,").is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_empty_input() {
+        let converter = Converter::new();
+        for input in ["", " \n\t\n"] {
+            let err = converter.convert(input).unwrap_err();
+            assert_eq!(err.to_string(), "Input is empty");
+        }
+
+        let converter = Converter::new().with_options(ConvertOptions {
+            allow_empty: true,
+            ..Default::default()
+        });
+        for input in ["", " \n\t\n"] {
+            assert_eq!(converter.convert(input).unwrap(), "");
+            assert_eq!(converter.count_entries(input).unwrap(), 0);
+        }
+    }
 
     #[test]
-    fn test_convert_simple_types() {
-        let converter = Converter::new(false);
+    fn test_show_depth() {
+        let converter = Converter::new().with_format(ToonOptions {
+            show_depth: true,
+            ..Default::default()
+        });
 
-        let json = r#"{"name": "Alice", "age": 30, "active": true, "middle": null}"#;
+        let json = r#"{"config": {"db": {"host": "x"}, "a.b": []}, "n": 1}"#;
         let toon = converter.convert(json).unwrap();
+        assert_eq!(
+            toon,
+            "2 | config.db.host=\"x\"\n1 | config.a.b=[]\n0 | n=1\n"
+        );
 
-        assert!(toon.contains("name=\"Alice\"\n"));
-        assert!(toon.contains("age=30\n"));
-        assert!(toon.contains("active=true\n"));
-        assert!(toon.contains("middle=null\n"));
+        assert_eq!(
+            converter.convert_record(r#"{"a": 1}"#, 3).unwrap(),
+            "1 | 3.a=1\n"
+        );
+        assert_eq!(converter.convert("5").unwrap(), "0 | =5\n");
     }
 
     #[test]
-    fn test_convert_nested_object() {
-        let converter = Converter::new(false);
+    fn test_tabular() {
+        let converter = Converter::new().with_format(ToonOptions {
+            tabular: true,
+            ..Default::default()
+        });
 
-        let json = r#"{"user": {"name": "Bob", "age": 25}}"#;
+        let json = r#"{"rows": [{"id": 1, "name": "Alice"}, {"name": "Bob", "id": 2}], "n": 1}"#;
         let toon = converter.convert(json).unwrap();
+        assert_eq!(toon, "rows[2]{id,name}:\n  1,\"Alice\"\n  2,\"Bob\"\nn=1\n");
 
-        assert!(toon.contains("user.name=\"Bob\"\n"));
-        assert!(toon.contains("user.age=25\n"));
+        // Counting still sees one entry per cell
+        assert_eq!(converter.count_entries(json).unwrap(), 5);
     }
 
     #[test]
-    fn test_convert_array() {
-        let converter = Converter::new(false);
+    fn test_tabular_preserves_row_order() {
+        let converter = Converter::new().with_format(ToonOptions {
+            tabular: true,
+            ..Default::default()
+        });
 
-        let json = r#"{"items": ["apple", "banana", "cherry"]}"#;
-        let toon = converter.convert(json).unwrap();
+        let toon = converter
+            .convert(r#"[{"id": 2, "v": "b"}, {"id": 10, "v": "a"}, {"id": 1, "v": "c"}]"#)
+            .unwrap();
+        assert_eq!(toon, "[3]{id,v}:\n  2,\"b\"\n  10,\"a\"\n  1,\"c\"\n");
 
-        assert!(toon.contains("items.0=\"apple\"\n"));
-        assert!(toon.contains("items.1=\"banana\"\n"));
-        assert!(toon.contains("items.2=\"cherry\"\n"));
+        let toon = converter
+            .convert(r#"[{"id": 1, "v": "c"}, {"id": 10, "v": "a"}, {"id": 2, "v": "b"}]"#)
+            .unwrap();
+        assert_eq!(toon, "[3]{id,v}:\n  1,\"c\"\n  10,\"a\"\n  2,\"b\"\n");
     }
 
     #[test]
-    fn test_convert_empty_array() {
-        let converter = Converter::new(false);
+    fn test_tabular_key_case() {
+        let converter = Converter::new()
+            .with_options(ConvertOptions {
+                key_case: Some(KeyCase::Upper),
+                ..Default::default()
+            })
+            .with_format(ToonOptions {
+                tabular: true,
+                ..Default::default()
+            });
 
-        let json = r#"{"items": []}"#;
-        let toon = converter.convert(json).unwrap();
+        let toon = converter
+            .convert(r#"{"rows": [{"id": 1}, {"id": 2}]}"#)
+            .unwrap();
+        assert_eq!(toon, "ROWS[2]{ID}:\n  1\n  2\n");
+    }
+
+    #[test]
+    fn test_tabular_falls_back_for_irregular_arrays() {
+        let converter = Converter::new().with_format(ToonOptions {
+            tabular: true,
+            ..Default::default()
+        });
+
+        let mixed_keys = r#"[{"a": 1}, {"b": 2}]"#;
+        assert_eq!(converter.convert(mixed_keys).unwrap(), "0.a=1\n1.b=2\n");
 
-        assert!(toon.contains("items=\"[]\"\n"));
+        let nested = r#"[{"a": {"x": 1}}, {"a": {"x": 2}}]"#;
+        assert_eq!(converter.convert(nested).unwrap(), "0.a.x=1\n1.a.x=2\n");
+
+        assert_eq!(converter.convert("[1, 2]").unwrap(), "0=1\n1=2\n");
     }
 
     #[test]
-    fn test_convert_empty_object() {
-        let converter = Converter::new(false);
+    fn test_max_recursion() {
+        let nested = |depth: usize| format!("{}1{}", "[".repeat(depth), "]".repeat(depth));
 
-        let json = r#"{"data": {}}"#;
-        let toon = converter.convert(json).unwrap();
+        // Far beyond what recursive parsing or traversal could survive
+        let err = Converter::new().convert(&nested(1_000_000)).unwrap_err();
+        assert!(matches!(
+            err,
+            ConvertError::TooDeep {
+                limit: DEFAULT_MAX_RECURSION
+            }
+        ));
+
+        // Deeper than serde_json's own limit of 128. Input at the default limit
+        // must fit in a main thread's usual 8 MiB stack, even unoptimized.
+        let toon = std::thread::Builder::new()
+            .stack_size(8 << 20)
+            .spawn(move || Converter::new().convert(&nested(DEFAULT_MAX_RECURSION)))
+            .unwrap()
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            toon,
+            format!("{}=1\n", vec!["0"; DEFAULT_MAX_RECURSION].join("."))
+        );
 
-        assert!(toon.contains("data=\"{}\"\n"));
+        // The highest limit the CLI accepts, on a stack sized like the CLI's
+        let deepest = Converter::new().with_options(ConvertOptions {
+            max_recursion: MAX_RECURSION_LIMIT,
+            ..Default::default()
+        });
+        let (arrays, objects) = std::thread::Builder::new()
+            .stack_size((8 << 20) + MAX_RECURSION_LIMIT * STACK_PER_LEVEL)
+            .spawn(move || {
+                let objects = format!(
+                    "{}1{}",
+                    r#"{"a":"#.repeat(MAX_RECURSION_LIMIT),
+                    "}".repeat(MAX_RECURSION_LIMIT)
+                );
+                (
+                    deepest.convert(&nested(MAX_RECURSION_LIMIT)),
+                    deepest.convert(&objects),
+                )
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(arrays.unwrap().len(), 2 * MAX_RECURSION_LIMIT + 2);
+        assert_eq!(objects.unwrap().len(), 2 * MAX_RECURSION_LIMIT + 2);
+
+        let converter = Converter::new().with_options(ConvertOptions {
+            max_recursion: 2,
+            ..Default::default()
+        });
+        assert!(converter.convert(r#"{"a": [1]}"#).is_ok());
+        assert!(converter.convert(r#"{"a": {"b": []}}"#).is_err());
+        // Brackets inside strings do not count
+        assert!(converter.convert(r#"{"a": "[[[{{{\"]]]"}"#).is_ok());
     }
 
     #[test]
-    fn test_convert_nested_array() {
-        let converter = Converter::new(false);
+    fn test_max_recursion_yaml() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            input_format: InputFormat::Yaml,
+            max_recursion: 2,
+            ..Default::default()
+        });
+        let err = converter.convert("a:\n  b:\n    c: 1\n").unwrap_err();
+        assert_eq!(err.to_string(), "Input is nested more than 2 levels deep");
+    }
 
-        let json = r#"{"matrix": [[1, 2], [3, 4]]}"#;
-        let toon = converter.convert(json).unwrap();
+    #[test]
+    fn test_coerce() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            coerce: vec![
+                CoercionRule::parse("config.port:number").unwrap(),
+                CoercionRule::parse("**.enabled:bool").unwrap(),
+                CoercionRule::parse("config.*:string").unwrap(),
+            ],
+            ..Default::default()
+        });
 
-        assert!(toon.contains("matrix.0.0=1\n"));
-        assert!(toon.contains("matrix.0.1=2\n"));
-        assert!(toon.contains("matrix.1.0=3\n"));
-        assert!(toon.contains("matrix.1.1=4\n"));
+        let json = r#"{"config": {"port": "8080", "retries": 3, "tls": {"enabled": "true"}}}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "config.port=8080\nconfig.retries=\"3\"\nconfig.tls.enabled=true\n"
+        );
+
+        let err = converter
+            .convert(r#"{"config": {"port": "http"}}"#)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Cannot coerce \"http\" at config.port to number"
+        );
     }
 
     #[test]
-    fn test_convert_complex_structure() {
-        let converter = Converter::new(false);
+    fn test_types() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            coerce: vec![CoercionRule::parse("config.name:string").unwrap()],
+            types: CoercionRule::parse_types(r#"{"config.port": "integer", "config.*": "bool"}"#)
+                .unwrap(),
+            ..Default::default()
+        });
 
-        let json = r#"{
-            "name": "Project",
-            "version": "1.0.0",
-            "authors": ["Alice", "Bob"],
-            "config": {
-                "debug": true,
-                "timeout": 30
+        let json = r#"{"config": {"port": "8080", "debug": "false", "name": 7, "tags": null}}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "config.port=8080\nconfig.debug=false\nconfig.name=\"7\"\nconfig.tags=null\n"
+        );
+
+        let err = converter
+            .convert(r#"{"config": {"port": "http"}}"#)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected integer at config.port, found \"http\""
+        );
+        let err = converter
+            .convert(r#"{"config": {"debug": {"level": 1}}}"#)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Expected bool at config.debug, found an object"
+        );
+    }
+
+    #[test]
+    fn test_flatten_from() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            flatten_from: 1,
+            ..Default::default()
+        });
+
+        let json = r#"{"name": "x", "db": {"host": "h", "port": 5432, "tags": [], "opts": {"ssl": true}}, "ids": [1, 2]}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "name=\"x\"\n\
+             db=\"{\\\"host\\\":\\\"h\\\",\\\"port\\\":5432,\\\"tags\\\":[]}\"\n\
+             db.opts.ssl=true\n\
+             ids=\"[1,2]\"\n"
+        );
+
+        // Arrays holding containers are flattened to keep their indices
+        let nested = r#"{"rows": [1, {"a": 2}]}"#;
+        assert_eq!(converter.convert(nested).unwrap(), "rows.0=1\nrows.1.a=2\n");
+    }
+
+    #[test]
+    fn test_flatten_from_levels() {
+        let json = r#"{"a": {"x": 1, "b": {"y": 2, "c": {"z": 3}}}}"#;
+        let convert = |flatten_from| {
+            let options = ConvertOptions {
+                flatten_from,
+                ..Default::default()
+            };
+            Converter::new()
+                .with_options(options)
+                .convert(json)
+                .unwrap()
+        };
+
+        assert_eq!(convert(0), "a.x=1\na.b.y=2\na.b.c.z=3\n");
+        assert_eq!(
+            convert(2),
+            "a=\"{\\\"x\\\":1}\"\na.b=\"{\\\"y\\\":2}\"\na.b.c.z=3\n"
+        );
+    }
+
+    #[test]
+    fn test_fold_scalar_arrays() {
+        let converter = Converter::new().with_format(ToonOptions {
+            fold_scalar_arrays: true,
+            ..Default::default()
+        });
+
+        let json = r#"{"ids": [1, 2, 3, 4], "tags": ["a", null, true]}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "ids=[1,2,3,4]\ntags=[\"a\",null,true]\n"
+        );
+
+        // Arrays holding objects or arrays keep the indexed form; nested ones may fold
+        let mixed = r#"{"xs": [1, [2], {"a": 3}]}"#;
+        assert_eq!(
+            converter.convert(mixed).unwrap(),
+            "xs.0=1\nxs.1=[2]\nxs.2.a=3\n"
+        );
+    }
+
+    #[test]
+    fn test_fold_scalar_arrays_skip_nulls() {
+        let converter = Converter::new()
+            .with_options(ConvertOptions {
+                skip_nulls: true,
+                ..Default::default()
+            })
+            .with_format(ToonOptions {
+                fold_scalar_arrays: true,
+                ..Default::default()
+            });
+
+        assert_eq!(
+            converter.convert(r#"{"xs": [1, null, 2]}"#).unwrap(),
+            "xs=[1,2]\n"
+        );
+        assert_eq!(
+            converter.convert(r#"{"xs": [null], "y": 1}"#).unwrap(),
+            "y=1\n"
+        );
+    }
+
+    #[test]
+    fn test_custom_separator() {
+        let converter = Converter::new().with_format(ToonOptions {
+            separator: '/',
+            ..Default::default()
+        });
+
+        let toon = converter.convert(r#"{"a": {"b.c": [true]}}"#).unwrap();
+        assert_eq!(toon, "a/b.c/0=true\n");
+    }
+
+    #[test]
+    fn test_array_separator() {
+        let converter = Converter::new().with_format(ToonOptions {
+            array_separator: Some('#'),
+            ..Default::default()
+        });
+        let json = r#"{"user": {"items": [{"0": "a"}, [true]]}}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "user.items#0.0=\"a\"\nuser.items#1#0=true\n"
+        );
+        assert_eq!(converter.convert("[1]").unwrap(), "0=1\n");
+
+        // JSON Pointers have a single separator
+        let converter = Converter::new().with_format(ToonOptions {
+            array_separator: Some('#'),
+            output_format: OutputFormat::JsonPointer,
+            ..Default::default()
+        });
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "/user/items/0/0 \"a\"\n/user/items/1/0 true\n"
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let converter = Converter::new();
+
+        let json = r#"{"a": {"b": [1, "x"]}, "c": null, "d": {}, "e": true}"#;
+        let stats = converter.stats(json).unwrap();
+
+        assert_eq!(stats.input_bytes, json.len());
+        assert_eq!(stats.key_count, 5);
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!(
+            stats.types,
+            TypeCounts {
+                string: 1,
+                number: 1,
+                bool: 1,
+                null: 1,
+                array: 0,
+                object: 1,
             }
+        );
+    }
+
+    #[test]
+    fn test_stats_add() {
+        let converter = Converter::new();
+        let mut total = converter.stats(r#"{"a": {"b": 1}}"#).unwrap();
+        total.add(&converter.stats(r#"["x", "y"]"#).unwrap());
+
+        assert_eq!(total.key_count, 3);
+        assert_eq!(total.max_depth, 2);
+        assert_eq!(total.types.number, 1);
+        assert_eq!(total.types.string, 2);
+    }
+
+    #[test]
+    fn test_array_root_key() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            array_root_key: "items".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(
+            converter.convert(r#"[{"id": 1}, [2]]"#).unwrap(),
+            "items.0.id=1\nitems.1.0=2\n"
+        );
+        assert_eq!(converter.convert("[]").unwrap(), "items=[]\n");
+        // Only arrays are nested
+        assert_eq!(converter.convert(r#"{"a": [1]}"#).unwrap(), "a.0=1\n");
+        assert_eq!(converter.convert_jsonl("[1]\n").unwrap(), "0.items.0=1\n");
+        assert!(
+            converter.estimate_size("[1, 2]").unwrap()
+                >= converter.convert("[1, 2]").unwrap().len()
+        );
+    }
+
+    #[test]
+    fn test_presence_keys() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            presence_keys: true,
+            skip_nulls: true,
+            ..Default::default()
+        });
+        let json = r#"{"a": null, "b": {"c": 1}, "d": [{"e": "x"}]}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "a.__present=true\nb.__present=true\nb.c.__present=true\nb.c=1\n\
+             d.__present=true\nd.0.e.__present=true\nd.0.e=\"x\"\n"
+        );
+        assert_eq!(converter.convert("{}").unwrap(), "={}\n");
+    }
+
+    #[test]
+    fn test_array_threshold() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            array_threshold: Some(2),
+            ..Default::default()
+        });
+        let json = r#"{"small": [1, 2], "big": [1, {"a": "x"}, [null]], "rows": [{"id": 1}]}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "small.0=1\nsmall.1=2\nbig=\"[1,{\\\"a\\\":\\\"x\\\"},[null]]\"\nrows.0.id=1\n"
+        );
+        // Arrays nested in arrays below the threshold are still checked
+        assert_eq!(converter.convert("[[1, 2, 3]]").unwrap(), "0=\"[1,2,3]\"\n");
+    }
+
+    #[test]
+    fn test_strip_prefix() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            strip_prefix: "config".to_string(),
+            ..Default::default()
+        });
+        let json =
+            r#"{"config": {"port": 80, "config": {"x": [1, 2]}}, "configured": true, "name": "a"}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "port=80\nconfig.x.0=1\nconfig.x.1=2\nconfigured=true\nname=\"a\"\n"
+        );
+        // A key equal to the prefix has no segment left to keep
+        assert_eq!(converter.convert(r#"{"config": 1}"#).unwrap(), "config=1\n");
+
+        let converter = Converter::new()
+            .with_format(ToonOptions {
+                output_format: OutputFormat::JsonPointer,
+                ..Default::default()
+            })
+            .with_options(ConvertOptions {
+                strip_prefix: "config/db".to_string(),
+                ..Default::default()
+            });
+        assert_eq!(
+            converter
+                .convert(r#"{"config": {"db": {"host": "x"}, "dbs": 1}}"#)
+                .unwrap(),
+            "/host \"x\"\n/config/dbs 1\n"
+        );
+    }
+
+    #[test]
+    fn test_quote_numeric_keys() {
+        let converter = Converter::new().with_format(ToonOptions {
+            quote_numeric_keys: true,
+            ..Default::default()
+        });
+        let json = r#"{"ids": {"0": "a", "12": ["b"], "x1": 1, "-1": 2}, "list": ["c"]}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "ids.\"0\"=\"a\"\nids.\"12\".0=\"b\"\nids.x1=1\nids.-1=2\nlist.0=\"c\"\n"
+        );
+        // Other quotes in keys are still escaped
+        assert_eq!(
+            converter.convert(r#"{"a\"b": {"7": 1}}"#).unwrap(),
+            "a\\\"b.\"7\"=1\n"
+        );
+        assert_eq!(
+            Converter::new().convert(r#"{"0": "a"}"#).unwrap(),
+            "0=\"a\"\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_embedded_json() {
+        let json = r#"{"event": "{\"user\": {\"id\": 7}, \"tags\": \"[\\\"a\\\"]\"}", "note": "[not json", "n": "42"}"#;
+        assert_eq!(
+            Converter::new()
+                .convert(json)
+                .unwrap()
+                .lines()
+                .next()
+                .unwrap(),
+            r#"event="{\"user\": {\"id\": 7}, \"tags\": \"[\\\"a\\\"]\"}""#
+        );
+
+        let converter = Converter::new().with_options(ConvertOptions {
+            parse_embedded_json: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "event.user.id=7\nevent.tags.0=\"a\"\nnote=\"[not json\"\nn=\"42\"\n"
+        );
+
+        // Embedded nesting counts towards the recursion limit
+        let converter = Converter::new().with_options(ConvertOptions {
+            parse_embedded_json: true,
+            max_recursion: 2,
+            ..Default::default()
+        });
+        assert!(converter.convert(r#"{"a": "{\"b\": 1}"}"#).is_ok());
+        assert!(matches!(
+            converter.convert(r#"{"a": "{\"b\": {\"c\": 1}}"}"#),
+            Err(ConvertError::TooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn test_source_lines() {
+        let json = "{\n  \"config\": {\n    \"port\": 8080,\n    \"hosts\": [\"a\",\n      \"b\"]\n  },\n  \"empty\": []\n}\n";
+        let converter = Converter::new().with_options(ConvertOptions {
+            source_lines: true,
+            key_case: Some(KeyCase::Upper),
+            ..Default::default()
+        });
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "CONFIG.PORT=8080  # L3\nCONFIG.HOSTS.0=\"a\"  # L4\nCONFIG.HOSTS.1=\"b\"  # L5\nEMPTY=[]  # L7\n"
+        );
+
+        let converter = Converter::new()
+            .with_options(ConvertOptions {
+                source_lines: true,
+                array_root_key: "rows".to_string(),
+                ..Default::default()
+            })
+            .with_format(ToonOptions {
+                tabular: true,
+                ..Default::default()
+            });
+        assert_eq!(
+            converter.convert("[\n{\"a\": 1},\n{\"a\": 2}\n]").unwrap(),
+            "rows[2]{a}:  # L1\n  1\n  2\n"
+        );
+    }
+
+    #[test]
+    fn test_preserved_float_style() {
+        let json = r#"{"price": 98.50, "qty": 5.0, "sizes": [1.50, 2E1], "id": 7}"#;
+        let converter = |float_style| {
+            Converter::new().with_format(ToonOptions {
+                float_style,
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            converter(FloatStyle::Preserve).convert(json).unwrap(),
+            "price=98.50\nqty=5.0\nsizes.0=1.50\nsizes.1=2E1\nid=7\n"
+        );
+        assert_eq!(
+            converter(FloatStyle::Trim).convert(json).unwrap(),
+            "price=98.5\nqty=5.0\nsizes.0=1.5\nsizes.1=20.0\nid=7\n"
+        );
+        // Each record or document is looked up in its own text
+        let preserving = converter(FloatStyle::Preserve);
+        assert_eq!(
+            preserving
+                .convert_jsonl("{\"a\": 2.50}\n{\"a\": 1.0}\n")
+                .unwrap(),
+            "0.a=2.50\n1.a=1.0\n"
+        );
+        assert_eq!(
+            preserving.convert_record(r#"{"a": 2.50}"#, 3).unwrap(),
+            "3.a=2.50\n"
+        );
+        assert_eq!(
+            preserving
+                .convert_multi_doc("{\"a\": 2.50}\n{\"a\": 7.10}[1.0]")
+                .unwrap(),
+            ("0.a=2.50\n1.a=7.10\n2.0=1.0\n".to_string(), 3)
+        );
+        // Without the JSON source, preserving falls back to trimming
+        let flat = converter(FloatStyle::Preserve).flatten(json).unwrap();
+        assert_eq!(flat["qty"], serde_json::json!(5.0));
+    }
+
+    #[test]
+    fn test_max_output_size() {
+        let json = r#"{"items": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]}"#;
+        let limited = |limit| {
+            Converter::new().with_options(ConvertOptions {
+                max_output_size: Some(limit),
+                ..Default::default()
+            })
+        };
+        let len = Converter::new().convert(json).unwrap().len();
+        assert_eq!(limited(len).convert(json).unwrap().len(), len);
+        assert!(matches!(
+            limited(len - 1).convert(json),
+            Err(ConvertError::OutputTooLarge { limit }) if limit == len - 1
+        ));
+        // Stopped as the output grows, not only once it is complete
+        let mut writer = ToonWriter::new();
+        let value: Value = serde_json::from_str(json).unwrap();
+        assert!(limited(20).convert_root(&mut writer, "", &value).is_err());
+        assert!(writer.finish().len() < len);
+
+        let jsonl = "{\"a\": 1}\n{\"a\": 2}\n";
+        assert!(limited(11).convert_jsonl(jsonl).is_err());
+        assert!(limited(12).convert_jsonl(jsonl).is_ok());
+    }
+
+    /// A custom format: one line per event, with containers bracketed
+    #[derive(Default)]
+    struct EventLog(Vec<String>);
+
+    impl ValueSink for EventLog {
+        fn write_string(&mut self, key: &str, value: &str) {
+            self.0.push(format!("{} {:?}", key, value));
+        }
+        fn write_number(&mut self, key: &str, value: f64) {
+            self.0.push(format!("{} {}", key, value));
+        }
+        fn write_bool(&mut self, key: &str, value: bool) {
+            self.0.push(format!("{} {}", key, value));
+        }
+        fn write_null(&mut self, key: &str) {
+            self.0.push(format!("{} null", key));
+        }
+        fn write_empty_array(&mut self, key: &str) {
+            self.0.push(format!("{} []", key));
+        }
+        fn write_empty_object(&mut self, key: &str) {
+            self.0.push(format!("{} {{}}", key));
+        }
+        fn begin_array(&mut self, key: &str) {
+            self.0.push(format!("{} [", key));
+        }
+        fn end_array(&mut self, key: &str) {
+            self.0.push(format!("] {}", key));
+        }
+        fn begin_object(&mut self, key: &str) {
+            self.0.push(format!("{} {{", key));
+        }
+        fn end_object(&mut self, key: &str) {
+            self.0.push(format!("}} {}", key));
+        }
+    }
+
+    #[test]
+    fn test_custom_sink() {
+        let value = serde_json::json!({"a": [1, {}], "b": {"c": null}, "d": "x"});
+        let mut log = EventLog::default();
+        let sink: &mut dyn ValueSink = &mut log;
+        Converter::new().write_entries(&value, sink).unwrap();
+        assert_eq!(
+            log.0,
+            [
+                " {", "a [", "a.0 1", "a.1 {}", "] a", "b {", "b.c null", "} b", "d \"x\"", "} "
+            ]
+        );
+
+        // Tables are not bracketed
+        let converter = Converter::new().with_format(ToonOptions {
+            tabular: true,
+            ..Default::default()
+        });
+        let mut writer = ToonWriter::new();
+        let sink: &mut dyn ValueSink = &mut writer;
+        converter
+            .write_entries(&serde_json::json!([{"id": 1}]), sink)
+            .unwrap();
+        assert_eq!(writer.finish(), "[1]{id}:\n  1\n");
+    }
+
+    #[test]
+    fn test_write_value() {
+        let json = r#"{"user": {"name": "Bob", "tags": ["a", 1]}}"#;
+        let value: Value = serde_json::from_str(json).unwrap();
+        let converter = Converter::new();
+
+        let mut out = String::from("# header\n");
+        converter.write_value(&value, &mut out).unwrap();
+        assert_eq!(
+            out,
+            format!("# header\n{}", converter.convert(json).unwrap())
+        );
+
+        let mut keys = KeyCollector::default();
+        converter.write_entries(&value, &mut keys).unwrap();
+        assert_eq!(
+            keys.keys.into_iter().collect::<Vec<_>>(),
+            ["user.name", "user.tags.0", "user.tags.1"]
+        );
+
+        // Pre-parsed values are still limited by max_recursion
+        let converter = Converter::new().with_options(ConvertOptions {
+            max_recursion: 3,
+            ..Default::default()
+        });
+        let deep = serde_json::json!([[[[1]]]]);
+        assert!(matches!(
+            converter.write_value(&deep, &mut String::new()),
+            Err(ConvertError::TooDeep { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dedup_values() {
+        let json = r#"{
+            "a": {"url": "https://example.com/api", "env": "prod"},
+            "b": {"url": "https://example.com/api", "env": "prod"},
+            "c": ["https://example.com/api", "x", "x"],
+            "d": "once only"
         }"#;
-        let toon = converter.convert(json).unwrap();
+        let converter = Converter::new().with_options(ConvertOptions {
+            dedup_values: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "&v1=\"https://example.com/api\"\n&v2=\"prod\"\n\n\
+             a.url=&v1\na.env=&v2\nb.url=&v1\nb.env=&v2\n\
+             c.0=&v1\nc.1=\"x\"\nc.2=\"x\"\nd=\"once only\"\n"
+        );
+        // Nothing repeats, so there is no table
+        assert_eq!(
+            converter.convert(r#"{"a": "xyzzy"}"#).unwrap(),
+            "a=\"xyzzy\"\n"
+        );
+    }
 
-        assert!(toon.contains("name=\"Project\"\n"));
-        assert!(toon.contains("version=\"1.0.0\"\n"));
-        assert!(toon.contains("authors.0=\"Alice\"\n"));
-        assert!(toon.contains("authors.1=\"Bob\"\n"));
-        assert!(toon.contains("config.debug=true\n"));
-        assert!(toon.contains("config.timeout=30\n"));
+    #[test]
+    fn test_try_relaxed() {
+        let json = "{'a': 1, // note\n 'b': [1, 2,],}";
+        assert!(matches!(
+            Converter::new().convert(json),
+            Err(ConvertError::Parse { .. })
+        ));
+
+        let converter = Converter::new().with_options(ConvertOptions {
+            try_relaxed: true,
+            ..Default::default()
+        });
+        assert_eq!(converter.convert(json).unwrap(), "a=1\nb.0=1\nb.1=2\n");
+        // Input that is not even relaxed JSON reports the strict error
+        match converter.convert("{'a': }") {
+            Err(ConvertError::Parse { source, .. }) => {
+                assert!(source.to_string().contains("key must be a string"))
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        // Number literals are found in the strict rewrite of the input
+        let converter = Converter::new()
+            .with_options(ConvertOptions {
+                try_relaxed: true,
+                ..Default::default()
+            })
+            .with_format(ToonOptions {
+                float_style: FloatStyle::Preserve,
+                ..Default::default()
+            });
+        assert_eq!(
+            converter.convert("{'a': 1.50, 'é': [2.0,],} // é").unwrap(),
+            "a=1.50
+é.0=2.0
+"
+        );
     }
 
     #[test]
-    fn test_invalid_json() {
-        let converter = Converter::new(false);
+    fn test_sample() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            sample: Some(2),
+            ..Default::default()
+        });
+        let json = r#"{"items": [{"id": 1, "tags": ["a", "b", "c"]}, {"id": 2}, {"id": 3}], "pair": [1, 2]}"#;
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "items.0.id=1\nitems.0.tags.0=\"a\"\nitems.0.tags.1=\"b\"\nitems.0.tags.__truncated=true\n\
+             items.1.id=2\nitems.__truncated=true\npair.0=1\npair.1=2\n"
+        );
+        assert_eq!(
+            converter.convert("[1, 2, 3]").unwrap(),
+            "0=1\n1=2\n__truncated=true\n"
+        );
 
-        let json = r#"{"invalid": json}"#;
-        let result = converter.convert(json);
+        // Tables are cut short too
+        let converter = Converter::new()
+            .with_options(ConvertOptions {
+                sample: Some(1),
+                ..Default::default()
+            })
+            .with_format(ToonOptions {
+                tabular: true,
+                ..Default::default()
+            });
+        let toon = converter
+            .convert(r#"{"rows": [{"a": 1}, {"a": 2}]}"#)
+            .unwrap();
+        assert!(toon.ends_with("rows.__truncated=true\n"), "{}", toon);
+        assert!(!toon.contains('2'), "{}", toon);
+    }
 
-        assert!(result.is_err());
+    #[test]
+    fn test_emit_root_type() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            emit_root_type: true,
+            ..Default::default()
+        });
+        assert_eq!(converter.convert("[1]").unwrap(), "#root=array\n0=1\n");
+        assert_eq!(converter.convert(r#"{"0": 1}"#).unwrap(), "0=1\n");
+        assert_eq!(converter.convert("true").unwrap(), "#root=bool\n=true\n");
+        assert_eq!(converter.convert("null").unwrap(), "#root=null\n=null\n");
+        // Records of JSONL input are not documents of their own
+        assert_eq!(converter.convert_jsonl("[1]\n").unwrap(), "0.0=1\n");
     }
 
     #[test]
-    fn test_top_level_array() {
-        let converter = Converter::new(false);
+    fn test_numeric_key_order() {
+        let json = r#"{"10": "a", "2": "b", "-1": {"3": 1, "003": 2, "1": 3}, "07": "c"}"#;
+        let converter = Converter::new();
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "10=\"a\"\n2=\"b\"\n-1.3=1\n-1.003=2\n-1.1=3\n07=\"c\"\n"
+        );
 
-        let json = r#"[1, 2, 3]"#;
-        let toon = converter.convert(json).unwrap();
+        let converter = Converter::new().with_options(ConvertOptions {
+            numeric_key_order: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            converter.convert(json).unwrap(),
+            "-1.1=3\n-1.3=1\n-1.003=2\n2=\"b\"\n07=\"c\"\n10=\"a\"\n"
+        );
+        // A single non-integer key keeps the source order
+        assert_eq!(
+            converter.convert(r#"{"10": 1, "2": 2, "x": 3}"#).unwrap(),
+            "10=1\n2=2\nx=3\n"
+        );
+        assert_eq!(integer_key("+1"), None);
+        assert_eq!(integer_key("-"), None);
+        assert_eq!(integer_key("1e3"), None);
+    }
 
-        assert!(toon.contains("0=1\n"));
-        assert!(toon.contains("1=2\n"));
-        assert!(toon.contains("2=3\n"));
+    #[test]
+    fn test_key_case() {
+        let converter = Converter::new().with_options(ConvertOptions {
+            key_case: Some(KeyCase::Snake),
+            ..Default::default()
+        });
+
+        let json = r#"{"userName": {"HomeAddress": [{"zipCode": 1}]}}"#;
+        let toon = converter.convert(json).unwrap();
+        assert_eq!(toon, "user_name.home_address.0.zip_code=1\n");
     }
 
     #[test]
-    fn test_top_level_primitive() {
-        let converter = Converter::new(false);
+    fn test_count_entries() {
+        let converter = Converter::new();
 
-        let json = r#""hello""#;
+        let json = r#"{"name": "Alice", "tags": ["a", "b"], "meta": {}, "items": []}"#;
+        let count = converter.count_entries(json).unwrap();
         let toon = converter.convert(json).unwrap();
 
-        assert_eq!(toon, "=\"hello\"\n");
+        assert_eq!(count, 5);
+        assert_eq!(count, toon.lines().count());
     }
 }
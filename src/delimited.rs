@@ -0,0 +1,124 @@
+//! Two-column `key,value` output for spreadsheets and dataframes
+
+use crate::converter::ValueSink;
+use crate::toon::{self, EntryValue, ToonOptions};
+
+/// Writer producing one delimited `key,value` record per entry
+///
+/// Values are rendered exactly as they appear after `=` in TOON output, so
+/// strings keep their quotes and numbers, booleans and null stay bare. The
+/// `csv` crate then quotes each field as needed for the delimiter.
+pub struct DelimitedWriter {
+    writer: csv::Writer<Vec<u8>>,
+    options: ToonOptions,
+    /// Reused buffer for rendering the value column
+    value: String,
+}
+
+impl DelimitedWriter {
+    pub fn new(delimiter: u8, options: ToonOptions) -> Self {
+        let writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .terminator(csv::Terminator::Any(b'\n'))
+            .from_writer(Vec::new());
+        Self {
+            writer,
+            options,
+            value: String::new(),
+        }
+    }
+
+    fn write_record(&mut self, key: &str, value: EntryValue) {
+        self.value.clear();
+        value.write_to(&mut self.value, &self.options);
+        self.writer
+            .write_record([key, self.value.as_str()])
+            .expect("writing CSV to memory cannot fail");
+    }
+
+    pub fn finish(self) -> String {
+        let bytes = self
+            .writer
+            .into_inner()
+            .expect("flushing CSV to memory cannot fail");
+        String::from_utf8(bytes).expect("CSV fields are valid UTF-8")
+    }
+}
+
+impl ValueSink for DelimitedWriter {
+    fn write_string(&mut self, key: &str, value: &str) {
+        let text = toon::string_text(value, &self.options);
+        let value = EntryValue::string(&text, &self.options);
+        self.write_record(key, value);
+    }
+
+    fn write_number(&mut self, key: &str, value: f64) {
+        let value = EntryValue::number(value, &self.options);
+        self.write_record(key, value);
+    }
+
+    fn write_integer(&mut self, key: &str, value: i128) {
+        let value = EntryValue::integer(value, &self.options);
+        self.write_record(key, value);
+    }
+
+    fn write_bool(&mut self, key: &str, value: bool) {
+        let value = EntryValue::bool(value, &self.options);
+        self.write_record(key, value);
+    }
+
+    fn write_null(&mut self, key: &str) {
+        self.write_record(key, EntryValue::Raw("null"));
+    }
+
+    fn write_empty_array(&mut self, key: &str) {
+        let token = self.options.empty_array_token.clone();
+        self.write_record(key, EntryValue::Raw(&token));
+    }
+
+    fn write_empty_object(&mut self, key: &str) {
+        let token = self.options.empty_object_token.clone();
+        self.write_record(key, EntryValue::Raw(&token));
+    }
+
+    /// Excludes records still in the CSV writer's buffer, so it can lag a little
+    fn output_len(&self) -> Option<usize> {
+        Some(self.writer.get_ref().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_quoting() {
+        let mut writer = DelimitedWriter::new(b',', ToonOptions::default());
+        writer.write_string("user.name", "Bob");
+        writer.write_number("user.age", 30.0);
+        writer.write_string("note", "a,b");
+        writer.write_null("middle");
+
+        assert_eq!(
+            writer.finish(),
+            "user.name,\"\"\"Bob\"\"\"\nuser.age,30\nnote,\"\"\"a,b\"\"\"\nmiddle,null\n"
+        );
+    }
+
+    #[test]
+    fn test_tsv() {
+        let options = ToonOptions {
+            unquoted_safe_strings: true,
+            ..Default::default()
+        };
+        let mut writer = DelimitedWriter::new(b'\t', options);
+        writer.write_string("name", "Alice");
+        writer.write_bool("active", true);
+        writer.write_empty_array("tags");
+
+        assert_eq!(
+            writer.finish(),
+            "name\tAlice\nactive\ttrue\ntags\t[]\n"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use owo_colors::OwoColorize;
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// When to colorize diagnostic output
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colorize when stderr is a terminal and NO_COLOR is unset
+    Auto,
+    /// Always colorize
+    Always,
+    /// Never colorize
+    Never,
+}
+
+/// Whether diagnostic tags are colorized (cyan info, green success, yellow warning, red error)
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Configure colorization from the `--color` flag and the environment
+pub fn init(choice: ColorChoice) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let enabled = should_color(choice, no_color, std::io::stderr().is_terminal());
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn should_color(choice: ColorChoice, no_color: bool, is_tty: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !no_color && is_tty,
+    }
+}
+
+fn color_enabled() -> bool {
+    COLOR_ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn info_tag() -> String {
+    if color_enabled() {
+        "[INFO]".cyan().to_string()
+    } else {
+        "[INFO]".to_string()
+    }
+}
+
+pub fn success_tag() -> String {
+    if color_enabled() {
+        "[SUCCESS]".green().to_string()
+    } else {
+        "[SUCCESS]".to_string()
+    }
+}
+
+pub fn error_tag() -> String {
+    if color_enabled() {
+        "[ERROR]".red().to_string()
+    } else {
+        "[ERROR]".to_string()
+    }
+}
+
+pub fn warn_tag() -> String {
+    if color_enabled() {
+        "[WARN]".yellow().to_string()
+    } else {
+        "[WARN]".to_string()
+    }
+}
+
+/// Tag for a log record, e.g. `[INFO]`; debug and trace tags are never colorized
+pub fn level_tag(level: log::Level) -> String {
+    match level {
+        log::Level::Error => error_tag(),
+        log::Level::Warn => warn_tag(),
+        log::Level::Info => info_tag(),
+        log::Level::Debug => "[DEBUG]".to_string(),
+        log::Level::Trace => "[TRACE]".to_string(),
+    }
+}
+
+/// Print an `[INFO]` diagnostic to stderr
+pub fn info(msg: impl Display) {
+    eprintln!("{} {}", info_tag(), msg);
+}
+
+/// Print a `[SUCCESS]` diagnostic to stderr
+pub fn success(msg: impl Display) {
+    eprintln!("{} {}", success_tag(), msg);
+}
+
+/// Print an `[ERROR]` diagnostic to stderr
+pub fn error(msg: impl Display) {
+    eprintln!("{} {}", error_tag(), msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_color_auto() {
+        assert!(should_color(ColorChoice::Auto, false, true));
+        assert!(!should_color(ColorChoice::Auto, false, false));
+        assert!(!should_color(ColorChoice::Auto, true, true));
+    }
+
+    #[test]
+    fn test_should_color_explicit() {
+        assert!(should_color(ColorChoice::Always, true, false));
+        assert!(!should_color(ColorChoice::Never, false, true));
+    }
+}
@@ -0,0 +1,98 @@
+//! Differences between the flattened entries of two JSON documents, for `json2toon diff`
+
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+use core::fmt;
+use serde_json::Value;
+
+/// A single difference between two flattened documents
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added { key: String, value: Value },
+    Removed { key: String, value: Value },
+    Changed { key: String, old: Value, new: Value },
+}
+
+impl Change {
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Added { key, .. }
+            | Change::Removed { key, .. }
+            | Change::Changed { key, .. } => key,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { key, value } => write!(f, "+ {}={}", key, value),
+            Change::Removed { key, value } => write!(f, "- {}={}", key, value),
+            Change::Changed { key, old, new } => write!(f, "~ {}: {} -> {}", key, old, new),
+        }
+    }
+}
+
+/// Compare two flattened documents, returning changes ordered by key
+pub fn diff_entries(old: &BTreeMap<String, Value>, new: &BTreeMap<String, Value>) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (key, old_value) in old {
+        match new.get(key) {
+            None => changes.push(Change::Removed {
+                key: key.clone(),
+                value: old_value.clone(),
+            }),
+            Some(new_value) if new_value != old_value => changes.push(Change::Changed {
+                key: key.clone(),
+                old: old_value.clone(),
+                new: new_value.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            changes.push(Change::Added {
+                key: key.clone(),
+                value: new_value.clone(),
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.key().cmp(b.key()));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entries(pairs: &[(&str, Value)]) -> BTreeMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_entries() {
+        let old = entries(&[("a", json!(1)), ("b", json!("x")), ("c", json!(true))]);
+        let new = entries(&[("a", json!(1)), ("b", json!("y")), ("d", json!(null))]);
+
+        let changes: Vec<String> = diff_entries(&old, &new)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+
+        assert_eq!(changes, ["~ b: \"x\" -> \"y\"", "- c=true", "+ d=null"]);
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let old = entries(&[("a", json!([]))]);
+        assert!(diff_entries(&old, &old.clone()).is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+//! Re-encoding of the output text for `--output-encoding`
+
+use crate::cli::OutputEncoding;
+use std::borrow::Cow;
+
+/// Encode the output text, with a byte order mark for UTF-16
+pub fn encode(encoding: OutputEncoding, text: &str) -> Cow<'_, [u8]> {
+    StreamEncoder::new(encoding).encode(text)
+}
+
+/// Encoder for output written in pieces, e.g. streamed JSON Lines records
+///
+/// The pieces' bytes join up to the encoding of the whole text, so the
+/// UTF-16 byte order mark only comes before the first.
+pub struct StreamEncoder {
+    encoding: OutputEncoding,
+    started: bool,
+}
+
+impl StreamEncoder {
+    pub fn new(encoding: OutputEncoding) -> Self {
+        Self {
+            encoding,
+            started: false,
+        }
+    }
+
+    /// Encode the next piece of the output
+    pub fn encode<'a>(&mut self, text: &'a str) -> Cow<'a, [u8]> {
+        let to_bytes: fn(u16) -> [u8; 2] = match self.encoding {
+            OutputEncoding::Utf8 => return Cow::Borrowed(text.as_bytes()),
+            OutputEncoding::Utf16le => u16::to_le_bytes,
+            OutputEncoding::Utf16be => u16::to_be_bytes,
+        };
+        let bom = (!self.started).then_some(0xFEFF);
+        self.started = true;
+        let mut bytes = Vec::with_capacity(2 * (text.len() + 1));
+        for unit in bom.into_iter().chain(text.encode_utf16()) {
+            bytes.extend_from_slice(&to_bytes(unit));
+        }
+        Cow::Owned(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(OutputEncoding::Utf8, "a=1\n").as_ref(), b"a=1\n");
+        assert_eq!(
+            encode(OutputEncoding::Utf16le, "a=\u{e9}").as_ref(),
+            [0xFF, 0xFE, b'a', 0, b'=', 0, 0xE9, 0]
+        );
+        // Outside the BMP as a surrogate pair
+        assert_eq!(
+            encode(OutputEncoding::Utf16be, "\u{1F600}").as_ref(),
+            [0xFE, 0xFF, 0xD8, 0x3D, 0xDE, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_stream_encoder() {
+        let mut encoder = StreamEncoder::new(OutputEncoding::Utf16le);
+        let mut bytes = encoder.encode("0.a=1\n").into_owned();
+        bytes.extend_from_slice(&encoder.encode("1.a=2\n"));
+        assert_eq!(bytes, *encode(OutputEncoding::Utf16le, "0.a=1\n1.a=2\n"));
+    }
+}
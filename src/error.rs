@@ -0,0 +1,125 @@
+//! Error type returned by the conversion library
+
+use crate::prelude::*;
+use core::error::Error;
+use thiserror::Error;
+
+/// Result alias for fallible conversion operations
+pub type Result<T, E = ConvertError> = core::result::Result<T, E>;
+
+/// Why a document could not be converted
+#[derive(Error, Debug)]
+pub enum ConvertError {
+    /// The input was empty or whitespace-only and empty input is not allowed
+    #[error("Input is empty")]
+    Empty,
+
+    /// The input is not valid for its input format
+    #[error("Failed to parse {format}")]
+    Parse {
+        /// Name of the input format, e.g. `JSON`
+        format: &'static str,
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+
+    /// A number cannot be represented, e.g. TOML `nan` or YAML `.inf`
+    #[error("Non-finite number at {}: {value}", display_key(key))]
+    NonFiniteNumber { key: String, value: String },
+
+    /// An object key cannot be turned into a flattened key segment
+    #[error("Invalid key at {}: {reason}", display_key(key))]
+    InvalidKey { key: String, reason: String },
+
+    /// Two source keys map to the same flattened key
+    #[error("Duplicate key: {}", display_key(key))]
+    DuplicateKey { key: String },
+
+    /// A `--coerce` rule matched a value that cannot take its type
+    #[error("Cannot coerce {value} at {} to {target}", display_key(key))]
+    Coerce {
+        key: String,
+        value: String,
+        target: &'static str,
+    },
+
+    /// A `--types` rule matched a value of another JSON type that cannot be coerced to it
+    #[error("Expected {expected} at {}, found {found}", display_key(key))]
+    WrongType {
+        key: String,
+        expected: &'static str,
+        found: String,
+    },
+
+    /// Containers are nested deeper than the configured `max_recursion`
+    #[error("Input is nested more than {limit} levels deep")]
+    TooDeep { limit: usize },
+
+    /// The output grew past the configured `max_output_size`
+    #[error("Output exceeds the limit of {limit} bytes")]
+    OutputTooLarge { limit: usize },
+
+    /// `--expand-env` found a placeholder for a variable that is not set
+    #[error("Environment variable {name} is not set (at {})", display_key(key))]
+    MissingVar { key: String, name: String },
+
+    /// The destination of `Converter::write_value` refused the output
+    #[error("Failed to write output")]
+    Write(#[from] core::fmt::Error),
+
+    /// A JSON Lines record failed to convert
+    #[error("Failed to convert JSONL record on line {line}")]
+    Record {
+        line: usize,
+        #[source]
+        source: Box<ConvertError>,
+    },
+
+    /// One of several concatenated JSON documents failed to convert
+    #[error("Failed to convert document {index}")]
+    Document {
+        index: usize,
+        #[source]
+        source: Box<ConvertError>,
+    },
+}
+
+impl ConvertError {
+    pub(crate) fn parse(format: &'static str, source: impl Error + Send + Sync + 'static) -> Self {
+        ConvertError::Parse {
+            format,
+            source: Box::new(source),
+        }
+    }
+}
+
+/// Flattened key for messages, `<root>` for the top-level value
+fn display_key(key: &str) -> &str {
+    if key.is_empty() { "<root>" } else { key }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let err = ConvertError::NonFiniteNumber {
+            key: "a.b".to_string(),
+            value: "nan".to_string(),
+        };
+        assert_eq!(err.to_string(), "Non-finite number at a.b: nan");
+
+        let err = ConvertError::DuplicateKey { key: String::new() };
+        assert_eq!(err.to_string(), "Duplicate key: <root>");
+    }
+
+    #[test]
+    fn test_parse_error_keeps_source() {
+        let json_err = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let err = ConvertError::parse("JSON", json_err);
+
+        let source = err.source().expect("parse errors have a source");
+        assert!(source.downcast_ref::<serde_json::Error>().is_some());
+    }
+}
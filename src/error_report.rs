@@ -0,0 +1,92 @@
+use serde::Serialize;
+
+/// Machine-readable error written to stderr with `--error-format json`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ErrorReport {
+    /// Error category: `parse`, `io`, or `conversion`
+    pub error: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl ErrorReport {
+    /// Classify an error by the first JSON or I/O error in its cause chain
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        let message = format!("{:#}", err);
+
+        if let Some(json_err) = err
+            .chain()
+            .find_map(|e| e.downcast_ref::<serde_json::Error>())
+        {
+            let kind = if json_err.is_io() { "io" } else { "parse" };
+            return Self {
+                error: kind,
+                message,
+                line: Some(json_err.line()),
+                column: Some(json_err.column()),
+            };
+        }
+
+        let kind = if err.chain().any(|e| e.is::<std::io::Error>()) {
+            "io"
+        } else {
+            "conversion"
+        };
+        Self {
+            error: kind,
+            message,
+            line: None,
+            column: None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("error report serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_parse_error_report() {
+        let err = serde_json::from_str::<serde_json::Value>("{\n  \"a\": nope\n}")
+            .context("Failed to parse JSON")
+            .unwrap_err();
+        let report = ErrorReport::from_error(&err);
+
+        assert_eq!(report.error, "parse");
+        assert_eq!(report.line, Some(2));
+        assert_eq!(report.column, Some(9));
+        assert!(report.message.starts_with("Failed to parse JSON: "));
+    }
+
+    #[test]
+    fn test_io_error_report() {
+        let err = std::fs::read_to_string("/nonexistent/input.json")
+            .context("Failed to read input file")
+            .unwrap_err();
+        let report = ErrorReport::from_error(&err);
+
+        assert_eq!(report.error, "io");
+        assert_eq!(
+            report.to_json(),
+            format!(
+                "{{\"error\":\"io\",\"message\":{}}}",
+                serde_json::to_string(&report.message).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_other_error_report() {
+        let report = ErrorReport::from_error(&anyhow::anyhow!("Invalid number: 1e999"));
+        assert_eq!(report.error, "conversion");
+        assert_eq!(report.line, None);
+    }
+}
@@ -0,0 +1,113 @@
+//! Commands run after each successful conversion for `--on-success`
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// A command template with `{input}` and `{output}` placeholders
+///
+/// The template is split into words like a shell would split a simple
+/// command (whitespace, with `'...'` and `"..."` quoting), but it is run
+/// directly rather than through a shell, so substituted paths are always a
+/// single argument however they are spelled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    words: Vec<String>,
+}
+
+impl Hook {
+    /// Parse a template such as `git add {output}`
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let words = split_words(template)?;
+        if words.is_empty() {
+            return Err("hook command is empty".to_string());
+        }
+        Ok(Self { words })
+    }
+
+    /// Run the command for one conversion, failing if it cannot start or exits non-zero
+    ///
+    /// `input` is `-` when the input was read from stdin.
+    pub fn run(&self, input: &Path, output: &Path) -> Result<()> {
+        let words = self.substitute(input, output);
+        let display = words.join(" ");
+        log::info!("Running --on-success hook: {}", display);
+
+        let status = Command::new(&words[0])
+            .args(&words[1..])
+            .status()
+            .with_context(|| format!("Failed to run --on-success hook: {}", display))?;
+        if !status.success() {
+            anyhow::bail!("--on-success hook `{}` failed ({})", display, status);
+        }
+        Ok(())
+    }
+
+    fn substitute(&self, input: &Path, output: &Path) -> Vec<String> {
+        let (input, output) = (input.to_string_lossy(), output.to_string_lossy());
+        self.words
+            .iter()
+            .map(|word| word.replace("{input}", &input).replace("{output}", &output))
+            .collect()
+    }
+}
+
+/// Split on unquoted whitespace; quotes group words and are removed
+fn split_words(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+
+    for ch in text.chars() {
+        match (quote, ch) {
+            (Some(open), ch) if ch == open => quote = None,
+            (Some(_), ch) => word.get_or_insert_default().push(ch),
+            (None, '\'' | '"') => {
+                quote = Some(ch);
+                word.get_or_insert_default();
+            }
+            (None, ch) if ch.is_whitespace() => words.extend(word.take()),
+            (None, ch) => word.get_or_insert_default().push(ch),
+        }
+    }
+    if let Some(open) = quote {
+        return Err(format!("unclosed {} in hook command", open));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(split_words("git add {output}").unwrap(), ["git", "add", "{output}"]);
+        assert_eq!(
+            split_words(r#"  sh -c 'cat "$1"' x  "" "#).unwrap(),
+            ["sh", "-c", "cat \"$1\"", "x", ""]
+        );
+        assert!(split_words("echo 'oops").is_err());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let hook = Hook::parse("cp {output} 'backup/{input}.bak'").unwrap();
+        let words = hook.substitute(Path::new("my data.json"), Path::new("my data.toon"));
+        assert_eq!(words, ["cp", "my data.toon", "backup/my data.json.bak"]);
+        assert!(Hook::parse("  ").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let (input, output) = (Path::new("in.json"), Path::new("out.toon"));
+        assert!(Hook::parse("test {output} = out.toon").unwrap().run(input, output).is_ok());
+
+        let err = Hook::parse("test {input} = other").unwrap().run(input, output).unwrap_err();
+        assert!(err.to_string().contains("test in.json = other"), "{}", err);
+
+        assert!(Hook::parse("/nonexistent/hook").unwrap().run(input, output).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Parsing of non-JSON input formats into a JSON tree
+//!
+//! TOML and YAML are converted value by value rather than through serde so
+//! that values JSON cannot represent are reported instead of silently
+//! becoming `null`. Error locations use the default key separator.
+
+use crate::converter::child_key;
+use crate::error::{ConvertError, Result};
+use crate::toon::SEPARATOR;
+use serde_json::{Map, Number, Value};
+
+/// Parse TOML into a JSON tree; datetimes become strings
+pub(crate) fn parse_toml(input: &str) -> Result<Value> {
+    let table: toml::Table = input.parse().map_err(|e| ConvertError::parse("TOML", e))?;
+    toml_to_json(toml::Value::Table(table), "")
+}
+
+/// Parse YAML into a JSON tree; scalar mapping keys become strings
+pub(crate) fn parse_yaml(input: &str) -> Result<Value> {
+    let value: serde_yaml::Value =
+        serde_yaml::from_str(input).map_err(|e| ConvertError::parse("YAML", e))?;
+    yaml_to_json(value, "")
+}
+
+fn float_to_json(value: f64, key: &str) -> Result<Value> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or_else(|| ConvertError::NonFiniteNumber {
+            key: key.to_string(),
+            value: value.to_string(),
+        })
+}
+
+fn toml_to_json(value: toml::Value, key: &str) -> Result<Value> {
+    Ok(match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => float_to_json(f, key)?,
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(dt) => Value::String(dt.to_string()),
+        toml::Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| toml_to_json(item, &child_key(key, &i.to_string(), SEPARATOR)))
+                .collect::<Result<_>>()?,
+        ),
+        toml::Value::Table(table) => {
+            let mut map = Map::new();
+            for (name, item) in table {
+                let item = toml_to_json(item, &child_key(key, &name, SEPARATOR))?;
+                map.insert(name, item);
+            }
+            Value::Object(map)
+        }
+    })
+}
+
+fn yaml_to_json(value: serde_yaml::Value, key: &str) -> Result<Value> {
+    use serde_yaml::Value as Yaml;
+
+    Ok(match value {
+        Yaml::Null => Value::Null,
+        Yaml::Bool(b) => Value::Bool(b),
+        Yaml::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Value::from(i)
+            } else if let Some(u) = n.as_u64() {
+                Value::from(u)
+            } else {
+                float_to_json(n.as_f64().unwrap_or(f64::NAN), key)?
+            }
+        }
+        Yaml::String(s) => Value::String(s),
+        Yaml::Sequence(items) => Value::Array(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| yaml_to_json(item, &child_key(key, &i.to_string(), SEPARATOR)))
+                .collect::<Result<_>>()?,
+        ),
+        Yaml::Mapping(mapping) => {
+            let mut map = Map::new();
+            for (name, item) in mapping {
+                let name = match name {
+                    Yaml::String(s) => s,
+                    Yaml::Number(n) => n.to_string(),
+                    Yaml::Bool(b) => b.to_string(),
+                    _ => {
+                        return Err(ConvertError::InvalidKey {
+                            key: key.to_string(),
+                            reason: "mapping keys must be strings, numbers or booleans".to_string(),
+                        });
+                    }
+                };
+                let child = child_key(key, &name, SEPARATOR);
+                // e.g. `1: a` and `"1": b` both flatten to `1`
+                if map.contains_key(&name) {
+                    return Err(ConvertError::DuplicateKey { key: child });
+                }
+                let item = yaml_to_json(item, &child)?;
+                map.insert(name, item);
+            }
+            Value::Object(map)
+        }
+        // Tags such as `!!binary` carry no meaning in the flattened output
+        Yaml::Tagged(tagged) => yaml_to_json(tagged.value, key)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_non_finite_float() {
+        let err = parse_toml("[server]\nratio = nan\n").unwrap_err();
+        assert!(
+            matches!(err, ConvertError::NonFiniteNumber { ref key, .. } if key == "server.ratio")
+        );
+    }
+
+    #[test]
+    fn test_toml_datetime() {
+        let value = parse_toml("at = 1979-05-27T07:32:00Z\n").unwrap();
+        assert_eq!(value["at"], "1979-05-27T07:32:00Z");
+    }
+
+    #[test]
+    fn test_yaml_scalar_keys() {
+        let value = parse_yaml("1: one\ntrue: yes\n").unwrap();
+        assert_eq!(value["1"], "one");
+        assert_eq!(value["true"], "yes");
+    }
+
+    #[test]
+    fn test_yaml_invalid_and_duplicate_keys() {
+        let err = parse_yaml("a:\n  [1, 2]: x\n").unwrap_err();
+        assert!(matches!(err, ConvertError::InvalidKey { ref key, .. } if key == "a"));
+
+        let err = parse_yaml("1: a\n\"1\": b\n").unwrap_err();
+        assert!(matches!(err, ConvertError::DuplicateKey { ref key } if key == "1"));
+
+        let err = parse_yaml("x: .inf\n").unwrap_err();
+        assert!(matches!(err, ConvertError::NonFiniteNumber { .. }));
+    }
+}
@@ -0,0 +1,238 @@
+//! `${VAR}` interpolation into parsed documents for `--expand-env`
+
+use crate::converter::child_key;
+use crate::error::{ConvertError, Result};
+use crate::prelude::*;
+use alloc::borrow::Cow;
+use serde_json::{Map, Value};
+
+/// What to substitute for a variable that is not set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingVar {
+    /// Fail the conversion
+    #[default]
+    Error,
+    /// Substitute an empty string
+    Empty,
+    /// Leave the `${VAR}` placeholder as written
+    Keep,
+}
+
+/// Settings for expanding `${VAR}` placeholders
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Interpolation {
+    /// Expand placeholders in object keys as well as string values
+    pub keys: bool,
+    pub missing: MissingVar,
+}
+
+impl Interpolation {
+    /// Expand placeholders throughout a parsed document, resolving names with `lookup`
+    ///
+    /// `$${` is written as a literal `${`. Placeholders that are unterminated or
+    /// whose name is not an identifier are left alone. Errors name the
+    /// flattened key (joined with `separator`) of the value being expanded.
+    pub fn apply(
+        &self,
+        value: &mut Value,
+        separator: char,
+        lookup: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<()> {
+        self.apply_at(value, "", separator, lookup)
+    }
+
+    fn apply_at(
+        &self,
+        value: &mut Value,
+        key: &str,
+        separator: char,
+        lookup: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<()> {
+        match value {
+            Value::String(s) => {
+                if let Cow::Owned(expanded) = self.expand(s, key, lookup)? {
+                    *s = expanded;
+                }
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    let key = child_key(key, &i.to_string(), separator);
+                    self.apply_at(item, &key, separator, lookup)?;
+                }
+            }
+            Value::Object(obj) => {
+                if self.keys {
+                    *obj = self.expand_keys(core::mem::take(obj), key, separator, lookup)?;
+                }
+                for (field, val) in obj.iter_mut() {
+                    let key = child_key(key, field, separator);
+                    self.apply_at(val, &key, separator, lookup)?;
+                }
+            }
+            Value::Null | Value::Bool(_) | Value::Number(_) => {}
+        }
+        Ok(())
+    }
+
+    fn expand_keys(
+        &self,
+        obj: Map<String, Value>,
+        key: &str,
+        separator: char,
+        lookup: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Map<String, Value>> {
+        let mut expanded = Map::new();
+        for (field, val) in obj {
+            let field = self
+                .expand(&field, &child_key(key, &field, separator), lookup)?
+                .into_owned();
+            if expanded.contains_key(&field) {
+                return Err(ConvertError::DuplicateKey {
+                    key: child_key(key, &field, separator),
+                });
+            }
+            expanded.insert(field, val);
+        }
+        Ok(expanded)
+    }
+
+    /// Expand the placeholders of one string, borrowing it when there are none
+    fn expand<'a>(
+        &self,
+        text: &'a str,
+        key: &str,
+        lookup: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Cow<'a, str>> {
+        if !text.contains("${") {
+            return Ok(Cow::Borrowed(text));
+        }
+
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("${") {
+            // `$${` escapes a literal `${`
+            if rest[..start].ends_with('$') {
+                out.push_str(&rest[..start - 1]);
+                out.push_str("${");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(end) = after.find('}').filter(|&end| is_var_name(&after[..end])) else {
+                out.push_str("${");
+                rest = after;
+                continue;
+            };
+
+            let name = &after[..end];
+            match lookup(name) {
+                Some(value) => out.push_str(&value),
+                None => match self.missing {
+                    MissingVar::Error => {
+                        return Err(ConvertError::MissingVar {
+                            key: key.to_string(),
+                            name: name.to_string(),
+                        });
+                    }
+                    MissingVar::Empty => {}
+                    MissingVar::Keep => out.push_str(&rest[start..start + end + 3]),
+                },
+            }
+            rest = &after[end + 1..];
+        }
+        out.push_str(rest);
+        Ok(Cow::Owned(out))
+    }
+}
+
+/// Whether a placeholder name is an identifier: `[A-Za-z_][A-Za-z0-9_]*`
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("db.internal".to_string()),
+            "PORT" => Some("5432".to_string()),
+            _ => None,
+        }
+    }
+
+    fn expand(value: Value, interpolation: Interpolation) -> Result<Value> {
+        let mut value = value;
+        interpolation.apply(&mut value, '.', &lookup)?;
+        Ok(value)
+    }
+
+    #[test]
+    fn test_expand_values() {
+        let value = json!({"url": "postgres://${HOST}:${PORT}/app", "list": ["${HOST}"], "n": 1});
+        assert_eq!(
+            expand(value, Interpolation::default()).unwrap(),
+            json!({"url": "postgres://db.internal:5432/app", "list": ["db.internal"], "n": 1})
+        );
+    }
+
+    #[test]
+    fn test_literal_and_malformed_placeholders() {
+        let value = json!(["$${HOST}", "${}", "${1X}", "${HOST", "cost: $5"]);
+        assert_eq!(
+            expand(value.clone(), Interpolation::default()).unwrap(),
+            json!(["${HOST}", "${}", "${1X}", "${HOST", "cost: $5"])
+        );
+    }
+
+    #[test]
+    fn test_missing_vars() {
+        let value = json!({"a": {"b": "x${UNSET}y"}});
+
+        let err = expand(value.clone(), Interpolation::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Environment variable UNSET is not set (at a.b)");
+
+        let empty = Interpolation {
+            missing: MissingVar::Empty,
+            ..Default::default()
+        };
+        assert_eq!(expand(value.clone(), empty).unwrap(), json!({"a": {"b": "xy"}}));
+
+        let keep = Interpolation {
+            missing: MissingVar::Keep,
+            ..Default::default()
+        };
+        assert_eq!(expand(value.clone(), keep).unwrap(), value);
+    }
+
+    #[test]
+    fn test_expand_keys() {
+        let keys = Interpolation {
+            keys: true,
+            ..Default::default()
+        };
+        let value = json!({"${HOST}": {"port": "${PORT}"}});
+        assert_eq!(
+            expand(value.clone(), keys).unwrap(),
+            json!({"db.internal": {"port": "5432"}})
+        );
+        // Keys are left alone unless asked for
+        assert_eq!(
+            expand(value, Interpolation::default()).unwrap(),
+            json!({"${HOST}": {"port": "5432"}})
+        );
+
+        let clash = json!({"${HOST}": 1, "db.internal": 2});
+        assert!(matches!(
+            expand(clash, keys),
+            Err(ConvertError::DuplicateKey { .. })
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+//! JSON to TOON (Token-Oriented-Object-Notation) conversion library
+//!
+//! The `json2toon` binary is a thin CLI over these modules.
+//!
+//! Without the default `std` feature the library is `no_std` and needs only
+//! `alloc`: flattening JSON values to TOON still works, while the TOML and
+//! YAML inputs, CSV/TSV output and stderr diagnostics are left out.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod case;
+pub mod coerce;
+pub mod converter;
+#[cfg(feature = "std")]
+pub mod delimited;
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod diag;
+pub mod error;
+#[cfg(feature = "std")]
+mod input;
+pub mod interpolate;
+pub mod merge;
+pub mod pointer;
+#[cfg(feature = "std")]
+pub mod profile;
+mod prelude;
+pub mod relaxed;
+pub mod spans;
+pub mod toon;
@@ -0,0 +1,40 @@
+//! `log` backend: `[INFO]`-style lines on stderr, leveled by `-v` and `RUST_LOG`
+
+use json2toon::diag;
+use log::LevelFilter;
+use std::io::Write;
+
+/// Install the logger for `-v` given `verbosity` times
+///
+/// `RUST_LOG` is applied on top, so it can raise or lower the level,
+/// globally or per module (e.g. `RUST_LOG=json2toon::converter=debug`).
+pub fn init(verbosity: u8) {
+    env_logger::Builder::new()
+        .filter_level(level_filter(verbosity))
+        .parse_default_env()
+        .format(|buf, record| writeln!(buf, "{} {}", diag::level_tag(record.level()), record.args()))
+        .init();
+}
+
+/// Level for a `-v` count: warnings by default, `-v` info, `-vv` debug, `-vvv` trace
+fn level_filter(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_filter() {
+        assert_eq!(level_filter(0), LevelFilter::Warn);
+        assert_eq!(level_filter(1), LevelFilter::Info);
+        assert_eq!(level_filter(2), LevelFilter::Debug);
+        assert_eq!(level_filter(9), LevelFilter::Trace);
+    }
+}
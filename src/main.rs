@@ -1,91 +1,659 @@
+mod batch;
+mod checksum;
+mod cleanup;
 mod cli;
-mod converter;
-mod toon;
+mod encoding;
+mod error_report;
+mod hook;
+mod logging;
+mod repl;
 mod version;
 
 use anyhow::{Context, Result};
 use clap::Parser;
+use json2toon::coerce::CoercionRule;
+use json2toon::converter::{self, Converter};
+use json2toon::error::ConvertError;
+use json2toon::{diag, diff, profile, toon};
+use std::collections::BTreeSet;
 use std::fs;
-use std::io::Write;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
 
-fn main() -> Result<()> {
+fn main() {
     let args = cli::Args::parse();
+    if let Err(e) = args.validate() {
+        e.exit();
+    }
+    diag::init(args.color);
+    logging::init(args.verbose);
+
+    cleanup::keep_on_failure(args.keep_temp);
+    if let Err(e) = cleanup::install() {
+        diag::error(format!("Failed to install Ctrl-C handler: {}", e));
+    }
+
+    if let Err(e) = run_with_stack(&args) {
+        match args.error_format {
+            cli::ErrorFormat::Text => diag::error(format!("{:#}", e)),
+            cli::ErrorFormat::Json => {
+                eprintln!("{}", error_report::ErrorReport::from_error(&e).to_json())
+            }
+        }
+        std::process::exit(exit_code(&e));
+    }
+}
+
+/// Stack of the main thread assumed by `run_with_stack`, the usual Linux default
+const MAIN_STACK_SIZE: usize = 8 << 20;
+
+/// Run on a thread whose stack fits input nested `--max-recursion` levels deep
+///
+/// Parsing and traversal recurse once per level, so a raised limit would
+/// otherwise let deep input overflow the main thread's stack.
+fn run_with_stack(args: &cli::Args) -> Result<()> {
+    let stack_size = MAIN_STACK_SIZE + args.max_recursion * converter::STACK_PER_LEVEL;
+    std::thread::scope(|scope| {
+        let worker = std::thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, || run(args))
+            .context("Failed to start the conversion thread")?;
+        worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Exit code for arguments that cannot be used together, as clap uses for the ones it rejects
+const USAGE_ERROR: i32 = 2;
+
+/// Exit code for `--max-output-size` aborts, so wrappers can tell them from other failures
+const OUTPUT_TOO_LARGE: i32 = 3;
+
+/// Process exit code for a failed run
+fn exit_code(error: &anyhow::Error) -> i32 {
+    let too_large = error
+        .chain()
+        .any(|cause| matches!(cause.downcast_ref(), Some(ConvertError::OutputTooLarge { .. })));
+    if too_large {
+        OUTPUT_TOO_LARGE
+    } else if error.chain().any(|cause| cause.is::<batch::UsageError>()) {
+        USAGE_ERROR
+    } else {
+        1
+    }
+}
 
+fn run(args: &cli::Args) -> Result<()> {
     // Handle version flag
     if args.version {
         version::print_version();
         return Ok(());
     }
 
-    // Validate input file exists
-    if !args.input.exists() {
-        anyhow::bail!("Input file does not exist: {}", args.input.display());
+    if let Some(shell) = args.generate_completions {
+        cli::write_completions(shell, &mut io::stdout());
+        return Ok(());
     }
 
-    if !args.input.is_file() {
-        anyhow::bail!("Input path is not a file: {}", args.input.display());
+    if args.generate_man {
+        cli::write_man_page(&mut io::stdout()).context("Failed to write man page")?;
+        return Ok(());
     }
 
-    let output_path = args.get_output_path();
+    let start = Instant::now();
+    let mut options = args.convert_options();
+    options.patches = read_patches(args)?;
+    options.types = read_types(args)?;
+    let converter = Converter::new()
+        .with_options(options)
+        .with_format(args.toon_options());
 
-    // Read input file
-    if args.verbose {
-        println!("[INFO] Reading input file: {}", args.input.display());
+    if let Some(cli::Command::Diff { ref old, ref new }) = args.command {
+        return run_diff(args, &converter, old, new);
     }
 
-    let json_content = fs::read_to_string(&args.input)
-        .with_context(|| format!("Failed to read input file: {}", args.input.display()))?;
+    if let Some(cli::Command::Validate { ref file, jsonl }) = args.command {
+        return run_validate(args, &converter, file, jsonl);
+    }
 
-    if args.verbose {
-        let size_kb = json_content.len() as f64 / 1024.0;
-        println!("[INFO] File size: {:.1} KB", size_kb);
+    if let Some(cli::Command::Repl) = args.command {
+        let stdin = io::stdin();
+        let prompt = stdin.is_terminal();
+        repl::run(
+            stdin.lock(),
+            &mut io::stdout(),
+            args.convert_options(),
+            args.toon_options(),
+            prompt,
+        )
+        .context("Failed to run REPL")?;
+        return Ok(());
     }
 
-    // Convert JSON to TOON
-    let converter = converter::Converter::new(args.verbose);
+    if let Some(ref list) = args.input_list {
+        return batch::run_list(args, &converter, list);
+    }
 
-    if args.dry_run {
-        // Dry run mode
-        println!("[DRY RUN] Would perform the following steps:");
-
-        let size_kb = json_content.len() as f64 / 1024.0;
-        println!("  1. Read JSON from: {} ({:.1} KB)", args.input.display(), size_kb);
-        println!("  2. Parse JSON structure");
-
-        // Try to estimate output size
-        match converter.estimate_size(&json_content) {
-            Ok(estimated_size) => {
-                let est_kb = estimated_size as f64 / 1024.0;
-                println!("  3. Convert to TOON format");
-                println!("  4. Write TOON to: {} (estimated {:.1} KB)", output_path.display(), est_kb);
-            }
-            Err(e) => {
-                println!("  3. [ERROR] Failed to parse JSON: {}", e);
-                println!("\n[DRY RUN] No files were modified.");
-                std::process::exit(1);
-            }
-        }
+    if args.recursive {
+        return batch::run(args, &converter);
+    }
+
+    if let Some(pattern) = args.glob_pattern() {
+        return batch::run_glob(args, &converter, pattern);
+    }
 
-        println!("\n[DRY RUN] No files were modified.");
+    // Stream JSON Lines record by record so memory stays constant
+    if args.jsonl
+        && args.reads_stdin()
+        && args.writes_stdout()
+        && !args.count_only
+        && args.manifest.is_none()
+        && args.summary_json.is_none()
+    {
+        return stream_jsonl(args, &converter);
+    }
+
+    let read_start = Instant::now();
+    let json_content = read_input(args)?;
+    let read_ms = profile::millis(read_start.elapsed());
+
+    if args.count_only {
+        let count = if args.jsonl {
+            converter::jsonl_records(&json_content)
+                .map(|(_, record)| converter.count_entries(record))
+                .sum::<Result<usize, _>>()?
+        } else {
+            converter.count_entries(&json_content)?
+        };
+        println!("{}", count);
         return Ok(());
     }
 
+    if args.dry_run {
+        return dry_run(args, &converter, &json_content);
+    }
+
+    if let Some(ref manifest_path) = args.manifest {
+        write_manifest(args, &converter, &json_content, manifest_path)?;
+    }
+
+    let mut stats = match args.summary_json {
+        Some(_) => Some(summary_stats(args, &converter, &json_content)?),
+        None => None,
+    };
+
     // Perform actual conversion
-    let toon_content = converter.convert(&json_content)
-        .context("Failed to convert JSON to TOON")?;
+    let mut profile = None;
+    let mut documents = None;
+    let toon_content = if args.jsonl {
+        converter.convert_jsonl(&json_content)
+    } else if args.multi_doc {
+        converter.convert_multi_doc(&json_content).map(|(toon, count)| {
+            log::info!("Found {} document(s)", count);
+            documents = Some(count);
+            toon
+        })
+    } else if args.profile.is_some() {
+        converter.convert_profiled(&json_content).map(|(toon, timings)| {
+            profile = Some(timings);
+            toon
+        })
+    } else {
+        converter.convert(&json_content)
+    }
+    .context("Failed to convert JSON to TOON")?;
+    check_not_empty(args, &toon_content)?;
+    let toon_content = with_header(args, &input_name(args), toon_content);
+
+    if let (Some(stats), Some(path)) = (stats.as_mut(), args.summary_json.as_ref()) {
+        stats.output_bytes = toon_content.len();
+        stats.duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+        write_summary(stats, path)?;
+    }
+
+    let write_start = Instant::now();
+    if args.writes_stdout() {
+        let digest = write_stdout(args, io::stdout().lock(), &toon_content)?;
+        print_stdout_checksum(digest);
+    } else if args.output.len() > 1 {
+        let source = (!args.reads_stdin()).then(|| args.get_input_path().as_path());
+        write_outputs(args, source, &toon_content)?;
+    } else {
+        let output_path = args.get_output_path();
+
+        // Write output file
+        log::info!("Writing output to: {}", output_path.display());
+
+        let source = (!args.reads_stdin()).then(|| args.get_input_path().as_path());
+        if let Some(lines) = args.chunk_lines {
+            let count = write_chunks(args, source, &output_path, &toon_content, lines.get())?;
+            let message = format!(
+                "Converted {} to {} chunk(s): {} to {}",
+                input_name(args),
+                count,
+                chunk_path(&output_path, 1).display(),
+                chunk_path(&output_path, count).display()
+            );
+            report(message);
+        } else {
+            write_output(args, source, &output_path, &toon_content)?;
+            log::info!("Output written: {:.1} KB", toon_content.len() as f64 / 1024.0);
+            report(match documents {
+                Some(count) => format!(
+                    "Converted {} document(s) from {} to {}",
+                    count,
+                    input_name(args),
+                    output_path.display()
+                ),
+                None => format!("Converted {} to {}", input_name(args), output_path.display()),
+            });
+        }
+        if log::log_enabled!(log::Level::Info) {
+            diag::success(format!("Conversion completed in {:.2?}", start.elapsed()));
+        }
+    }
+
+    if let (Some(mut profile), Some(path)) = (profile, args.profile.as_ref()) {
+        profile.read_ms = read_ms;
+        profile.write_ms = profile::millis(write_start.elapsed());
+        profile.total_ms = profile::millis(start.elapsed());
+        write_profile(&profile, path)?;
+    }
+
+    Ok(())
+}
+
+/// Report a status line: as an `[INFO]` record with `-v`, otherwise on stdout
+fn report(message: impl std::fmt::Display) {
+    if log::log_enabled!(log::Level::Info) {
+        log::info!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// Display name for the input, `<stdin>` when reading from stdin
+fn input_name(args: &cli::Args) -> String {
+    if args.reads_stdin() {
+        "<stdin>".to_string()
+    } else {
+        args.get_input_path().display().to_string()
+    }
+}
+
+/// Read the whole input from the input file or stdin
+fn read_input(args: &cli::Args) -> Result<String> {
+    log::info!("Reading input: {}", input_name(args));
+
+    let json_content = if args.reads_stdin() {
+        if args.lossy_utf8 {
+            let mut bytes = Vec::new();
+            io::stdin()
+                .read_to_end(&mut bytes)
+                .context("Failed to read from stdin")?;
+            decode_lossy(&bytes)
+        } else {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .context("Failed to read from stdin")?;
+            content
+        }
+    } else {
+        read_file(args, args.get_input_path())?
+    };
+
+    log::debug!("File size: {:.1} KB", json_content.len() as f64 / 1024.0);
+
+    Ok(json_content)
+}
+
+/// Parse the --patch files, in the order given
+fn read_patches(args: &cli::Args) -> Result<Vec<serde_json::Value>> {
+    args.patch
+        .iter()
+        .map(|path| {
+            let content = read_file(args, path)?;
+            serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse patch file: {}", path.display()))
+        })
+        .collect()
+}
+
+/// Parse the --types file into rules, if one is given
+fn read_types(args: &cli::Args) -> Result<Vec<CoercionRule>> {
+    let Some(path) = &args.types else {
+        return Ok(Vec::new());
+    };
+    let content = read_file(args, path)?;
+    CoercionRule::parse_types(&content)
+        .map_err(|message| anyhow::anyhow!("Failed to parse types file {}: {}", path.display(), message))
+}
+
+/// Read an input file after checking that it exists and is not a directory
+///
+/// Named pipes and character devices are read like files, so process
+/// substitution (`json2toon <(generate.sh)`) and `/dev/stdin` work.
+fn read_file(args: &cli::Args, input: &Path) -> Result<String> {
+    // Validate input file exists
+    if !input.exists() {
+        let message = format!("Input file does not exist: {}", input.display());
+        return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+    }
+
+    if input.is_dir() {
+        let message = format!("Input path is a directory (use --recursive to convert one): {}", input.display());
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message).into());
+    }
+
+    if args.lossy_utf8 {
+        let bytes = fs::read(input)
+            .with_context(|| format!("Failed to read input file: {}", input.display()))?;
+        return Ok(decode_lossy(&bytes));
+    }
+
+    fs::read_to_string(input)
+        .with_context(|| format!("Failed to read input file: {}", input.display()))
+}
+
+/// Decode input bytes, replacing invalid UTF-8 sequences with U+FFFD
+fn decode_lossy(bytes: &[u8]) -> String {
+    let (content, replaced) = lossy_utf8(bytes);
+    if replaced > 0 {
+        log::info!("Replaced {} invalid UTF-8 sequence(s) with U+FFFD", replaced);
+    }
+    content
+}
+
+/// Lossily decode UTF-8, returning the text and the number of replacement characters added
+fn lossy_utf8(bytes: &[u8]) -> (String, usize) {
+    let mut content = String::with_capacity(bytes.len());
+    let mut replaced = 0;
+    for chunk in bytes.utf8_chunks() {
+        content.push_str(chunk.valid());
+        if !chunk.invalid().is_empty() {
+            content.push(char::REPLACEMENT_CHARACTER);
+            replaced += 1;
+        }
+    }
+    (content, replaced)
+}
+
+/// Print the flattened differences between two files, exiting with 1 if any
+fn run_diff(args: &cli::Args, converter: &Converter, old: &Path, new: &Path) -> Result<()> {
+    let old_entries = converter
+        .flatten(&read_file(args, old)?)
+        .with_context(|| format!("Failed to flatten {}", old.display()))?
+        .into_iter()
+        .collect();
+    let new_entries = converter
+        .flatten(&read_file(args, new)?)
+        .with_context(|| format!("Failed to flatten {}", new.display()))?
+        .into_iter()
+        .collect();
+
+    let changes = diff::diff_entries(&old_entries, &new_entries);
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    if !changes.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Parse a file without converting it, printing `<file>: OK` when it is valid
+fn run_validate(args: &cli::Args, converter: &Converter, file: &Path, jsonl: bool) -> Result<()> {
+    let content = read_file(args, file)?;
+    if jsonl {
+        converter.validate_jsonl(&content)
+    } else {
+        converter.validate(&content)
+    }
+    .with_context(|| format!("Invalid JSON: {}", file.display()))?;
+
+    println!("{}: OK", file.display());
+    Ok(())
+}
+
+/// Show the conversion steps and estimated output size without writing anything
+fn dry_run(args: &cli::Args, converter: &Converter, json_content: &str) -> Result<()> {
+    println!("[DRY RUN] Would perform the following steps:");
+
+    let size_kb = json_content.len() as f64 / 1024.0;
+    println!("  1. Read JSON from: {} ({:.1} KB)", input_name(args), size_kb);
+    println!("  2. Parse JSON structure");
+
+    let destination = if args.writes_stdout() {
+        "<stdout>".to_string()
+    } else {
+        let paths: Vec<String> = args.get_output_paths().iter().map(|path| path.display().to_string()).collect();
+        paths.join(", ")
+    };
+
+    // Try to estimate output size
+    match converter.estimate_size(json_content) {
+        Ok(estimated_size) => {
+            let est_kb = estimated_size as f64 / 1024.0;
+            println!("  3. Convert to TOON format");
+            println!("  4. Write TOON to: {} (estimated {:.1} KB)", destination, est_kb);
+        }
+        Err(e) => {
+            // Reported by `main`, in the --error-format
+            println!("  3. Convert to TOON format (skipped)");
+            println!("\n[DRY RUN] No files were modified.");
+            return Err(e.into());
+        }
+    }
+
+    if let Some(lines) = args.preview {
+        let toon = converter
+            .convert(json_content)
+            .context("Failed to convert JSON to TOON")?;
+        println!("\nPreview:");
+        print!("{}", preview(&toon, lines));
+    }
+
+    println!("\n[DRY RUN] No files were modified.");
+    Ok(())
+}
+
+/// The first `lines` lines of the output, plus a trailer counting the rest
+fn preview(toon: &str, lines: usize) -> String {
+    let mut shown = String::new();
+    let mut remaining = toon.lines();
+    for line in remaining.by_ref().take(lines) {
+        shown.push_str(line);
+        shown.push('\n');
+    }
+
+    let more = remaining.count();
+    if more > 0 {
+        shown.push_str(&format!("... ({} more lines)\n", more));
+    }
+    shown
+}
+
+/// Write the sorted key paths of the document (or of every JSONL record) to a file
+fn write_manifest(
+    args: &cli::Args,
+    converter: &Converter,
+    json_content: &str,
+    manifest_path: &Path,
+) -> Result<()> {
+    let keys = if args.jsonl {
+        let mut keys = BTreeSet::new();
+        for (_, record) in converter::jsonl_records(json_content) {
+            keys.extend(converter.collect_keys(record)?);
+        }
+        keys
+    } else {
+        converter.collect_keys(json_content)?
+    };
+
+    let mut manifest = String::new();
+    for key in &keys {
+        manifest.push_str(&toon::escape_string(key));
+        manifest.push('\n');
+    }
+    write_atomic(manifest_path, manifest.as_bytes())?;
+
+    log::info!("Manifest written to: {} ({} keys)", manifest_path.display(), keys.len());
+    Ok(())
+}
+
+/// Entry statistics of the document, or of all JSONL records combined
+fn summary_stats(
+    args: &cli::Args,
+    converter: &Converter,
+    json_content: &str,
+) -> Result<converter::ConversionStats> {
+    if !args.jsonl {
+        return Ok(converter.stats(json_content)?);
+    }
+
+    let mut stats = converter::ConversionStats::default();
+    for (_, record) in converter::jsonl_records(json_content) {
+        stats.add(&converter.stats(record)?);
+    }
+    // Count blank lines and newlines too
+    stats.input_bytes = json_content.len();
+    Ok(stats)
+}
+
+/// Write the conversion summary (or a batch's array of them) as pretty-printed JSON
+fn write_summary(stats: &impl serde::Serialize, path: &Path) -> Result<()> {
+    let mut json = serde_json::to_string_pretty(stats).expect("stats serialize");
+    json.push('\n');
+    write_atomic(path, json.as_bytes())?;
+
+    log::info!("Summary written to: {}", path.display());
+    Ok(())
+}
+
+/// Write the phase timings as pretty-printed JSON
+fn write_profile(profile: &profile::Profile, path: &Path) -> Result<()> {
+    let mut json = serde_json::to_string_pretty(profile).expect("profile serialize");
+    json.push('\n');
+    write_atomic(path, json.as_bytes())?;
+
+    log::info!("Profile written to: {}", path.display());
+    Ok(())
+}
+
+/// Write converted output to `out` (stdout) in the --output-encoding, returning
+/// the --checksum digest of the bytes written
+fn write_stdout(args: &cli::Args, out: impl Write, content: &str) -> Result<Option<String>> {
+    let mut out = checksum::HashingWriter::new(out, args.checksum);
+    out.write_all(&encoding::encode(args.output_encoding, content))
+        .and_then(|_| out.flush())
+        .context("Failed to write to stdout")?;
+    Ok(out.finish())
+}
+
+/// Print the --checksum digest of output written to stdout, named `-` like `sha256sum` does
+fn print_stdout_checksum(digest: Option<String>) {
+    if let Some(hex) = digest {
+        eprintln!("{}  -", hex);
+    }
+}
+
+/// Convert JSON Lines from stdin, flushing each record to stdout as it is produced
+fn stream_jsonl(args: &cli::Args, converter: &Converter) -> Result<()> {
+    let digest = stream_records(args, converter, io::stdin().lock(), io::stdout().lock())?;
+    print_stdout_checksum(digest);
+    Ok(())
+}
 
-    // Write output file
-    if args.verbose {
-        println!("[INFO] Writing output to: {}", output_path.display());
+/// Convert JSON Lines from `input` to `output` (stdin and stdout) in the
+/// --output-encoding, returning the --checksum digest of the bytes written
+fn stream_records(args: &cli::Args, converter: &Converter, input: impl BufRead, output: impl Write) -> Result<Option<String>> {
+    let mut stdout = checksum::HashingWriter::new(output, args.checksum);
+    let mut encoder = encoding::StreamEncoder::new(args.output_encoding);
+    let mut index = 0;
+    let mut written = 0;
+    if args.header {
+        stdout
+            .write_all(&encoder.encode(&version::header("<stdin>")))
+            .context("Failed to write to stdout")?;
     }
 
-    // Write to a temporary file first, then rename (atomic operation)
-    let temp_path = output_path.with_extension("toon.tmp");
+    for (i, line) in input.lines().enumerate() {
+        let line = line.context("Failed to read from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let toon = converter
+            .convert_record(&line, index)
+            .with_context(|| format!("Failed to convert JSONL record on line {}", i + 1))?;
+        // Records already streamed stay written; the one over the limit is not
+        written += toon.len();
+        converter.check_output_len(written)?;
+        stdout
+            .write_all(&encoder.encode(&toon))
+            .and_then(|_| stdout.flush())
+            .context("Failed to write to stdout")?;
+        index += 1;
+    }
+
+    // Output with no records at all still gets its byte order mark
+    stdout
+        .write_all(&encoder.encode(""))
+        .and_then(|_| stdout.flush())
+        .context("Failed to write to stdout")?;
+    if args.fail_on_empty && written == 0 {
+        anyhow::bail!("{}", EMPTY_OUTPUT);
+    }
+    Ok(stdout.finish())
+}
+
+/// With `--header`, the output preceded by a provenance comment naming `source`
+fn with_header(args: &cli::Args, source: &str, toon: String) -> String {
+    if args.header {
+        version::header(source) + &toon
+    } else {
+        toon
+    }
+}
+
+const EMPTY_OUTPUT: &str = "Conversion produced no output (--fail-on-empty)";
+
+/// With `--fail-on-empty`, reject output without a single entry before it is written
+fn check_not_empty(args: &cli::Args, toon: &str) -> Result<()> {
+    if args.fail_on_empty && toon.trim().is_empty() {
+        anyhow::bail!("{}", EMPTY_OUTPUT);
+    }
+    Ok(())
+}
 
-    let mut file = fs::File::create(&temp_path)
+/// Unique temporary path next to `output_path`, e.g. `out.toon.4242.0.tmp`
+///
+/// The process id and a per-process counter keep parallel conversions into
+/// the same directory apart, and staying in the same directory keeps the
+/// final rename on one filesystem so it remains atomic.
+fn temp_path_for(output_path: &Path) -> std::path::PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let name = output_path.file_name().unwrap_or_default().to_string_lossy();
+    output_path.with_file_name(format!("{}.{}.{}.tmp", name, std::process::id(), n))
+}
+
+/// Write to a temporary file first, then rename (atomic operation)
+///
+/// The temporary file is removed if writing fails (unless --keep-temp) or
+/// the user presses Ctrl-C.
+fn write_atomic(output_path: &Path, content: &[u8]) -> Result<()> {
+    let temp = cleanup::TempFile::register(temp_path_for(output_path));
+    let temp_path = temp.path();
+
+    let mut file = fs::File::create(temp_path)
         .with_context(|| format!("Failed to create output file: {}", temp_path.display()))?;
 
-    file.write_all(toon_content.as_bytes())
+    file.write_all(content)
         .with_context(|| format!("Failed to write to output file: {}", temp_path.display()))?;
 
     file.sync_all()
@@ -93,16 +661,289 @@ fn main() -> Result<()> {
 
     drop(file);
 
-    fs::rename(&temp_path, &output_path)
+    fs::rename(temp_path, output_path)
         .with_context(|| format!("Failed to rename temporary file to: {}", output_path.display()))?;
+    temp.persist();
 
-    if args.verbose {
-        let size_kb = toon_content.len() as f64 / 1024.0;
-        println!("[INFO] Output written: {:.1} KB", size_kb);
-        println!("[SUCCESS] Conversion completed");
-    } else {
-        println!("Converted {} to {}", args.input.display(), output_path.display());
+    Ok(())
+}
+
+/// Write converted output atomically in the --output-encoding, then apply
+/// --preserve-mode and --checksum and run --on-success
+///
+/// `source` is the input file, or `None` for stdin.
+fn write_output(args: &cli::Args, source: Option<&Path>, output_path: &Path, content: &str) -> Result<()> {
+    let content = encoding::encode(args.output_encoding, content);
+    log::info!("Output encoding: {}", args.output_encoding.name());
+    write_atomic(output_path, &content)?;
+
+    if args.preserve_mode {
+        match source {
+            Some(source) => copy_permissions(source, output_path)?,
+            None => log::warn!("--preserve-mode has no effect when reading stdin"),
+        }
+    }
+
+    if let Some(algorithm) = args.checksum {
+        // Of the bytes on disk, so it matches `sha256sum` of the file
+        let hex = checksum::digest(algorithm, &content);
+        eprintln!("{}  {}", hex, output_path.display());
+
+        if args.checksum_file {
+            let sidecar = checksum::write_sidecar(output_path, algorithm, &hex)?;
+            log::info!("Checksum written to: {}", sidecar.display());
+        }
+    }
+
+    if let Some(hook) = &args.on_success {
+        hook.run(source.unwrap_or(Path::new("-")), output_path)?;
     }
+    Ok(())
+}
 
+/// Write the same content to every --output path
+///
+/// Each file is written atomically on its own, so a failure leaves the other
+/// outputs intact; every failure is reported before the run fails.
+fn write_outputs(args: &cli::Args, source: Option<&Path>, content: &str) -> Result<()> {
+    let outputs = args.get_output_paths();
+    let mut failed = 0;
+    for output_path in &outputs {
+        log::info!("Writing output to: {}", output_path.display());
+        if let Err(e) = write_output(args, source, output_path, content) {
+            diag::error(format!("{:#}", e));
+            failed += 1;
+            continue;
+        }
+        report(format!("Converted {} to {}", input_name(args), output_path.display()));
+    }
+    if failed > 0 {
+        anyhow::bail!("Failed to write {} of {} outputs", failed, outputs.len());
+    }
     Ok(())
 }
+
+/// Write `content` as numbered parts of at most `lines` lines each, returning how many
+///
+/// Parts are written like a single output, so each is encoded, checksummed
+/// and passed to the `--on-success` hook on its own. Lines are never split,
+/// and empty output still gets one (empty) part.
+fn write_chunks(args: &cli::Args, source: Option<&Path>, output_path: &Path, content: &str, lines: usize) -> Result<usize> {
+    let chunks = split_lines(content, lines);
+    for (i, chunk) in chunks.iter().enumerate() {
+        write_output(args, source, &chunk_path(output_path, i + 1), chunk)?;
+    }
+    Ok(chunks.len())
+}
+
+/// Path of the numbered part of an output, e.g. `out.toon.001`
+fn chunk_path(output_path: &Path, number: usize) -> PathBuf {
+    let mut path = output_path.as_os_str().to_owned();
+    path.push(format!(".{:03}", number));
+    PathBuf::from(path)
+}
+
+/// `content` in runs of at most `lines` whole lines (at least one run)
+fn split_lines(content: &str, lines: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for (n, (end, _)) in content.match_indices('\n').enumerate() {
+        if (n + 1) % lines == 0 {
+            chunks.push(&content[start..=end]);
+            start = end + 1;
+        }
+    }
+    if start < content.len() || chunks.is_empty() {
+        chunks.push(&content[start..]);
+    }
+    chunks
+}
+
+/// Apply the permissions of `source` to `target` (mode bits on Unix)
+fn copy_permissions(source: &Path, target: &Path) -> Result<()> {
+    let permissions = fs::metadata(source)
+        .with_context(|| format!("Failed to read permissions of: {}", source.display()))?
+        .permissions();
+    fs::set_permissions(target, permissions)
+        .with_context(|| format!("Failed to set permissions of: {}", target.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_paths_are_unique() {
+        let first = temp_path_for(Path::new("dir/out.toon"));
+        let second = temp_path_for(Path::new("dir/out.toon"));
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(Path::new("dir")));
+        assert!(first.file_name().unwrap().to_string_lossy().starts_with("out.toon."));
+    }
+
+    #[test]
+    fn test_with_header() {
+        let args = cli::Args::parse_from(["json2toon", "x.json"]);
+        assert_eq!(with_header(&args, "x.json", "a=1\n".to_string()), "a=1\n");
+
+        let args = cli::Args::parse_from(["json2toon", "--header", "x.json"]);
+        let toon = with_header(&args, "x.json", "a=1\n".to_string());
+        assert!(toon.starts_with("# generated from x.json by json2toon "), "{}", toon);
+        assert!(toon.ends_with("Z\na=1\n"), "{}", toon);
+    }
+
+    #[test]
+    fn test_check_not_empty() {
+        let args = cli::Args::parse_from(["json2toon", "x.json"]);
+        assert!(check_not_empty(&args, "").is_ok());
+
+        let args = cli::Args::parse_from(["json2toon", "--fail-on-empty", "x.json"]);
+        assert!(check_not_empty(&args, "a=1\n").is_ok());
+        assert!(check_not_empty(&args, "").is_err());
+        assert!(check_not_empty(&args, "\n").is_err());
+    }
+
+    #[test]
+    fn test_split_lines() {
+        assert_eq!(split_lines("a=1\nb=2\nc=3\n", 2), ["a=1\nb=2\n", "c=3\n"]);
+        assert_eq!(split_lines("a=1\nb=2\n", 2), ["a=1\nb=2\n"]);
+        assert_eq!(split_lines("a=1\nb=2", 1), ["a=1\n", "b=2"]);
+        assert_eq!(split_lines("", 5), [""]);
+        assert_eq!(chunk_path(Path::new("out/data.toon"), 2), PathBuf::from("out/data.toon.002"));
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(exit_code(&anyhow::anyhow!("oops")), 1);
+        let err = anyhow::Error::new(ConvertError::OutputTooLarge { limit: 10 }).context("Failed to convert x.json");
+        assert_eq!(exit_code(&err), OUTPUT_TOO_LARGE);
+        let err = anyhow::Error::new(batch::UsageError("--output cannot be used".to_string()));
+        assert_eq!(exit_code(&err), 2);
+    }
+
+    #[test]
+    fn test_concurrent_writes_into_one_directory() {
+        let dir = std::env::temp_dir().join(format!("json2toon-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // `data.json` and `data.yaml` used to share the temp file `data.toon.tmp`
+        let outputs = [dir.join("data.json"), dir.join("data.yaml"), dir.join("data.json")];
+        std::thread::scope(|scope| {
+            for (i, output) in outputs.iter().enumerate() {
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        write_atomic(output, format!("writer={}\n", i).as_bytes()).unwrap();
+                    }
+                });
+            }
+        });
+
+        let json = fs::read_to_string(dir.join("data.json")).unwrap();
+        assert!(json == "writer=0\n" || json == "writer=2\n");
+        assert_eq!(fs::read_to_string(dir.join("data.yaml")).unwrap(), "writer=1\n");
+
+        // No temporary files are left behind
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["data.json", "data.yaml"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("json2toon-mode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("secret.json"), dir.join("secret.toon"));
+        fs::write(&input, "{}").unwrap();
+        fs::set_permissions(&input, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write_atomic(&output, b"x=1\n").unwrap();
+        copy_permissions(&input, &output).unwrap();
+
+        let mode = fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_file_from_fifo() {
+        let dir = std::env::temp_dir().join(format!("json2toon-fifo-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let fifo = dir.join("input.json");
+        let status = std::process::Command::new("mkfifo").arg(&fifo).status().unwrap();
+        assert!(status.success());
+
+        // Opening a FIFO for writing blocks until the reader opens it
+        let writer = std::thread::spawn({
+            let fifo = fifo.clone();
+            move || fs::write(fifo, r#"{"a": 1}"#).unwrap()
+        });
+        let args = cli::Args::parse_from(["json2toon", "x.json"]);
+        assert_eq!(read_file(&args, &fifo).unwrap(), r#"{"a": 1}"#);
+        writer.join().unwrap();
+
+        let err = read_file(&args, &dir).unwrap_err();
+        assert!(err.to_string().contains("is a directory"), "{}", err);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_stdout_checksum() {
+        let args = cli::Args::parse_from(["json2toon", "--checksum", "sha256", "--output-encoding", "utf16le", "-"]);
+        let mut stdout = Vec::new();
+        let digest = write_stdout(&args, &mut stdout, "a=1\n").unwrap();
+        assert_eq!(stdout, *encoding::encode(args.output_encoding, "a=1\n"));
+        assert_eq!(digest, Some(checksum::digest(cli::ChecksumAlgorithm::Sha256, &stdout)));
+
+        let args = cli::Args::parse_from(["json2toon", "-"]);
+        assert_eq!(write_stdout(&args, &mut Vec::new(), "a=1\n").unwrap(), None);
+    }
+
+    #[test]
+    fn test_stream_records_encoding() {
+        let args = cli::Args::parse_from(["json2toon", "--jsonl", "--output-encoding", "utf16le", "--checksum", "md5", "-"]);
+        let converter = Converter::new();
+        let mut stdout = Vec::new();
+        let digest = stream_records(&args, &converter, "{\"a\":1}\n\n{\"a\":2}\n".as_bytes(), &mut stdout).unwrap();
+        assert_eq!(stdout, *encoding::encode(args.output_encoding, "0.a=1\n1.a=2\n"));
+        assert_eq!(digest, Some(checksum::digest(cli::ChecksumAlgorithm::Md5, &stdout)));
+
+        let mut stdout = Vec::new();
+        stream_records(&args, &converter, "".as_bytes(), &mut stdout).unwrap();
+        assert_eq!(stdout, [0xFF, 0xFE]);
+    }
+
+    #[test]
+    fn test_dry_run_returns_parse_errors() {
+        let args = cli::Args::parse_from(["json2toon", "-n", "--error-format", "json", "bad.json"]);
+        let err = dry_run(&args, &Converter::new(), "{").unwrap_err();
+        let report = error_report::ErrorReport::from_error(&err).to_json();
+        assert!(report.starts_with(r#"{"error":"parse""#), "{}", report);
+    }
+
+    #[test]
+    fn test_preview() {
+        let toon = "a=1\nb=2\nc=3\n";
+        assert_eq!(preview(toon, 2), "a=1\nb=2\n... (1 more lines)\n");
+        assert_eq!(preview(toon, 3), toon);
+        assert_eq!(preview(toon, 10), toon);
+        assert_eq!(preview(toon, 0), "... (3 more lines)\n");
+    }
+
+    #[test]
+    fn test_lossy_utf8() {
+        assert_eq!(lossy_utf8(b"{\"a\": \"ok\"}"), ("{\"a\": \"ok\"}".to_string(), 0));
+
+        let (content, replaced) = lossy_utf8(b"\"caf\xe9 \xff\xfe ok \xef\xbf\xbd\"");
+        // Each invalid byte becomes its own U+FFFD; a literal U+FFFD is not counted
+        assert_eq!(content, "\"caf\u{fffd} \u{fffd}\u{fffd} ok \u{fffd}\"");
+        assert_eq!(replaced, 3);
+    }
+}
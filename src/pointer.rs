@@ -0,0 +1,105 @@
+//! JSON Pointer leaf listing for `--format jsonpath`
+
+use crate::converter::ValueSink;
+use crate::prelude::*;
+use alloc::borrow::Cow;
+use core::fmt::Write;
+use serde_json::{Number, Value};
+
+/// Writer producing one `<pointer> <json>` line per entry, e.g. `/config/db/host "x"`
+///
+/// Keys are expected to already be RFC 6901 pointers; see [`escape_segment`].
+/// Values are JSON-encoded, so the part after the first space of a line
+/// parses as JSON.
+#[derive(Default)]
+pub struct PointerWriter {
+    output: String,
+}
+
+impl PointerWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn write_line(&mut self, key: &str, value: impl core::fmt::Display) {
+        writeln!(self.output, "{} {}", key, value).expect("writing to a String cannot fail");
+    }
+
+    pub fn finish(self) -> String {
+        self.output
+    }
+}
+
+impl ValueSink for PointerWriter {
+    fn write_string(&mut self, key: &str, value: &str) {
+        self.write_line(key, Value::from(value));
+    }
+
+    fn write_number(&mut self, key: &str, value: f64) {
+        match Number::from_f64(value) {
+            Some(n) => self.write_line(key, n),
+            None => self.write_line(key, "null"),
+        }
+    }
+
+    fn write_integer(&mut self, key: &str, value: i128) {
+        self.write_line(key, value);
+    }
+
+    fn write_bool(&mut self, key: &str, value: bool) {
+        self.write_line(key, value);
+    }
+
+    fn write_null(&mut self, key: &str) {
+        self.write_line(key, "null");
+    }
+
+    fn write_empty_array(&mut self, key: &str) {
+        self.write_line(key, "[]");
+    }
+
+    fn write_empty_object(&mut self, key: &str) {
+        self.write_line(key, "{}");
+    }
+
+    fn output_len(&self) -> Option<usize> {
+        Some(self.output.len())
+    }
+}
+
+/// Escape one reference token per RFC 6901: `~` as `~0` and `/` as `~1`
+pub fn escape_segment(segment: &str) -> Cow<'_, str> {
+    if segment.contains(['~', '/']) {
+        Cow::Owned(segment.replace('~', "~0").replace('/', "~1"))
+    } else {
+        Cow::Borrowed(segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_segment() {
+        assert_eq!(escape_segment("host"), "host");
+        assert_eq!(escape_segment("a/b"), "a~1b");
+        assert_eq!(escape_segment("~/"), "~0~1");
+    }
+
+    #[test]
+    fn test_writer() {
+        let mut writer = PointerWriter::new();
+        writer.write_string("/name", "a \"b\"");
+        writer.write_integer("/n", 3);
+        writer.write_number("/f", 1.5);
+        writer.write_bool("/ok", true);
+        writer.write_null("/none");
+        writer.write_empty_array("/xs");
+        writer.write_empty_object("/o");
+        assert_eq!(
+            writer.finish(),
+            "/name \"a \\\"b\\\"\"\n/n 3\n/f 1.5\n/ok true\n/none null\n/xs []\n/o {}\n"
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Phase and per-key timings for `--profile`
+
+use crate::converter::ValueSink;
+use crate::toon::Cell;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Where the time of one conversion went
+///
+/// `Converter::convert_profiled` fills in the parse and traverse phases and
+/// the top-level keys; the caller sets `read_ms`, `write_ms` and `total_ms`
+/// around its own I/O.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Profile {
+    pub total_ms: f64,
+    pub read_ms: f64,
+    pub parse_ms: f64,
+    /// Flattening the parsed tree into the output buffer
+    pub traverse_ms: f64,
+    pub write_ms: f64,
+    /// Traversal time of each top-level key (or array index), in document order
+    pub top_level_keys: Vec<KeyTiming>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyTiming {
+    pub key: String,
+    pub duration_ms: f64,
+    /// Entries written under the key
+    pub entries: usize,
+}
+
+/// Milliseconds as a float, the unit of every `Profile` duration
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Sink forwarding to another while timing each top-level subtree
+pub(crate) struct ProfilingSink<'a, S> {
+    inner: &'a mut S,
+    current: Option<(String, Instant, usize)>,
+    timings: Vec<KeyTiming>,
+}
+
+impl<'a, S: ValueSink> ProfilingSink<'a, S> {
+    pub(crate) fn new(inner: &'a mut S) -> Self {
+        Self {
+            inner,
+            current: None,
+            timings: Vec::new(),
+        }
+    }
+
+    /// Close the last key and return the timings
+    pub(crate) fn finish(mut self) -> Vec<KeyTiming> {
+        self.close_key();
+        self.timings
+    }
+
+    fn close_key(&mut self) {
+        if let Some((key, start, entries)) = self.current.take() {
+            self.timings.push(KeyTiming {
+                key,
+                duration_ms: millis(start.elapsed()),
+                entries,
+            });
+        }
+    }
+
+    fn entry(&mut self) {
+        if let Some((_, _, entries)) = &mut self.current {
+            *entries += 1;
+        }
+    }
+}
+
+impl<S: ValueSink> ValueSink for ProfilingSink<'_, S> {
+    fn write_string(&mut self, key: &str, value: &str) {
+        self.entry();
+        self.inner.write_string(key, value);
+    }
+
+    fn write_number(&mut self, key: &str, value: f64) {
+        self.entry();
+        self.inner.write_number(key, value);
+    }
+
+    fn write_integer(&mut self, key: &str, value: i128) {
+        self.entry();
+        self.inner.write_integer(key, value);
+    }
+
+    fn write_bool(&mut self, key: &str, value: bool) {
+        self.entry();
+        self.inner.write_bool(key, value);
+    }
+
+    fn write_null(&mut self, key: &str) {
+        self.entry();
+        self.inner.write_null(key);
+    }
+
+    fn write_empty_array(&mut self, key: &str) {
+        self.entry();
+        self.inner.write_empty_array(key);
+    }
+
+    fn write_empty_object(&mut self, key: &str) {
+        self.entry();
+        self.inner.write_empty_object(key);
+    }
+
+    fn write_blank_line(&mut self) {
+        self.inner.write_blank_line();
+    }
+
+    fn set_depth(&mut self, depth: usize) {
+        self.inner.set_depth(depth);
+    }
+
+    fn output_len(&self) -> Option<usize> {
+        self.inner.output_len()
+    }
+
+    fn start_top_level(&mut self, key: &str) {
+        self.close_key();
+        self.current = Some((key.to_string(), Instant::now(), 0));
+    }
+
+    fn begin_array(&mut self, key: &str) {
+        self.inner.begin_array(key);
+    }
+
+    fn end_array(&mut self, key: &str) {
+        self.inner.end_array(key);
+    }
+
+    fn begin_object(&mut self, key: &str) {
+        self.inner.begin_object(key);
+    }
+
+    fn end_object(&mut self, key: &str) {
+        self.inner.end_object(key);
+    }
+
+    fn write_table(&mut self, key: &str, columns: &[&str], rows: &[Vec<Cell>]) -> bool {
+        let written = self.inner.write_table(key, columns, rows);
+        if written && let Some((_, _, entries)) = &mut self.current {
+            *entries += rows.len() * columns.len();
+        }
+        written
+    }
+
+    fn write_scalar_array(&mut self, key: &str, items: &[Cell]) -> bool {
+        let written = self.inner.write_scalar_array(key, items);
+        if written {
+            self.entry();
+        }
+        written
+    }
+}
@@ -0,0 +1,111 @@
+//! Lenient JSON syntax for `--try-relaxed`
+
+use crate::prelude::*;
+
+/// Rewrite relaxed JSON into strict JSON text
+///
+/// Accepts `//` and `/* */` comments, a trailing comma before `]` or `}`,
+/// and `'single-quoted'` strings (where `\'` is a quote and `"` needs no
+/// escape). Comments become spaces, keeping their newlines, so strict parse
+/// errors still point at the right line. Anything else is copied as is and
+/// left for the strict parser to accept or reject.
+pub fn to_strict(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    // Index in `output` of a comma that may turn out to be trailing
+    let mut pending_comma = None;
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                pending_comma = None;
+                output.push('"');
+                while let Some(ch) = chars.next() {
+                    output.push(ch);
+                    match ch {
+                        '\\' => output.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '\'' => {
+                pending_comma = None;
+                output.push('"');
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => match chars.next() {
+                            Some('\'') => output.push('\''),
+                            Some(escaped) => {
+                                output.push('\\');
+                                output.push(escaped);
+                            }
+                            None => output.push('\\'),
+                        },
+                        '"' => output.push_str("\\\""),
+                        '\'' => break,
+                        _ => output.push(ch),
+                    }
+                }
+                output.push('"');
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&ch| ch != '\n').is_some() {}
+                output.push(' ');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = '\0';
+                for ch in chars.by_ref() {
+                    if last == '*' && ch == '/' {
+                        break;
+                    }
+                    if ch == '\n' {
+                        output.push('\n');
+                    }
+                    last = ch;
+                }
+                output.push(' ');
+            }
+            ',' => {
+                pending_comma = Some(output.len());
+                output.push(',');
+            }
+            ']' | '}' => {
+                if let Some(index) = pending_comma.take() {
+                    output.replace_range(index..index + 1, " ");
+                }
+                output.push(ch);
+            }
+            _ => {
+                if !ch.is_whitespace() {
+                    pending_comma = None;
+                }
+                output.push(ch);
+            }
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_strict() {
+        let relaxed = "{\n  // name\n  'name': 'it\\'s \"x\"', /* a\nb */ \"tags\": [1, 2,],\n}";
+        let strict = to_strict(relaxed);
+        assert_eq!(strict, "{\n   \n  \"name\": \"it's \\\"x\\\"\", \n  \"tags\": [1, 2 ] \n}");
+        let value: serde_json::Value = serde_json::from_str(&strict).unwrap();
+        assert_eq!(value, serde_json::json!({"name": "it's \"x\"", "tags": [1, 2]}));
+    }
+
+    #[test]
+    fn test_strings_are_left_alone() {
+        let strict = r#"{"url": "http://x/*y*/", "list": "[1,]", "q": "'"}"#;
+        assert_eq!(to_strict(strict), strict);
+        // A comma that is not trailing is kept, so the strict parser still rejects `[1,,2]`
+        assert_eq!(to_strict("[1,,2]"), "[1,,2]");
+    }
+}
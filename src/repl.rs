@@ -0,0 +1,195 @@
+//! Interactive `repl` subcommand: convert JSON snippets typed on stdin
+
+use crate::cli::parse_separator;
+use json2toon::converter::{ConvertOptions, Converter};
+use json2toon::diag;
+use json2toon::toon::{ToonOptions, check_assign};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+Enter a JSON snippet followed by a blank line to convert it.
+Commands:
+  :set separator <char>                 Key separator (default .)
+  :set annotate-types <on|off>          Embed JSON types in keys
+  :set unquoted-safe-strings <on|off>   Leave safe strings unquoted
+  :set skip-nulls <on|off>              Omit null values
+  :help                                 Show this help
+  :quit                                 Exit
+";
+
+/// Options carried between snippets and changed with `:set`
+struct Session {
+    convert: ConvertOptions,
+    format: ToonOptions,
+}
+
+impl Session {
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "separator" => {
+                let separator = parse_separator(value)?;
+                check_assign(&self.format.assign, separator)?;
+                self.format.separator = separator;
+            }
+            "annotate-types" => self.format.annotate_types = parse_switch(value)?,
+            "unquoted-safe-strings" => self.format.unquoted_safe_strings = parse_switch(value)?,
+            "skip-nulls" => self.convert.skip_nulls = parse_switch(value)?,
+            _ => return Err(format!("unknown option: {}", name)),
+        }
+        Ok(())
+    }
+
+    fn convert(&self, json: &str) -> String {
+        Converter::new()
+            .with_options(self.convert.clone())
+            .with_format(self.format.clone())
+            .convert(json)
+            .unwrap_or_else(|e| format!("{} {}\n", diag::error_tag(), error_chain(&e)))
+    }
+}
+
+/// Read snippets from `input` until `:quit` or end of input, writing TOON to `output`
+pub fn run<R: BufRead, W: Write>(
+    input: R,
+    output: &mut W,
+    convert: ConvertOptions,
+    format: ToonOptions,
+    prompt: bool,
+) -> io::Result<()> {
+    let mut session = Session { convert, format };
+    let mut snippet = String::new();
+    let mut lines = input.lines();
+
+    loop {
+        if prompt {
+            let prompt = if snippet.is_empty() {
+                "json> "
+            } else {
+                "...> "
+            };
+            write!(output, "{}", prompt)?;
+            output.flush()?;
+        }
+
+        let Some(line) = lines.next().transpose()? else {
+            break;
+        };
+
+        if snippet.is_empty() {
+            if let Some(command) = line.trim().strip_prefix(':') {
+                match run_command(&mut session, command) {
+                    Command::Quit => return Ok(()),
+                    Command::Reply(reply) => write!(output, "{}", reply)?,
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+        } else if line.trim().is_empty() {
+            write!(output, "{}", session.convert(&snippet))?;
+            snippet.clear();
+            continue;
+        }
+
+        snippet.push_str(&line);
+        snippet.push('\n');
+    }
+
+    // Convert a final snippet that was not followed by a blank line
+    if !snippet.is_empty() {
+        write!(output, "{}", session.convert(&snippet))?;
+    }
+    Ok(())
+}
+
+enum Command {
+    Quit,
+    /// Text to print, possibly empty
+    Reply(String),
+}
+
+fn run_command(session: &mut Session, command: &str) -> Command {
+    let mut words = command.split_whitespace();
+    let reply = match (words.next(), words.next(), words.next(), words.next()) {
+        (Some("quit" | "q"), None, ..) => return Command::Quit,
+        (Some("help"), None, ..) => HELP.to_string(),
+        (Some("set"), Some(name), Some(value), None) => match session.set(name, value) {
+            Ok(()) => String::new(),
+            Err(e) => format!("{} {}\n", diag::error_tag(), e),
+        },
+        _ => format!(
+            "{} unknown command :{} (try :help)\n",
+            diag::error_tag(),
+            command
+        ),
+    };
+    Command::Reply(reply)
+}
+
+fn parse_switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(format!("expected on or off, got {}", value)),
+    }
+}
+
+/// Error message with its causes, like `{:#}` on an `anyhow::Error`
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repl(input: &str) -> String {
+        let mut output = Vec::new();
+        run(
+            input.as_bytes(),
+            &mut output,
+            ConvertOptions::default(),
+            ToonOptions::default(),
+            false,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_converts_snippets() {
+        let output = repl("{\"a\": {\n  \"b\": 1\n}}\n\n[true]\n");
+        assert_eq!(output, "a.b=1\n0=true\n");
+    }
+
+    #[test]
+    fn test_set_separator_persists() {
+        let output = repl(":set separator /\n{\"a\": {\"b\": 1}}\n\n{\"c\": [2]}\n\n");
+        assert_eq!(output, "a/b=1\nc/0=2\n");
+    }
+
+    #[test]
+    fn test_quit_stops_reading() {
+        let output = repl("{\"a\": 1}\n\n:quit\n{\"b\": 2}\n\n");
+        assert_eq!(output, "a=1\n");
+    }
+
+    #[test]
+    fn test_errors_do_not_end_session() {
+        let output = repl("{\"a\":\n\n:set separator ==\n:bogus\n{\"b\": 2}\n");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("Failed to parse JSON: "));
+        assert!(lines[1].contains("single character"));
+        assert!(lines[2].contains("unknown command :bogus"));
+        assert_eq!(lines[3], "b=2");
+    }
+}
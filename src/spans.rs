@@ -0,0 +1,225 @@
+//! Source line numbers of JSON values for `--source-lines`, and number
+//! literals as written for `--float-style preserve`
+
+use crate::prelude::*;
+
+/// One step of the path from the document root to a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Object member, with the key as decoded from the source
+    Key(String),
+    /// Array item
+    Index(usize),
+}
+
+/// The 1-based line on which each value of a JSON document starts, in document order
+///
+/// Every value is listed, containers before their members, under its path
+/// from the root (the root itself has an empty path). `json` must already
+/// be known to parse, since this scanner only finds where values start and
+/// does not validate anything.
+pub fn value_lines(json: &str) -> Vec<(Vec<PathSegment>, usize)> {
+    let mut scanner = Scanner::new(json, false);
+    scanner.value();
+    scanner.lines
+}
+
+/// The text of each number in a JSON document as written, e.g. `98.50`, in document order
+///
+/// Numbers are listed under their path from the root, like [`value_lines`]
+/// lists values; `json` must already be known to parse.
+pub fn number_literals(json: &str) -> Vec<(Vec<PathSegment>, &str)> {
+    let mut scanner = Scanner::new(json, true);
+    scanner.value();
+    scanner.numbers
+}
+
+struct Scanner<'a> {
+    json: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+    path: Vec<PathSegment>,
+    lines: Vec<(Vec<PathSegment>, usize)>,
+    /// Whether to collect `numbers` rather than `lines`
+    numbers_only: bool,
+    numbers: Vec<(Vec<PathSegment>, &'a str)>,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(json: &'a str, numbers_only: bool) -> Self {
+        Self {
+            bytes: json.as_bytes(),
+            json,
+            pos: 0,
+            line: 1,
+            path: Vec::new(),
+            lines: Vec::new(),
+            numbers_only,
+            numbers: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(byte) = self.peek() {
+            match byte {
+                b'\n' => self.line += 1,
+                b' ' | b'\t' | b'\r' => {}
+                _ => return,
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) {
+        self.skip_whitespace();
+        if !self.numbers_only {
+            self.lines.push((self.path.clone(), self.line));
+        }
+        match self.peek() {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => {
+                self.string();
+            }
+            // Numbers, `true`, `false` and `null` run up to the next delimiter
+            first => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|byte| !matches!(byte, b',' | b']' | b'}') && !byte.is_ascii_whitespace())
+                {
+                    self.pos += 1;
+                }
+                if self.numbers_only && first.is_some_and(|byte| byte == b'-' || byte.is_ascii_digit()) {
+                    self.numbers.push((self.path.clone(), &self.json[start..self.pos]));
+                }
+            }
+        }
+    }
+
+    fn object(&mut self) {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return;
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string();
+            let key = if key.contains('\\') {
+                serde_json::from_str(key).unwrap_or_default()
+            } else {
+                key[1..key.len() - 1].to_string()
+            };
+            self.skip_whitespace();
+            // The `:`
+            self.pos += 1;
+            self.path.push(PathSegment::Key(key));
+            self.value();
+            self.path.pop();
+            if !self.next_member(b'}') {
+                return;
+            }
+        }
+    }
+
+    fn array(&mut self) {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return;
+        }
+        for index in 0.. {
+            self.path.push(PathSegment::Index(index));
+            self.value();
+            self.path.pop();
+            if !self.next_member(b']') {
+                return;
+            }
+        }
+    }
+
+    /// Step past the `,` before another member, or the closing bracket, returning whether another follows
+    fn next_member(&mut self, close: u8) -> bool {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b',') => {
+                self.pos += 1;
+                true
+            }
+            Some(byte) if byte == close => {
+                self.pos += 1;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// Step over a string, returning it with its quotes and escapes as written
+    fn string(&mut self) -> &'a str {
+        let start = self.pos;
+        self.pos += 1;
+        while let Some(byte) = self.peek() {
+            self.pos += 1;
+            match byte {
+                b'\\' => self.pos += 1,
+                b'"' => break,
+                _ => {}
+            }
+        }
+        &self.json[start..self.pos.min(self.json.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str) -> PathSegment {
+        PathSegment::Key(key.to_string())
+    }
+
+    #[test]
+    fn test_value_lines() {
+        let json = "{\n  \"name\": \"a,b]\\\"\",\n  \"tags\": [\n    1,\n    {}\n  ],\n  \"k\\u0065y\": null\n}";
+        assert_eq!(
+            value_lines(json),
+            [
+                (vec![], 1),
+                (vec![key("name")], 2),
+                (vec![key("tags")], 3),
+                (vec![key("tags"), PathSegment::Index(0)], 4),
+                (vec![key("tags"), PathSegment::Index(1)], 5),
+                (vec![key("key")], 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let json = r#"{"price": 98.50, "n": [1E+2, -0.0, "7.0"], "ok": true}"#;
+        assert_eq!(
+            number_literals(json),
+            [
+                (vec![key("price")], "98.50"),
+                (vec![key("n"), PathSegment::Index(0)], "1E+2"),
+                (vec![key("n"), PathSegment::Index(1)], "-0.0"),
+            ]
+        );
+        assert_eq!(number_literals("\n 5.0 \n"), [(vec![], "5.0")]);
+    }
+
+    #[test]
+    fn test_scalar_and_empty_documents() {
+        assert_eq!(value_lines("\n\n42\n"), [(vec![], 3)]);
+        assert_eq!(value_lines("[]"), [(vec![], 1)]);
+        assert_eq!(value_lines(r#"{"a": [[], {"b": true}]}"#).len(), 5);
+    }
+}
@@ -1,3 +1,35 @@
+use crate::prelude::*;
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use core::fmt::Write;
+
+/// Default separator between key path segments
+pub const SEPARATOR: char = '.';
+
+/// Quote character surrounding string values
+pub const QUOTE: char = '"';
+
+/// Default operator between a key and its value
+pub const ASSIGN: &str = "=";
+
+/// Start of the tokens `--dedup-values` writes for repeated strings: `&v1`, `&v2`, ...
+pub const REFERENCE_PREFIX: &str = "&v";
+
+/// Start of the line `--emit-root-type` writes for a top-level non-object, e.g. `#root=array`
+pub const ROOT_TYPE_PREFIX: &str = "#root=";
+
+/// Default marker for an empty array, written without quotes
+pub const EMPTY_ARRAY: &str = "[]";
+
+/// Default marker for an empty object, written without quotes
+pub const EMPTY_OBJECT: &str = "{}";
+
+/// Default magnitude below which whole floats are written as integers, 2^53
+///
+/// Every integer below it is exactly representable as an f64, so no two
+/// distinct integers share a float and the written digits are exact.
+pub const WHOLE_NUMBER_LIMIT: u64 = 1 << 53;
+
 /// TOON (Token-Oriented-Object-Notation) writer
 ///
 /// Converts JSON structures to TOON format using flattened key-value pairs.
@@ -7,36 +39,729 @@
 /// - Numbers, booleans, and null are unquoted
 pub struct ToonWriter {
     buffer: String,
+    options: ToonOptions,
+    /// Section headers currently open in pretty mode
+    open_sections: Vec<String>,
+    /// Number of key segments of the entries being written
+    depth: usize,
+    /// Number of the reference token standing for each interned string value
+    references: BTreeMap<String, usize>,
+    /// Source line of the value behind each key, noted after its entry
+    source_lines: BTreeMap<String, usize>,
+    /// Source text of the number behind each key, for `FloatStyle::Preserve`
+    number_literals: BTreeMap<String, String>,
+}
+
+/// Indentation used by the pretty (nested) renderer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    /// Indent each level by this many spaces
+    Spaces(usize),
+    /// Indent each level by one tab
+    Tab,
+}
+
+/// Overall shape of the output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// `key=value` lines
+    #[default]
+    Toon,
+    /// Two-column `key,value` CSV
+    #[cfg(feature = "std")]
+    Csv,
+    /// Two-column tab-separated `key<TAB>value`
+    #[cfg(feature = "std")]
+    Tsv,
+    /// `<JSON Pointer> <JSON value>` lines, e.g. `/config/db/host "x"`
+    JsonPointer,
+}
+
+/// Which half of each entry is written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Projection {
+    /// Full `key=value` lines
+    #[default]
+    Entries,
+    /// Only the key of each entry
+    KeysOnly,
+    /// Only the value of each entry
+    ValuesOnly,
+}
+
+/// Which length `--annotate-bytes` reports for each value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ByteCount {
+    /// The value's own text, before quoting, escaping and truncation
+    #[default]
+    Raw,
+    /// The value exactly as written, quotes and escapes included
+    Escaped,
+}
+
+/// How floats are written, e.g. `5.0` and `98.50` from the source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FloatStyle {
+    /// Whole floats below `whole_number_limit` become integers (`5`), others
+    /// are trimmed (`98.5`)
+    #[default]
+    IntegerCollapse,
+    /// The shortest text that reads back as the same float, always with a
+    /// decimal point or exponent (`5.0`, `98.5`)
+    Trim,
+    /// The number as written in the JSON source (`5.0`, `98.50`), for entries
+    /// whose source text is known; others are trimmed
+    Preserve,
+}
+
+/// Formatting options for `ToonWriter`
+#[derive(Debug, Clone)]
+pub struct ToonOptions {
+    /// Character joining key path segments, `.` by default
+    pub separator: char,
+    /// Character joining an array index to its array's key instead of the
+    /// `separator`, e.g. `#` for `user.items#0`, so array items cannot be
+    /// mistaken for object fields
+    pub array_separator: Option<char>,
+    /// Text between each key and its value, `=` by default; see [`check_assign`]
+    pub assign: String,
+    /// Embed the source JSON type in each key, e.g. `age:integer=30`
+    pub annotate_types: bool,
+    /// Omit quotes around strings that cannot be mistaken for other values
+    pub unquoted_safe_strings: bool,
+    /// Render nested sections as indented blocks instead of full dotted keys
+    pub pretty: Option<Indent>,
+    /// Render each entry through a user format string instead of `key=value`
+    pub template: Option<Template>,
+    /// Write TOON lines or delimited `key,value` records
+    pub output_format: OutputFormat,
+    /// Prefix each line with its nesting level, e.g. `2 | config.db.host="x"`
+    pub show_depth: bool,
+    /// Write arrays of uniform flat objects as a header line plus one line per row,
+    /// rows always in array order
+    pub tabular: bool,
+    /// Write arrays of only scalars on one line, e.g. `ids=[1,2,3]`
+    pub fold_scalar_arrays: bool,
+    /// Keep only the first N characters of longer strings, adding `…(M more)`
+    pub truncate_strings: Option<usize>,
+    /// Quote numbers like strings, e.g. `age="30"`
+    pub quote_numbers: bool,
+    /// Quote booleans like strings, e.g. `active="true"`
+    pub quote_bools: bool,
+    /// Write full entries, or only their keys or values
+    pub projection: Projection,
+    /// Escape the separator inside quoted string values, e.g. `"a\/b"` with `/`
+    pub escape_separator: bool,
+    /// Write object keys made only of digits quoted, e.g. `ids."0"="a"`, so they
+    /// cannot be mistaken for array indices; see [`is_quoted_index`]
+    pub quote_numeric_keys: bool,
+    /// Bare value written for an empty array, `[]` by default; see [`check_empty_token`]
+    pub empty_array_token: String,
+    /// Bare value written for an empty object, `{}` by default
+    pub empty_object_token: String,
+    /// How floats are written; see [`FloatStyle`]
+    pub float_style: FloatStyle,
+    /// Whole floats below this magnitude are written as integers, e.g. `age=42`
+    /// rather than `age=42.0`, with `FloatStyle::IntegerCollapse`; see [`is_whole_number`]
+    pub whole_number_limit: u64,
+    /// Collapse each run of whitespace inside string values to one space and
+    /// trim both ends (table cells and folded arrays are left as they are);
+    /// see [`normalize_whitespace`]
+    pub normalize_whitespace: bool,
+    /// Follow each entry with the UTF-8 byte length of its value, e.g. `name="Ada" (3B)`;
+    /// table rows are not annotated
+    pub annotate_bytes: Option<ByteCount>,
+}
+
+impl Default for ToonOptions {
+    fn default() -> Self {
+        Self {
+            separator: SEPARATOR,
+            array_separator: None,
+            assign: ASSIGN.to_string(),
+            annotate_types: false,
+            unquoted_safe_strings: false,
+            pretty: None,
+            template: None,
+            output_format: OutputFormat::default(),
+            show_depth: false,
+            tabular: false,
+            fold_scalar_arrays: false,
+            truncate_strings: None,
+            quote_numbers: false,
+            quote_bools: false,
+            projection: Projection::default(),
+            escape_separator: false,
+            quote_numeric_keys: false,
+            empty_array_token: EMPTY_ARRAY.to_string(),
+            empty_object_token: EMPTY_OBJECT.to_string(),
+            float_style: FloatStyle::default(),
+            whole_number_limit: WHOLE_NUMBER_LIMIT,
+            normalize_whitespace: false,
+            annotate_bytes: None,
+        }
+    }
+}
+
+/// Per-line format string with `{key}`, `{value}` and `{type}` placeholders
+///
+/// `{{` and `}}` produce literal braces, and `\t`, `\n` and `\\` are
+/// unescaped so tab-separated formats can be given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Key,
+    Value,
+    Type,
+}
+
+impl Template {
+    /// Parse a format string, requiring both `{key}` and `{value}`
+    pub fn parse(format: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' => {
+                    let rest = chars.as_str();
+                    if let Some(stripped) = rest.strip_prefix('{') {
+                        literal.push('{');
+                        chars = stripped.chars();
+                        continue;
+                    }
+                    let Some(close) = rest.find('}') else {
+                        return Err("unclosed '{' in template".to_string());
+                    };
+                    let segment = match &rest[..close] {
+                        "key" => Segment::Key,
+                        "value" => Segment::Value,
+                        "type" => Segment::Type,
+                        other => return Err(format!("unknown template placeholder {{{}}}", other)),
+                    };
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(core::mem::take(&mut literal)));
+                    }
+                    segments.push(segment);
+                    chars = rest[close + 1..].chars();
+                }
+                '}' => {
+                    if chars.as_str().starts_with('}') {
+                        chars.next();
+                    }
+                    literal.push('}');
+                }
+                _ => literal.push(ch),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        for (required, name) in [(Segment::Key, "{key}"), (Segment::Value, "{value}")] {
+            if !segments.contains(&required) {
+                return Err(format!("template must contain {}", name));
+            }
+        }
+        Ok(Self { segments })
+    }
+}
+
+/// Scalar value in a row of a tabular array
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cell<'a> {
+    String(&'a str),
+    Number(f64),
+    Integer(i128),
+    Bool(bool),
+    Null,
+}
+
+/// Value part of an entry as it appears after `=`
+#[derive(Clone, Copy)]
+pub(crate) enum EntryValue<'a> {
+    /// Written as-is
+    Raw(&'a str),
+    /// Written quoted and escaped
+    Quoted(&'a str),
+    /// The start of a long string, quoted and followed by `…(N more)` for the chars of `rest`
+    Truncated { start: &'a str, rest: &'a str },
+    /// A number, surrounded by quotes for `quote_numbers`
+    Number { value: f64, quoted: bool },
+    /// An exact integer, surrounded by quotes for `quote_numbers`
+    Integer { value: i128, quoted: bool },
+}
+
+impl<'a> EntryValue<'a> {
+    /// A string value, left unquoted only when the options allow it and it is safe
+    pub(crate) fn string(value: &'a str, options: &ToonOptions) -> Self {
+        if options.unquoted_safe_strings
+            && is_safe_unquoted(value, options.separator, &options.assign)
+            && value != options.empty_array_token
+            && value != options.empty_object_token
+        {
+            EntryValue::Raw(value)
+        } else {
+            EntryValue::quoted(value, options)
+        }
+    }
+
+    /// A string value that is always quoted, truncated past `truncate_strings`
+    pub(crate) fn quoted(value: &'a str, options: &ToonOptions) -> Self {
+        if let Some(limit) = options.truncate_strings
+            && let Some((end, _)) = value.char_indices().nth(limit)
+        {
+            let (start, rest) = value.split_at(end);
+            return EntryValue::Truncated { start, rest };
+        }
+        EntryValue::Quoted(value)
+    }
+
+    /// A number, quoted when the options ask for it and written as an integer when
+    /// whole and collapsed by the `float_style`
+    pub(crate) fn number(value: f64, options: &ToonOptions) -> Self {
+        if collapses_to_integer(value, options) {
+            return Self::integer(value as i128, options);
+        }
+        EntryValue::Number {
+            value,
+            quoted: options.quote_numbers,
+        }
+    }
+
+    /// An integer, quoted when the options ask for it
+    pub(crate) fn integer(value: i128, options: &ToonOptions) -> Self {
+        EntryValue::Integer {
+            value,
+            quoted: options.quote_numbers,
+        }
+    }
+
+    /// A boolean, quoted when the options ask for it
+    pub(crate) fn bool(value: bool, options: &ToonOptions) -> Self {
+        let text = if value { "true" } else { "false" };
+        if options.quote_bools {
+            EntryValue::Quoted(text)
+        } else {
+            EntryValue::Raw(text)
+        }
+    }
+
+    /// UTF-8 length of the value as written, or of its text before quoting,
+    /// escaping and truncation for `ByteCount::Raw`
+    fn byte_len(&self, count: ByteCount, options: &ToonOptions) -> usize {
+        let mut text = String::new();
+        match (count, *self) {
+            (ByteCount::Raw, EntryValue::Raw(value) | EntryValue::Quoted(value)) => return value.len(),
+            (ByteCount::Raw, EntryValue::Truncated { start, rest }) => return start.len() + rest.len(),
+            (ByteCount::Raw, EntryValue::Number { value, .. }) => write_number_text(&mut text, value),
+            (ByteCount::Raw, EntryValue::Integer { value, .. }) => {
+                // (writing to a String cannot fail)
+                let _ = write!(text, "{}", value);
+            }
+            (ByteCount::Escaped, _) => self.write_to(&mut text, options),
+        }
+        text.len()
+    }
+
+    /// Append the value as written, escaping quoted text as `options` ask
+    pub(crate) fn write_to(&self, buffer: &mut String, options: &ToonOptions) {
+        let separator = options.escape_separator.then_some(options.separator);
+        match *self {
+            EntryValue::Raw(text) => buffer.push_str(text),
+            EntryValue::Quoted(text) => {
+                buffer.push(QUOTE);
+                escape_into(buffer, text, separator);
+                buffer.push(QUOTE);
+            }
+            EntryValue::Truncated { start, rest } => {
+                buffer.push(QUOTE);
+                escape_into(buffer, start, separator);
+                // (writing to a String cannot fail)
+                let _ = write!(buffer, "\u{2026}({} more){}", rest.chars().count(), QUOTE);
+            }
+            EntryValue::Number { value, quoted } => {
+                if quoted {
+                    buffer.push(QUOTE);
+                }
+                write_number_text(buffer, value);
+                if quoted {
+                    buffer.push(QUOTE);
+                }
+            }
+            EntryValue::Integer { value, quoted } => {
+                // (writing to a String cannot fail)
+                let _ = if quoted {
+                    write!(buffer, "{}{}{}", QUOTE, value, QUOTE)
+                } else {
+                    write!(buffer, "{}", value)
+                };
+            }
+        }
+    }
+}
+
+/// Whether a number is written as an integer: whole and below `limit` in magnitude
+///
+/// Larger whole numbers are written like other floats (`1e16`,
+/// `9007199254740992.0`), so the switch happens at one predictable
+/// magnitude.
+pub fn is_whole_number(n: f64, limit: u64) -> bool {
+    // (`f64::fract` needs std; truncation through i128 is exact below 2^64)
+    n.abs() < limit as f64 && n == n as i128 as f64
+}
+
+/// Whether `float_style` and `whole_number_limit` have a float written as an integer
+pub(crate) fn collapses_to_integer(n: f64, options: &ToonOptions) -> bool {
+    options.float_style == FloatStyle::IntegerCollapse && is_whole_number(n, options.whole_number_limit)
+}
+
+/// Collapse each run of whitespace to a single space and trim both ends
+///
+/// Whitespace is any Unicode white space, so tabs and line breaks are
+/// collapsed too; strings that need no change are borrowed.
+pub fn normalize_whitespace(s: &str) -> Cow<'_, str> {
+    let unchanged = !s.starts_with(' ')
+        && !s.ends_with(' ')
+        && !s.contains("  ")
+        && !s.chars().any(|ch| ch.is_whitespace() && ch != ' ');
+    if unchanged {
+        return Cow::Borrowed(s);
+    }
+    let mut words = s.split_whitespace();
+    let mut normalized = String::with_capacity(s.len());
+    normalized.extend(words.next());
+    for word in words {
+        normalized.push(' ');
+        normalized.push_str(word);
+    }
+    Cow::Owned(normalized)
+}
+
+/// Text of a string value as written, normalized when the options ask for it
+pub(crate) fn string_text<'a>(value: &'a str, options: &ToonOptions) -> Cow<'a, str> {
+    if options.normalize_whitespace {
+        normalize_whitespace(value)
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Format a non-whole number
+///
+/// Floats use ryu's shortest representation that parses back to the same
+/// value, switching to exponent notation for very large or small
+/// magnitudes (`1e300` rather than 301 digits). Like integers, which are
+/// written with `Display`, the text never depends on the locale: the
+/// decimal point is always `.` and digits are never grouped, whatever
+/// `LC_NUMERIC` or `LC_ALL` say.
+fn write_number_text(buffer: &mut String, n: f64) {
+    buffer.push_str(ryu::Buffer::new().format(n));
 }
 
 impl ToonWriter {
     pub fn new() -> Self {
+        Self::with_options(ToonOptions::default())
+    }
+
+    pub fn with_options(options: ToonOptions) -> Self {
+        Self::with_capacity(options, 0)
+    }
+
+    /// Create a writer whose buffer is preallocated to hold `capacity` bytes
+    pub fn with_capacity(options: ToonOptions, capacity: usize) -> Self {
         Self {
-            buffer: String::new(),
+            buffer: String::with_capacity(capacity),
+            options,
+            open_sections: Vec::new(),
+            depth: 0,
+            references: BTreeMap::new(),
+            source_lines: BTreeMap::new(),
+            number_literals: BTreeMap::new(),
+        }
+    }
+
+    /// Note the source line of each entry whose key is in `lines`, e.g. `port=8080  # L42`
+    pub fn set_source_lines(&mut self, lines: BTreeMap<String, usize>) {
+        self.source_lines = lines;
+    }
+
+    /// Write the float entries whose key is in `literals` as their text there, e.g.
+    /// `price=98.50`, with `FloatStyle::Preserve`
+    ///
+    /// A literal is only used while it still reads as the entry's value, so
+    /// values changed since parsing are written trimmed.
+    pub fn set_number_literals(&mut self, literals: BTreeMap<String, String>) {
+        self.number_literals = literals;
+    }
+
+    /// End an entry's line, with its source line noted if known
+    fn end_line(&mut self, key: &str) {
+        if let Some(line) = self.source_lines.get(key) {
+            // (writing to a String cannot fail)
+            let _ = write!(self.buffer, "  # L{}", line);
         }
+        self.buffer.push('\n');
+    }
+
+    /// Write the `#root=<type>` line for a top-level value that is not an object
+    pub fn write_root_type(&mut self, type_name: &str) {
+        self.buffer.push_str(ROOT_TYPE_PREFIX);
+        self.buffer.push_str(type_name);
+        self.buffer.push('\n');
+    }
+
+    /// Write a table of `&v1="..."` lines, one per value, followed by a blank line
+    ///
+    /// Later string entries equal to one of the values are written as its
+    /// token, e.g. `key=&v1`, and other strings starting with `&` are always
+    /// quoted so they cannot be mistaken for one. Call before any entries.
+    pub fn write_references(&mut self, values: Vec<String>) {
+        if values.is_empty() {
+            return;
+        }
+        for (i, value) in values.into_iter().enumerate() {
+            let number = i + 1;
+            // (writing to a String cannot fail)
+            let _ = write!(self.buffer, "{}{}{}", REFERENCE_PREFIX, number, self.options.assign);
+            EntryValue::Quoted(&string_text(&value, &self.options)).write_to(&mut self.buffer, &self.options);
+            self.buffer.push('\n');
+            self.references.insert(value, number);
+        }
+        self.buffer.push('\n');
     }
 
     pub fn write_string(&mut self, key: &str, value: &str) {
-        let escaped = escape_string(value);
-        self.buffer
-            .push_str(&format!("{}=\"{}\"\n", key, escaped));
+        if let Some(number) = self.references.get(value) {
+            let token = format!("{}{}", REFERENCE_PREFIX, number);
+            self.write_entry(key, "string", EntryValue::Raw(&token));
+            return;
+        }
+        let text = string_text(value, &self.options);
+        let value = if !self.references.is_empty() && text.starts_with('&') {
+            EntryValue::quoted(&text, &self.options)
+        } else {
+            EntryValue::string(&text, &self.options)
+        };
+        self.write_entry(key, "string", value);
     }
 
     pub fn write_number(&mut self, key: &str, value: f64) {
-        // Format number without unnecessary decimals
-        if value.fract() == 0.0 && value.abs() < 1e15 {
-            self.buffer.push_str(&format!("{}={}\n", key, value as i64));
-        } else {
-            self.buffer.push_str(&format!("{}={}\n", key, value));
-        }
+        let literal = match self.options.float_style {
+            FloatStyle::Preserve => self.number_literals.get(key).filter(|text| text.parse() == Ok(value)).cloned(),
+            _ => None,
+        };
+        let entry = match &literal {
+            Some(text) if self.options.quote_numbers => EntryValue::Quoted(text),
+            Some(text) => EntryValue::Raw(text),
+            None => EntryValue::number(value, &self.options),
+        };
+        self.write_entry(key, "float", entry);
+    }
+
+    /// Write an integer exactly, tagged `integer` when annotating types
+    pub fn write_integer(&mut self, key: &str, value: i128) {
+        let value = EntryValue::integer(value, &self.options);
+        self.write_entry(key, "integer", value);
     }
 
     pub fn write_bool(&mut self, key: &str, value: bool) {
-        self.buffer.push_str(&format!("{}={}\n", key, value));
+        let value = EntryValue::bool(value, &self.options);
+        self.write_entry(key, "bool", value);
     }
 
     pub fn write_null(&mut self, key: &str) {
-        self.buffer.push_str(&format!("{}=null\n", key));
+        self.write_entry(key, "null", EntryValue::Raw("null"));
+    }
+
+    /// Write the marker for an empty JSON array
+    pub fn write_empty_array(&mut self, key: &str) {
+        let token = self.options.empty_array_token.clone();
+        self.write_entry(key, "array", EntryValue::Raw(&token));
+    }
+
+    /// Write the marker for an empty JSON object
+    pub fn write_empty_object(&mut self, key: &str) {
+        let token = self.options.empty_object_token.clone();
+        self.write_entry(key, "object", EntryValue::Raw(&token));
+    }
+
+    /// Write an empty line, used to visually group entries
+    pub fn write_blank_line(&mut self) {
+        self.buffer.push('\n');
+    }
+
+    /// Write the header of a tabular array, e.g. `rows[2]{id,name}:`
+    ///
+    /// Column names must satisfy [`is_table_column`].
+    pub fn write_table_header(&mut self, key: &str, columns: &[&str], rows: usize) {
+        escape_key_into(&mut self.buffer, &self.options, key);
+        // (writing to a String cannot fail)
+        let _ = write!(self.buffer, "[{}]{{", rows);
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                self.buffer.push(',');
+            }
+            escape_into(&mut self.buffer, column, None);
+        }
+        self.buffer.push_str("}:");
+        self.end_line(key);
+    }
+
+    /// Write one indented, comma-separated row of a tabular array
+    ///
+    /// Strings containing a comma are always quoted so cells stay unambiguous.
+    pub fn write_table_row(&mut self, cells: &[Cell]) {
+        self.buffer.push_str("  ");
+        for (i, cell) in cells.iter().enumerate() {
+            if i > 0 {
+                self.buffer.push(',');
+            }
+            let value = self.cell_value(cell, &[',']);
+            value.write_to(&mut self.buffer, &self.options);
+        }
+        self.buffer.push('\n');
+    }
+
+    /// Write an array of scalars as one entry, e.g. `ids=[1,2,3]`
+    ///
+    /// Items are quoted as standalone values would be, and strings containing
+    /// a comma or bracket are always quoted so the list stays unambiguous.
+    pub fn write_scalar_array(&mut self, key: &str, items: &[Cell]) {
+        let mut list = String::from("[");
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                list.push(',');
+            }
+            self.cell_value(item, &[',', '[', ']']).write_to(&mut list, &self.options);
+        }
+        list.push(']');
+        self.write_entry(key, "array", EntryValue::Raw(&list));
+    }
+
+    /// How a cell is written, quoting strings that contain any of `delimiters`
+    fn cell_value<'c>(&self, cell: &Cell<'c>, delimiters: &[char]) -> EntryValue<'c> {
+        match *cell {
+            Cell::String(s) if s.contains(delimiters) => EntryValue::quoted(s, &self.options),
+            Cell::String(s) => EntryValue::string(s, &self.options),
+            Cell::Number(n) => EntryValue::number(n, &self.options),
+            Cell::Integer(n) => EntryValue::integer(n, &self.options),
+            Cell::Bool(b) => EntryValue::bool(b, &self.options),
+            Cell::Null => EntryValue::Raw("null"),
+        }
+    }
+
+    /// Set the number of key segments of the entries that follow
+    ///
+    /// Only used by `show_depth`, which prints the nesting level: one less
+    /// than the segment count, so top-level keys are level 0.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    /// Write one `key=value` line (or its projection), or render it through the template if set
+    fn write_entry(&mut self, key: &str, type_name: &str, value: EntryValue) {
+        if self.options.show_depth {
+            // (writing to a String cannot fail)
+            let _ = write!(self.buffer, "{} | ", self.depth.saturating_sub(1));
+        }
+        if let Some(ref template) = self.options.template {
+            for segment in &template.segments {
+                match segment {
+                    Segment::Literal(text) => self.buffer.push_str(text),
+                    Segment::Key => escape_key_into(&mut self.buffer, &self.options, key),
+                    Segment::Value => value.write_to(&mut self.buffer, &self.options),
+                    Segment::Type => self.buffer.push_str(type_name),
+                }
+            }
+        } else {
+            match self.options.projection {
+                Projection::Entries => {
+                    self.write_key(key, type_name);
+                    self.buffer.push_str(&self.options.assign);
+                    value.write_to(&mut self.buffer, &self.options);
+                }
+                Projection::KeysOnly => self.write_key(key, type_name),
+                Projection::ValuesOnly => value.write_to(&mut self.buffer, &self.options),
+            }
+        }
+        if let Some(count) = self.options.annotate_bytes {
+            // (writing to a String cannot fail)
+            let _ = write!(self.buffer, " ({}B)", value.byte_len(count, &self.options));
+        }
+        self.end_line(key);
+    }
+
+    /// Write the key, followed by the type tag when annotating types
+    ///
+    /// Keys are escaped like string values so a newline or control character
+    /// in an object field name cannot break the line grammar.
+    fn write_key(&mut self, key: &str, type_name: &str) {
+        match self.options.pretty {
+            Some(indent) => self.write_nested_key(key, indent),
+            None => escape_key_into(&mut self.buffer, &self.options, key),
+        }
+        if self.options.annotate_types {
+            self.buffer.push(':');
+            self.buffer.push_str(type_name);
+        }
+    }
+
+    /// Write a key in pretty mode: open any new `section:` headers, then the
+    /// last segment indented to its depth
+    fn write_nested_key(&mut self, key: &str, indent: Indent) {
+        let segments: Vec<&str> = key.split(self.options.separator).collect();
+        let (leaf, parents) = segments.split_last().expect("split yields a segment");
+
+        let common = self
+            .open_sections
+            .iter()
+            .zip(parents)
+            .take_while(|(open, segment)| open == *segment)
+            .count();
+        self.open_sections.truncate(common);
+
+        for (depth, segment) in parents.iter().enumerate().skip(common) {
+            self.write_indent(indent, depth);
+            escape_key_into(&mut self.buffer, &self.options, segment);
+            self.buffer.push_str(":\n");
+            self.open_sections.push(segment.to_string());
+        }
+
+        self.write_indent(indent, parents.len());
+        escape_key_into(&mut self.buffer, &self.options, leaf);
+    }
+
+    fn write_indent(&mut self, indent: Indent, depth: usize) {
+        match indent {
+            Indent::Spaces(width) => self.buffer.extend(core::iter::repeat_n(' ', width * depth)),
+            Indent::Tab => self.buffer.extend(core::iter::repeat_n('\t', depth)),
+        }
+    }
+
+    /// Bytes written so far
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
     }
 
     pub fn finish(self) -> String {
@@ -50,20 +775,171 @@ impl Default for ToonWriter {
     }
 }
 
+/// Check whether a string can be written without quotes unambiguously
+///
+/// Safe strings are non-empty, contain no whitespace, separator, quote,
+/// backslash, `=` or assignment operator, and do not read as
+/// `true`/`false`/`null` or a number.
+fn is_safe_unquoted(s: &str, separator: char, assign: &str) -> bool {
+    if s.is_empty() || matches!(s, "true" | "false" | "null") || s.parse::<f64>().is_ok() {
+        return false;
+    }
+    !s.contains(assign)
+        && !s.chars().any(|ch| {
+            ch == separator || ch.is_whitespace() || ch.is_control() || matches!(ch, QUOTE | '\\' | '=')
+        })
+}
+
+/// Validate an assignment operator for use with the given key separator
+///
+/// It must be non-empty and free of the separator, quotes, backslashes and
+/// control characters, so keys, values and escapes stay distinguishable.
+pub fn check_assign(assign: &str, separator: char) -> Result<(), String> {
+    if assign.is_empty() {
+        return Err("assignment operator must not be empty".to_string());
+    }
+    if assign.contains(separator) {
+        return Err(format!(
+            "assignment operator {:?} contains the key separator {:?}",
+            assign, separator
+        ));
+    }
+    if assign.chars().any(|ch| ch == QUOTE || ch == '\\' || ch.is_control()) {
+        return Err(format!(
+            "assignment operator {:?} cannot contain quotes, backslashes or control characters",
+            assign
+        ));
+    }
+    Ok(())
+}
+
+/// Validate a marker written for empty arrays or objects
+///
+/// It must be non-empty, free of whitespace, quotes, backslashes and control
+/// characters, and must not read as `true`/`false`/`null` or a number, so it
+/// cannot be confused with any scalar value.
+pub fn check_empty_token(token: &str) -> Result<(), String> {
+    if token.is_empty() {
+        return Err("empty-collection token must not be empty".to_string());
+    }
+    if token.chars().any(|ch| ch.is_whitespace() || ch.is_control() || ch == QUOTE || ch == '\\') {
+        return Err(format!(
+            "empty-collection token {:?} cannot contain whitespace, quotes, backslashes or control characters",
+            token
+        ));
+    }
+    if matches!(token, "true" | "false" | "null") || token.parse::<f64>().is_ok() {
+        return Err(format!("empty-collection token {:?} reads as a scalar value", token));
+    }
+    Ok(())
+}
+
+/// Whether an object key can be used as a column name in a table header
+///
+/// Names must be non-empty and free of the characters that delimit the
+/// header (`,`, `[`, `]`, `{`, `}`) and of anything that needs escaping.
+pub fn is_table_column(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|ch| {
+            ch.is_control() || matches!(ch, ',' | '[' | ']' | '{' | '}' | QUOTE | '\\')
+        })
+}
+
+/// Append a key, or a segment of one, escaped like a string value except
+/// for `quote_numeric_keys` segments such as `"0"`, which are kept as they are
+fn escape_key_into(result: &mut String, options: &ToonOptions, key: &str) {
+    if !options.quote_numeric_keys || !key.contains(QUOTE) {
+        escape_into(result, key, None);
+        return;
+    }
+    for (i, segment) in key.split(options.separator).enumerate() {
+        if i > 0 {
+            result.push(options.separator);
+        }
+        if is_quoted_index(segment) {
+            result.push_str(segment);
+        } else {
+            escape_into(result, segment, None);
+        }
+    }
+}
+
+/// Whether a key segment is a quoted all-digit object key, e.g. `"0"`,
+/// written for `quote_numeric_keys` to tell it apart from the array index `0`
+///
+/// An object key spelled with the quotes in the source looks the same.
+pub fn is_quoted_index(segment: &str) -> bool {
+    segment
+        .strip_prefix(QUOTE)
+        .and_then(|rest| rest.strip_suffix(QUOTE))
+        .is_some_and(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
 /// Escape special characters in strings for TOON format
-fn escape_string(s: &str) -> String {
+pub fn escape_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    escape_into(&mut result, s, None);
+    result
+}
+
+/// Escape a string value like [`escape_string`], also writing `separator` as `\<separator>`
+///
+/// With `/` as the key separator, `a/b` becomes `a\/b`, so a value can never
+/// look like it holds a key path.
+pub fn escape_string_with_separator(s: &str, separator: char) -> String {
     let mut result = String::with_capacity(s.len());
+    escape_into(&mut result, s, Some(separator));
+    result
+}
+
+/// Length of `s` once escaped by [`escape_into`], without building the escaped string
+pub(crate) fn escaped_len(s: &str, separator: Option<char>) -> usize {
+    s.chars()
+        .map(|ch| match ch {
+            _ if Some(ch) == separator => 1 + ch.len_utf8(),
+            '"' | '\\' | '\n' | '\r' | '\t' => 2,
+            c if is_unicode_escaped(c) => 6,
+            c => c.len_utf8(),
+        })
+        .sum()
+}
+
+/// Whether a character is written as a `\uXXXX` escape
+///
+/// Besides control characters this covers U+2028 and U+2029, which are valid
+/// in JSON strings but end the line for some line-oriented readers.
+fn is_unicode_escaped(ch: char) -> bool {
+    ch.is_control() || matches!(ch, '\u{2028}' | '\u{2029}')
+}
+
+/// Append `s` to `result` with TOON escapes applied, and a backslash before any `separator`
+fn escape_into(result: &mut String, s: &str, separator: Option<char>) {
+    if !s
+        .chars()
+        .any(|ch| ch == '"' || ch == '\\' || is_unicode_escaped(ch) || Some(ch) == separator)
+    {
+        result.push_str(s);
+        return;
+    }
+
     for ch in s.chars() {
         match ch {
+            _ if Some(ch) == separator => {
+                result.push('\\');
+                result.push(ch);
+            }
             '"' => result.push_str("\\\""),
             '\\' => result.push_str("\\\\"),
             '\n' => result.push_str("\\n"),
             '\r' => result.push_str("\\r"),
             '\t' => result.push_str("\\t"),
+            // (writing to a String cannot fail)
+            c if is_unicode_escaped(c) => {
+                let _ = write!(result, "\\u{:04x}", c as u32);
+            }
             _ => result.push(ch),
         }
     }
-    result
 }
 
 #[cfg(test)]
@@ -98,6 +974,157 @@ mod tests {
         assert_eq!(writer.finish(), "score=98.5\n");
     }
 
+    #[test]
+    fn test_write_float_shortest_round_trip() {
+        let cases = [
+            (0.1 + 0.2, "0.30000000000000004"),
+            (1e300, "1e300"),
+            (1e16, "1e16"),
+            (-2.5e-8, "-2.5e-8"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+        ];
+        for (value, expected) in cases {
+            let mut writer = ToonWriter::new();
+            writer.write_number("x", value);
+            let line = writer.finish();
+            assert_eq!(line, format!("x={}\n", expected));
+            let text = &line[2..line.len() - 1];
+            assert_eq!(text.parse::<f64>().unwrap(), value, "{} round-trips", text);
+        }
+    }
+
+    /// Run by `test_numbers_ignore_locale` in a child process with a German locale
+    #[test]
+    #[ignore]
+    fn numbers_in_locale() {
+        let mut writer = ToonWriter::new();
+        writer.write_number("f", 1234567.5);
+        writer.write_number("g", 1e-7);
+        writer.write_number("w", 1234567.0);
+        writer.write_integer("i", -1234567);
+        writer.write_scalar_array("xs", &[Cell::Number(0.5), Cell::Integer(1000)]);
+        let toon = writer.finish();
+        assert_eq!(toon, "f=1234567.5\ng=1e-7\nw=1234567\ni=-1234567\nxs=[0.5,1000]\n");
+    }
+
+    // The locale is set on a child test process, since changing this
+    // process's environment would race the tests that spawn commands
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_numbers_ignore_locale() {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "toon::tests::numbers_in_locale", "--ignored"])
+            .env("LC_ALL", "de_DE.UTF-8")
+            .env("LC_NUMERIC", "de_DE.UTF-8")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("1 passed"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_float_styles() {
+        let written = |float_style| {
+            let mut writer = ToonWriter::with_options(ToonOptions {
+                float_style,
+                ..Default::default()
+            });
+            writer.set_number_literals(BTreeMap::from([
+                ("a".to_string(), "5.0".to_string()),
+                ("b".to_string(), "98.50".to_string()),
+                ("c".to_string(), "1.25".to_string()),
+            ]));
+            writer.write_number("a", 5.0);
+            writer.write_number("b", 98.5);
+            // Changed since parsing, so the literal no longer applies
+            writer.write_number("c", 2.5);
+            writer.write_scalar_array("xs", &[Cell::Number(3.0)]);
+            writer.finish()
+        };
+        assert_eq!(written(FloatStyle::IntegerCollapse), "a=5\nb=98.5\nc=2.5\nxs=[3]\n");
+        assert_eq!(written(FloatStyle::Trim), "a=5.0\nb=98.5\nc=2.5\nxs=[3.0]\n");
+        assert_eq!(written(FloatStyle::Preserve), "a=5.0\nb=98.50\nc=2.5\nxs=[3.0]\n");
+    }
+
+    #[test]
+    fn test_whole_number_limit() {
+        let cases = [
+            (9007199254740991.0, "9007199254740991"),
+            (-9007199254740991.0, "-9007199254740991"),
+            (9007199254740992.0, "9007199254740992.0"),
+            (1e15, "1000000000000000"),
+        ];
+        for (value, expected) in cases {
+            let mut writer = ToonWriter::new();
+            writer.write_number("x", value);
+            assert_eq!(writer.finish(), format!("x={}\n", expected));
+        }
+
+        let options = ToonOptions {
+            whole_number_limit: 1000,
+            ..ToonOptions::default()
+        };
+        let mut writer = ToonWriter::with_options(options);
+        writer.write_number("a", 999.0);
+        writer.write_number("b", 1000.0);
+        writer.write_scalar_array("c", &[Cell::Number(-999.0), Cell::Number(-1000.0)]);
+        assert_eq!(writer.finish(), "a=999\nb=1000.0\nc=[-999,-1000.0]\n");
+
+        assert!(is_whole_number(1.8e19, u64::MAX));
+        assert!(!is_whole_number(1.9e19, u64::MAX));
+        assert!(!is_whole_number(0.5, u64::MAX));
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert!(matches!(normalize_whitespace("a b"), Cow::Borrowed("a b")));
+        assert!(matches!(normalize_whitespace(""), Cow::Borrowed("")));
+        assert_eq!(normalize_whitespace("  a \t\n b\u{a0}c  "), "a b c");
+        assert_eq!(normalize_whitespace("a  b"), "a b");
+        assert_eq!(normalize_whitespace(" \n "), "");
+
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            normalize_whitespace: true,
+            ..ToonOptions::default()
+        });
+        writer.write_references(vec!["  dup  ".to_string()]);
+        writer.write_string("a", "  dup  ");
+        writer.write_string("b", "line one\n\tline two ");
+        assert_eq!(writer.finish(), "&v1=\"dup\"\n\na=&v1\nb=\"line one line two\"\n");
+    }
+
+    #[test]
+    fn test_annotate_bytes() {
+        let options = ToonOptions {
+            annotate_bytes: Some(ByteCount::Raw),
+            truncate_strings: Some(3),
+            ..ToonOptions::default()
+        };
+        let mut writer = ToonWriter::with_options(options.clone());
+        writer.write_string("s", "a\"\u{e9}");
+        writer.write_string("long", "abcdef");
+        writer.write_number("f", 1.5);
+        writer.write_integer("i", -42);
+        writer.write_empty_array("xs");
+        assert_eq!(
+            writer.finish(),
+            "s=\"a\\\"\u{e9}\" (4B)\nlong=\"abc\u{2026}(3 more)\" (6B)\nf=1.5 (3B)\ni=-42 (3B)\nxs=[] (2B)\n"
+        );
+
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            annotate_bytes: Some(ByteCount::Escaped),
+            ..options
+        });
+        writer.write_string("s", "a\"\u{e9}");
+        writer.write_string("long", "abcdef");
+        assert_eq!(
+            writer.finish(),
+            "s=\"a\\\"\u{e9}\" (7B)\nlong=\"abc\u{2026}(3 more)\" (16B)\n"
+        );
+    }
+
     #[test]
     fn test_write_bool() {
         let mut writer = ToonWriter::new();
@@ -119,6 +1146,57 @@ mod tests {
         assert_eq!(escape_string("with\\backslash"), "with\\\\backslash");
         assert_eq!(escape_string("line\nbreak"), "line\\nbreak");
         assert_eq!(escape_string("tab\there"), "tab\\there");
+        assert_eq!(escape_string("bell\u{7}"), "bell\\u0007");
+        assert_eq!(escape_string("a\u{2028}b\u{2029}c"), "a\\u2028b\\u2029c");
+    }
+
+    #[test]
+    fn test_write_key_with_control_characters() {
+        let mut writer = ToonWriter::new();
+        writer.write_number("a\nb", 1.0);
+        writer.write_bool("tab\tkey\u{1}", true);
+        assert_eq!(writer.finish(), "a\\nb=1\ntab\\tkey\\u0001=true\n");
+    }
+
+    #[test]
+    fn test_unquoted_safe_strings() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            unquoted_safe_strings: true,
+            ..Default::default()
+        });
+        writer.write_string("name", "Alice");
+        writer.write_string("greeting", "hello world");
+        writer.write_string("flag", "true");
+        writer.write_string("zip", "12345");
+        writer.write_string("version", "1.0");
+        writer.write_string("empty", "");
+        writer.write_empty_array("items");
+
+        assert_eq!(
+            writer.finish(),
+            "name=Alice\n\
+             greeting=\"hello world\"\n\
+             flag=\"true\"\n\
+             zip=\"12345\"\n\
+             version=\"1.0\"\n\
+             empty=\"\"\n\
+             items=[]\n"
+        );
+    }
+
+    #[test]
+    fn test_is_safe_unquoted() {
+        assert!(is_safe_unquoted("simple", SEPARATOR, ASSIGN));
+        assert!(is_safe_unquoted("user@example", SEPARATOR, ASSIGN));
+        assert!(!is_safe_unquoted("a=b", SEPARATOR, ASSIGN));
+        assert!(!is_safe_unquoted("a.b", SEPARATOR, ASSIGN));
+        assert!(!is_safe_unquoted("say \"hi\"", SEPARATOR, ASSIGN));
+        assert!(!is_safe_unquoted("tab\there", SEPARATOR, ASSIGN));
+        assert!(!is_safe_unquoted("null", SEPARATOR, ASSIGN));
+        assert!(!is_safe_unquoted("-1e5", SEPARATOR, ASSIGN));
+        assert!(!is_safe_unquoted("NaN", SEPARATOR, ASSIGN));
+        assert!(is_safe_unquoted("a.b", '/', ASSIGN));
+        assert!(!is_safe_unquoted("a/b", '/', ASSIGN));
     }
 
     #[test]
@@ -135,4 +1213,392 @@ mod tests {
         assert!(result.contains("active=false\n"));
         assert!(result.contains("middle=null\n"));
     }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut writer = ToonWriter::with_capacity(ToonOptions::default(), 64);
+        writer.write_string("name", "Alice");
+        let result = writer.finish();
+        assert_eq!(result, "name=\"Alice\"\n");
+        assert!(result.capacity() >= 64);
+    }
+
+    #[test]
+    fn test_write_blank_line() {
+        let mut writer = ToonWriter::new();
+        writer.write_bool("a", true);
+        writer.write_blank_line();
+        writer.write_bool("b", false);
+        assert_eq!(writer.finish(), "a=true\n\nb=false\n");
+    }
+
+    #[test]
+    fn test_pretty_spaces() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            pretty: Some(Indent::Spaces(2)),
+            ..Default::default()
+        });
+        writer.write_string("user.name", "Bob");
+        writer.write_number("user.tags.0", 1.0);
+        writer.write_number("user.tags.1", 2.0);
+        writer.write_bool("user.active", true);
+        writer.write_null("other");
+
+        assert_eq!(
+            writer.finish(),
+            "user:\n  name=\"Bob\"\n  tags:\n    0=1\n    1=2\n  active=true\nother=null\n"
+        );
+    }
+
+    #[test]
+    fn test_pretty_tabs() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            pretty: Some(Indent::Tab),
+            ..Default::default()
+        });
+        writer.write_number("a.b.c", 1.0);
+        writer.write_number("a.d", 2.0);
+
+        assert_eq!(writer.finish(), "a:\n\tb:\n\t\tc=1\n\td=2\n");
+    }
+
+    #[test]
+    fn test_empty_collection_markers() {
+        let mut writer = ToonWriter::new();
+        writer.write_empty_array("items");
+        writer.write_empty_object("data");
+        assert_eq!(writer.finish(), "items=[]\ndata={}\n");
+    }
+
+    #[test]
+    fn test_annotate_types() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            annotate_types: true,
+            ..Default::default()
+        });
+        writer.write_string("name", "Bob");
+        writer.write_integer("age", 30);
+        writer.write_number("score", 98.5);
+        writer.write_bool("active", true);
+        writer.write_null("middle");
+        writer.write_empty_array("tags");
+        writer.write_empty_object("meta");
+
+        assert_eq!(
+            writer.finish(),
+            "name:string=\"Bob\"\n\
+             age:integer=30\n\
+             score:float=98.5\n\
+             active:bool=true\n\
+             middle:null=null\n\
+             tags:array=[]\n\
+             meta:object={}\n"
+        );
+    }
+
+    #[test]
+    fn test_template() {
+        let template = Template::parse("{key}\\t{type}\\t{value}").unwrap();
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            template: Some(template),
+            ..Default::default()
+        });
+        writer.write_string("user.name", "Bob");
+        writer.write_integer("user.age", 30);
+        writer.write_null("middle");
+
+        assert_eq!(
+            writer.finish(),
+            "user.name\tstring\t\"Bob\"\nuser.age\tinteger\t30\nmiddle\tnull\tnull\n"
+        );
+    }
+
+    #[test]
+    fn test_template_literal_braces() {
+        let template = Template::parse("{{{key}}}: {value}").unwrap();
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            template: Some(template),
+            ..Default::default()
+        });
+        writer.write_bool("a", true);
+        assert_eq!(writer.finish(), "{a}: true\n");
+    }
+
+    #[test]
+    fn test_template_validation() {
+        assert!(Template::parse("{key}={value}").is_ok());
+        assert!(Template::parse("{key}").unwrap_err().contains("{value}"));
+        assert!(Template::parse("{value}").unwrap_err().contains("{key}"));
+        assert!(Template::parse("{key} {nope} {value}").is_err());
+        assert!(Template::parse("{key} {value").is_err());
+    }
+
+    #[test]
+    fn test_projections() {
+        let write = |projection| {
+            let mut writer = ToonWriter::with_options(ToonOptions {
+                projection,
+                ..Default::default()
+            });
+            writer.write_string("user.name", "Bob");
+            writer.write_number("user.age", 30.0);
+            writer.finish()
+        };
+
+        assert_eq!(write(Projection::KeysOnly), "user.name\nuser.age\n");
+        assert_eq!(write(Projection::ValuesOnly), "\"Bob\"\n30\n");
+    }
+
+    #[test]
+    fn test_show_depth() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            show_depth: true,
+            ..Default::default()
+        });
+        writer.set_depth(3);
+        writer.write_string("config.db.host", "x");
+        writer.set_depth(1);
+        writer.write_bool("debug", true);
+        assert_eq!(writer.finish(), "2 | config.db.host=\"x\"\n0 | debug=true\n");
+    }
+
+    #[test]
+    fn test_write_table() {
+        let mut writer = ToonWriter::new();
+        writer.write_table_header("rows", &["id", "name"], 2);
+        writer.write_table_row(&[Cell::Integer(1), Cell::String("Alice")]);
+        writer.write_table_row(&[Cell::Number(2.5), Cell::Null]);
+
+        assert_eq!(writer.finish(), "rows[2]{id,name}:\n  1,\"Alice\"\n  2.5,null\n");
+    }
+
+    #[test]
+    fn test_table_row_quotes_commas() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            unquoted_safe_strings: true,
+            ..Default::default()
+        });
+        writer.write_table_row(&[Cell::String("plain"), Cell::String("a,b"), Cell::Bool(true)]);
+        assert_eq!(writer.finish(), "  plain,\"a,b\",true\n");
+    }
+
+    #[test]
+    fn test_is_table_column() {
+        assert!(is_table_column("name"));
+        assert!(is_table_column("first name"));
+        assert!(!is_table_column(""));
+        assert!(!is_table_column("a,b"));
+        assert!(!is_table_column("x{y}"));
+    }
+
+    #[test]
+    fn test_pretty_custom_separator() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            separator: '/',
+            pretty: Some(Indent::Spaces(2)),
+            ..Default::default()
+        });
+        writer.write_number("a/b.c", 1.0);
+        assert_eq!(writer.finish(), "a:\n  b.c=1\n");
+    }
+
+    #[test]
+    fn test_quote_numbers_and_bools() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            quote_numbers: true,
+            quote_bools: true,
+            ..Default::default()
+        });
+        writer.write_number("age", 30.0);
+        writer.write_number("score", 98.5);
+        writer.write_bool("active", true);
+        writer.write_null("middle");
+
+        assert_eq!(
+            writer.finish(),
+            "age=\"30\"\nscore=\"98.5\"\nactive=\"true\"\nmiddle=null\n"
+        );
+    }
+
+    #[test]
+    fn test_truncate_strings() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            truncate_strings: Some(4),
+            unquoted_safe_strings: true,
+            ..Default::default()
+        });
+        writer.write_string("short", "abcd");
+        writer.write_string("blob", "aGVsbG8gd29ybGQ=");
+        writer.write_string("accents", "h\"éé\"llo");
+        writer.write_table_row(&[Cell::String("a,b,c,d,e")]);
+        assert_eq!(
+            writer.finish(),
+            "short=abcd\n\
+             blob=\"aGVs\u{2026}(12 more)\"\n\
+             accents=\"h\\\"éé\u{2026}(4 more)\"\n  \
+             \"a,b,\u{2026}(5 more)\"\n"
+        );
+    }
+
+    #[test]
+    fn test_assign() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            assign: ": ".to_string(),
+            annotate_types: true,
+            ..Default::default()
+        });
+        writer.write_string("name", "Bob");
+        writer.write_empty_array("tags");
+        assert_eq!(writer.finish(), "name:string: \"Bob\"\ntags:array: []\n");
+
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            assign: ":".to_string(),
+            unquoted_safe_strings: true,
+            ..Default::default()
+        });
+        writer.write_string("url", "http://x");
+        writer.write_string("host", "x");
+        assert_eq!(writer.finish(), "url:\"http://x\"\nhost:x\n");
+    }
+
+    #[test]
+    fn test_check_assign() {
+        assert!(check_assign("=", SEPARATOR).is_ok());
+        assert!(check_assign(": ", SEPARATOR).is_ok());
+        assert!(check_assign("->", SEPARATOR).is_ok());
+        for bad in ["", ".", "=.", "\"", "\\", "\t"] {
+            assert!(check_assign(bad, SEPARATOR).is_err(), "{:?} should be rejected", bad);
+        }
+        assert!(check_assign(":", ':').is_err());
+    }
+
+    #[test]
+    fn test_write_references() {
+        let options = ToonOptions {
+            unquoted_safe_strings: true,
+            ..Default::default()
+        };
+        let mut writer = ToonWriter::with_options(options);
+        writer.write_references(vec!["https://example.com".to_string(), "a \"b\"".to_string()]);
+        writer.write_string("home", "https://example.com");
+        writer.write_string("title", "a \"b\"");
+        writer.write_string("other", "plain");
+        writer.write_string("amp", "&v1");
+        assert_eq!(
+            writer.finish(),
+            "&v1=\"https://example.com\"\n&v2=\"a \\\"b\\\"\"\n\n\
+             home=&v1\ntitle=&v2\nother=plain\namp=\"&v1\"\n"
+        );
+
+        let mut writer = ToonWriter::new();
+        writer.write_references(Vec::new());
+        writer.write_string("amp", "&v1");
+        assert_eq!(writer.finish(), "amp=\"&v1\"\n");
+    }
+
+    #[test]
+    fn test_escaped_len() {
+        for s in ["simple", "with \"quotes\"", "back\\slash", "line\nbreak\r\t", "bell\u{7}", "ünï/cödé", "line\u{2028}sep\u{2029}", ""] {
+            assert_eq!(escaped_len(s, None), escape_string(s).len(), "{:?}", s);
+            assert_eq!(escaped_len(s, Some('/')), escape_string_with_separator(s, '/').len(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn test_quote_numeric_keys() {
+        assert!(is_quoted_index("\"0\"") && is_quoted_index("\"042\""));
+        assert!(!is_quoted_index("0") && !is_quoted_index("\"\"") && !is_quoted_index("\"a1\""));
+
+        let options = ToonOptions {
+            quote_numeric_keys: true,
+            pretty: Some(Indent::Spaces(2)),
+            ..Default::default()
+        };
+        let mut writer = ToonWriter::with_options(options);
+        writer.write_integer("ids.\"0\".n", 1);
+        writer.write_integer("ids.\"x\"", 2);
+        assert_eq!(writer.finish(), "ids:\n  \"0\":\n    n=1\n  \\\"x\\\"=2\n");
+    }
+
+    #[test]
+    fn test_escape_separator() {
+        assert_eq!(escape_string("a/b"), "a/b");
+        assert_eq!(escape_string_with_separator("a/b", '/'), "a\\/b");
+        assert_eq!(escape_string_with_separator("\"x\".y", '.'), "\\\"x\\\"\\.y");
+
+        let options = ToonOptions {
+            separator: '/',
+            escape_separator: true,
+            unquoted_safe_strings: true,
+            ..Default::default()
+        };
+        let mut writer = ToonWriter::with_options(options);
+        writer.write_string("path/to", "a/b");
+        writer.write_string("plain", "ab");
+        assert_eq!(writer.finish(), "path/to=\"a\\/b\"\nplain=ab\n");
+
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            separator: '/',
+            ..Default::default()
+        });
+        writer.write_string("path/to", "a/b");
+        assert_eq!(writer.finish(), "path/to=\"a/b\"\n");
+    }
+
+    #[test]
+    fn test_check_empty_token() {
+        for good in [EMPTY_ARRAY, EMPTY_OBJECT, "-", "<empty>"] {
+            assert!(check_empty_token(good).is_ok(), "{:?} should be accepted", good);
+        }
+        for bad in ["", "\"[]\"", "a b", "\\", "null", "0", "-1e5"] {
+            assert!(check_empty_token(bad).is_err(), "{:?} should be rejected", bad);
+        }
+    }
+
+    #[test]
+    fn test_unquoted_strings_matching_empty_tokens() {
+        let options = ToonOptions {
+            unquoted_safe_strings: true,
+            ..Default::default()
+        };
+        let mut writer = ToonWriter::with_options(options);
+        writer.write_string("a", "[]");
+        writer.write_string("b", "{}");
+        writer.write_empty_array("c");
+        assert_eq!(writer.finish(), "a=\"[]\"\nb=\"{}\"\nc=[]\n");
+    }
+
+    #[test]
+    fn test_write_integer_is_exact() {
+        let mut writer = ToonWriter::new();
+        writer.write_integer("max", u64::MAX as i128);
+        writer.write_integer("min", i64::MIN as i128);
+        assert_eq!(
+            writer.finish(),
+            "max=18446744073709551615\nmin=-9223372036854775808\n"
+        );
+    }
+
+    #[test]
+    fn test_write_scalar_array() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            unquoted_safe_strings: true,
+            ..Default::default()
+        });
+        writer.write_scalar_array("ids", &[Cell::Integer(1), Cell::Number(2.5), Cell::Null]);
+        writer.write_scalar_array("tags", &[Cell::String("a"), Cell::String("b,c"), Cell::String("[d]")]);
+        assert_eq!(writer.finish(), "ids=[1,2.5,null]\ntags=[a,\"b,c\",\"[d]\"]\n");
+    }
+
+    #[test]
+    fn test_write_scalar_array_quoting() {
+        let mut writer = ToonWriter::with_options(ToonOptions {
+            quote_numbers: true,
+            quote_bools: true,
+            annotate_types: true,
+            ..Default::default()
+        });
+        writer.write_scalar_array("xs", &[Cell::Integer(1), Cell::Bool(true), Cell::String("x")]);
+        assert_eq!(writer.finish(), "xs:array=[\"1\",\"true\",\"x\"]\n");
+    }
 }
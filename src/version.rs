@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 pub fn print_version() {
     println!("json2toon {}", env!("CARGO_PKG_VERSION"));
     println!("Copyright (c) 2025 Michael A Wright");
@@ -11,3 +13,73 @@ pub fn print_version() {
     println!("  Commit: {}", env!("GIT_COMMIT_SHA"));
     println!("  Date: {}", env!("BUILD_TIMESTAMP"));
 }
+
+/// Provenance comment for `--header`, ending in a newline
+///
+/// e.g. `# generated from input.json by json2toon 0.1.0 (commit abc1234) at 2026-01-02T03:04:05Z`
+pub fn header(source: &str) -> String {
+    header_at(source, SystemTime::now())
+}
+
+fn header_at(source: &str, time: SystemTime) -> String {
+    format!(
+        "# generated from {} by json2toon {} (commit {}) at {}\n",
+        source,
+        env!("CARGO_PKG_VERSION"),
+        env!("GIT_COMMIT_SHA"),
+        utc_timestamp(time)
+    )
+}
+
+/// RFC 3339 UTC timestamp to the second, e.g. `2026-01-02T03:04:05Z`
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Proleptic Gregorian date of a day count since 1970-01-01 (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_utc_timestamp() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_723);
+        assert_eq!(utc_timestamp(leap_day), "2000-02-29T01:02:03Z");
+        let later = UNIX_EPOCH + Duration::from_secs(1_791_936_000);
+        assert_eq!(utc_timestamp(later), "2026-10-14T00:00:00Z");
+    }
+
+    #[test]
+    fn test_header() {
+        let header = header_at("input.json", UNIX_EPOCH);
+        assert!(header.starts_with("# generated from input.json by json2toon "), "{}", header);
+        assert!(header.ends_with(" at 1970-01-01T00:00:00Z\n"), "{}", header);
+        assert_eq!(header.lines().count(), 1);
+    }
+}